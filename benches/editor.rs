@@ -0,0 +1,161 @@
+//! Timings for the editing and rendering hot paths, driven through a headless editor.
+//!
+//! Run with `cargo bench`; pass a name (e.g. `cargo bench -- render`) to run only the
+//! benchmarks containing it.
+
+extern crate kilo_rs;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use kilo_rs::Kilo;
+
+const ROWS: usize = 50;
+const COLS: usize = 160;
+
+/// Batches each benchmark's iterations are timed in, to see how much the timings vary.
+const SAMPLES: u32 = 20;
+
+const PAGE_DOWN: &[u8] = b"\x1b[6~";
+
+const SOURCE: &str = "/// Returns the sum of `values`, skipping negative ones.
+fn sum(values: &[i64]) -> i64 {
+    let mut total = 0; // running total
+    for value in values.iter().filter(|v| **v >= 0) {
+        total += value * 2 + 0x10;
+    }
+    println!(\"total: {}\", total);
+    total
+}
+";
+
+/// Writes `contents` to a scratch file that is removed when dropped.
+struct Fixture(PathBuf);
+
+impl Fixture {
+    fn new(name: &str, contents: &str) -> Fixture {
+        let path = env::temp_dir().join(format!("kilo-bench-{}-{}", std::process::id(), name));
+        fs::write(&path, contents).expect("unable to write fixture");
+        Fixture(path)
+    }
+
+    fn path(&self) -> &str {
+        self.0.to_str().expect("non-UTF-8 temp dir")
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// A scratch directory used as the config and state directories while the benchmarks run,
+/// so they neither read the user's kilorc nor add the fixtures to their recent files.
+struct Home(PathBuf);
+
+impl Home {
+    fn new() -> Home {
+        let path = env::temp_dir().join(format!("kilo-bench-{}-home", std::process::id()));
+        fs::create_dir_all(&path).expect("unable to create scratch home");
+        env::set_var("XDG_CONFIG_HOME", path.join("config"));
+        env::set_var("XDG_STATE_HOME", path.join("state"));
+        Home(path)
+    }
+}
+
+impl Drop for Home {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn editor(fixture: &Fixture) -> Kilo {
+    let mut kilo = Kilo::headless(ROWS, COLS);
+    kilo.open(fixture.path()).expect("unable to open fixture");
+    kilo
+}
+
+/// Times `iterations` calls of `routine` after running `setup`, in `SAMPLES` batches, and
+/// reports the median time per call with the spread of the batches around it: the middle
+/// half of them, and the fastest and slowest.
+fn bench<S, T, F>(name: &str, iterations: u32, mut setup: S, mut routine: F)
+where
+    S: FnMut() -> T,
+    F: FnMut(&mut T),
+{
+    let filters: Vec<String> = env::args().skip(1).filter(|a| !a.starts_with("--")).collect();
+    if !filters.is_empty() && !filters.iter().any(|f| name.contains(f.as_str())) {
+        return;
+    }
+
+    let samples = SAMPLES.min(iterations);
+    let batch = iterations / samples;
+    let mut state = setup();
+    let mut times = Vec::new();
+
+    for _ in 0..samples {
+        let start = Instant::now();
+        for _ in 0..batch {
+            routine(&mut state);
+        }
+        times.push(start.elapsed() / batch);
+    }
+    times.sort();
+
+    let quartile = |q: usize| times[(times.len() - 1) * q / 4];
+    println!(
+        "{:<24} {:>12.3?}/iter (middle half {:.3?}..{:.3?}, range {:.3?}..{:.3?}, {} x {})",
+        name,
+        quartile(2),
+        quartile(1),
+        quartile(3),
+        times[0],
+        times[times.len() - 1],
+        samples,
+        batch
+    );
+}
+
+fn main() {
+    let _home = Home::new();
+
+    let large = Fixture::new("large.txt", &"lorem ipsum dolor sit amet\n".repeat(1_000_000));
+    bench("open_1m_lines", 5, || Kilo::headless(ROWS, COLS), |kilo| {
+        kilo.open(large.path()).expect("unable to open fixture");
+    });
+
+    bench("keystroke_1m_lines", 1_000, || {
+        let mut kilo = editor(&large);
+        kilo.set_cursor(500_000, 10);
+        kilo
+    }, |kilo| {
+        kilo.feed(b"a").expect("unable to insert");
+        kilo.render();
+    });
+
+    let long = Fixture::new("long.txt", &format!("{}\n", "x".repeat(10_000)));
+    bench("insert_mid_long_line", 10_000, || {
+        let mut kilo = editor(&long);
+        kilo.set_cursor(0, 5_000);
+        kilo
+    }, |kilo| {
+        kilo.feed(b"a").expect("unable to insert");
+    });
+
+    let source = Fixture::new("source.rs", &SOURCE.repeat(10_000));
+    bench("page_down_render", 1_000, || editor(&source), |kilo| {
+        kilo.feed(PAGE_DOWN).expect("unable to scroll");
+        kilo.render();
+    });
+
+    bench("render_highlighted", 1_000, || {
+        let mut kilo = editor(&source);
+        kilo.set_cursor(ROWS * 10, 0);
+        kilo
+    }, |kilo| {
+        kilo.render();
+    });
+}
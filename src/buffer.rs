@@ -0,0 +1,740 @@
+//! The text model: rows of text and their rendered form.
+
+use super::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Position {
+    pub(crate) cx: usize,
+    pub(crate) cy: usize,
+}
+
+/// Bytes of a file that aren't valid UTF-8 are stored in row text as the characters from
+/// here on, one per byte, and written back as the original bytes when saving.
+const RAW_BYTE_BASE: u32 = 0x10_fd00;
+
+/// The file byte that `c` stands for, if it is a placeholder for one that isn't UTF-8.
+pub(crate) fn raw_byte(c: char) -> Option<u8> {
+    match (c as u32).checked_sub(RAW_BYTE_BASE) {
+        Some(byte @ 0x80..=0xff) => Some(byte as u8),
+        _ => None,
+    }
+}
+
+/// The bytes of a file as text. Invalid UTF-8 becomes placeholder characters, as do
+/// the bytes of any genuine characters in the placeholder range, so that `encode_text`
+/// gives back exactly the same bytes.
+pub(crate) fn decode_bytes(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len());
+    let raw = |text: &mut String, byte: u8| {
+        text.extend(char::from_u32(RAW_BYTE_BASE + u32::from(byte)));
+    };
+
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match raw_byte(c) {
+                Some(_) => c.encode_utf8(&mut [0; 4]).bytes().for_each(|b| raw(&mut text, b)),
+                None => text.push(c),
+            }
+        }
+        for &byte in chunk.invalid() {
+            raw(&mut text, byte);
+        }
+    }
+
+    text
+}
+
+/// `text` as file bytes, turning placeholder characters back into the bytes they stand for.
+pub(crate) fn encode_text(text: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(text.len());
+    for c in text.chars() {
+        match raw_byte(c) {
+            Some(byte) => bytes.push(byte),
+            None => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+    bytes
+}
+
+/// The lines of a file's text without their line endings, whether every line ends with
+/// `\r\n` rather than `\n`, and whether the last line ends with either. Where only some
+/// lines end with `\r\n` the `\r` stays in the line, so the text can be written back as it was.
+pub(crate) fn split_lines(text: &str) -> (Vec<&str>, bool, bool) {
+    let final_newline = text.is_empty() || text.ends_with('\n');
+    let mut lines = match text.strip_suffix('\n') {
+        _ if text.is_empty() => Vec::new(),
+        Some(body) => body.split('\n').collect::<Vec<&str>>(),
+        None => text.split('\n').collect::<Vec<&str>>(),
+    };
+
+    // The lines a newline follows, which is all of them unless the last one lacks it.
+    let ended = lines.len() - usize::from(!final_newline);
+    let crlf = ended > 0 && lines[..ended].iter().all(|line| line.ends_with('\r'));
+    if crlf {
+        for line in &mut lines[..ended] {
+            *line = &line[..(line.len() - 1)];
+        }
+    }
+
+    (lines, crlf, final_newline)
+}
+
+/// What a line is indented with, as counted towards a file's dominant indentation style:
+/// a tab, a space, or nothing for unindented and blank lines.
+pub(crate) fn leading_indent(chars: &str) -> Option<char> {
+    match chars.chars().next() {
+        Some('\t') => Some('\t'),
+        Some(' ') if !chars.trim().is_empty() => Some(' '),
+        _ => None,
+    }
+}
+
+/// The number of bytes `c` takes up in the file.
+pub(crate) fn encoded_char_len(c: char) -> usize {
+    if raw_byte(c).is_some() { 1 } else { c.len_utf8() }
+}
+
+/// The number of bytes `text` takes up in the file.
+pub(crate) fn encoded_len(text: &str) -> usize {
+    text.chars().map(encoded_char_len).sum()
+}
+
+/// The width of the placeholder `c` is displayed as: `^A` for control characters and `<80>`
+/// for bytes that aren't UTF-8. Zero for characters displayed as themselves.
+pub(crate) fn placeholder_width(c: char) -> usize {
+    if caret(c).is_some() {
+        2
+    } else if raw_byte(c).is_some() {
+        4
+    } else {
+        0
+    }
+}
+
+/// The letter a control character is displayed with in caret notation (`^A`, `^?`), or
+/// `None` for other characters. Tabs are expanded to spaces instead.
+pub(crate) fn caret(c: char) -> Option<char> {
+    match c {
+        '\t' => None,
+        '\0'..='\x1f' => Some((c as u8 + b'@') as char),
+        '\x7f' => Some('?'),
+        _ => None,
+    }
+}
+
+/// `chars` as displayed, with tabs expanded to the next multiple of `tabstop`, control
+/// characters shown as `^` and a letter and bytes that aren't UTF-8 as `<` hex digits `>`.
+pub(crate) fn render_text(chars: &str, tabstop: usize) -> String {
+    let mut render = String::new();
+    let mut col = 0;
+
+    for c in chars.chars() {
+        if c == '\t' {
+            let width = tabstop - col % tabstop;
+            render.extend((0..width).map(|_| ' '));
+            col += width;
+        } else if let Some(letter) = caret(c) {
+            render.push('^');
+            render.push(letter);
+            col += 2;
+        } else if let Some(byte) = raw_byte(c) {
+            render.push_str(&format!("<{:02x}>", byte));
+            col += 4;
+        } else {
+            render.push(c);
+            col += 1;
+        }
+    }
+
+    render
+}
+
+/// A line of text, along with how it is displayed.
+pub struct Row {
+    pub(crate) chars: String,
+    pub(crate) render: String,
+    pub(crate) hl: Vec<Highlight>,
+    pub(crate) hl_state: HighlightState,
+    /// Set for rows of an opened file until they are first displayed, since working out
+    /// `render` for every row of a large file up front would only slow opening it down.
+    pub(crate) stale: bool,
+    /// What the row was indented with when it was last counted in the buffer's indentation.
+    pub(crate) indent: Option<char>,
+}
+
+impl Row {
+    pub(crate) fn new(chars: &str) -> Row {
+        Row {
+            chars: chars.to_string(),
+            render: String::new(),
+            hl: Vec::new(),
+            hl_state: HighlightState::default(),
+            stale: false,
+            indent: leading_indent(chars),
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.chars
+    }
+
+    /// The text as displayed, with tabs expanded to spaces, control characters in caret
+    /// notation and bytes that aren't UTF-8 in hex, such as `<ff>`. Rows of a file the
+    /// editor opened are only rendered once they have been displayed.
+    pub fn render(&self) -> &str {
+        &self.render
+    }
+}
+
+/// The file size of each row, counting its line ending, with the sum for each chunk of
+/// `OFFSET_INDEX_CHUNK` rows, so that the offset of any row, and the row at any offset, are
+/// found by adding up chunks and then the rows of one chunk. Editing, adding or removing a
+/// row updates the index in place: the chunks after an added or removed row each pass one
+/// row on to their neighbour.
+pub(crate) struct OffsetIndex {
+    lens: Vec<usize>,
+    sums: Vec<usize>,
+}
+
+impl OffsetIndex {
+    pub(crate) fn new(rows: &[Row], ending: usize) -> OffsetIndex {
+        let lens = rows.iter().map(|row| encoded_len(&row.chars) + ending);
+        let lens = lens.collect::<Vec<usize>>();
+        let sums = lens.chunks(OFFSET_INDEX_CHUNK).map(|chunk| chunk.iter().sum()).collect();
+
+        OffsetIndex { lens, sums }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.lens.len()
+    }
+
+    /// Records that row `at` now takes up `len` bytes, line ending included.
+    pub(crate) fn set(&mut self, at: usize, len: usize) {
+        let chunk = at / OFFSET_INDEX_CHUNK;
+        self.sums[chunk] = self.sums[chunk] - self.lens[at] + len;
+        self.lens[at] = len;
+    }
+
+    /// Records a row of `len` bytes added before row `at`.
+    pub(crate) fn insert(&mut self, at: usize, len: usize) {
+        self.lens.insert(at, len);
+        if self.lens.len() > self.sums.len() * OFFSET_INDEX_CHUNK {
+            self.sums.push(0);
+        }
+
+        let chunk = at / OFFSET_INDEX_CHUNK;
+        self.sums[chunk] += len;
+        // Each following chunk's last row moves on to the chunk after it.
+        for c in chunk..(self.sums.len() - 1) {
+            let moved = self.lens[(c + 1) * OFFSET_INDEX_CHUNK];
+            self.sums[c] -= moved;
+            self.sums[c + 1] += moved;
+        }
+    }
+
+    /// Records that row `at` was removed.
+    pub(crate) fn remove(&mut self, at: usize) {
+        let len = self.lens.remove(at);
+
+        let chunk = at / OFFSET_INDEX_CHUNK;
+        self.sums[chunk] -= len;
+        // Each following chunk's first row moves back to the chunk before it.
+        for c in chunk..(self.sums.len() - 1) {
+            let moved = self.lens[(c + 1) * OFFSET_INDEX_CHUNK - 1];
+            self.sums[c] += moved;
+            self.sums[c + 1] -= moved;
+        }
+
+        if self.lens.len() <= (self.sums.len() - 1) * OFFSET_INDEX_CHUNK {
+            self.sums.pop();
+        }
+    }
+
+    /// The offset at which row `at` starts, which for `len()` is the size of the file.
+    pub(crate) fn offset(&self, at: usize) -> usize {
+        let at = at.min(self.len());
+        let chunk = at / OFFSET_INDEX_CHUNK;
+        let rows = &self.lens[(chunk * OFFSET_INDEX_CHUNK)..at];
+        self.sums[..chunk].iter().sum::<usize>() + rows.iter().sum::<usize>()
+    }
+
+    /// The row that byte `offset` is on, and the offset within it. Offsets from the size of
+    /// the file on are on row `len()`.
+    pub(crate) fn find(&self, offset: usize) -> (usize, usize) {
+        let (mut at, mut rest) = (0, offset);
+        for &sum in &self.sums {
+            if sum > rest {
+                break;
+            }
+            at = cmp::min(at + OFFSET_INDEX_CHUNK, self.len());
+            rest -= sum;
+        }
+        while at < self.len() && self.lens[at] <= rest {
+            rest -= self.lens[at];
+            at += 1;
+        }
+        (at, rest)
+    }
+}
+
+/// The text being edited, as rows. Positions are `(line, column)` pairs counted in
+/// characters from zero; a line of `line_count()` is the empty line after the last one.
+pub struct Buffer {
+    pub(crate) rows: Vec<Row>,
+    pub(crate) tabstop: usize,
+    /// Whether lines end with `\r\n`, as they all did in the file that was read.
+    pub(crate) crlf: bool,
+    /// Whether the last line ends with a line ending, as it did in the file that was read.
+    pub(crate) final_newline: bool,
+    /// Built when first needed, so that opening a file doesn't pay for it.
+    offsets: RefCell<Option<OffsetIndex>>,
+    /// The number of rows indented with tabs and with spaces, kept up to date as rows are
+    /// edited, along with the number of rows counted so that added or removed rows are
+    /// caught.
+    indents: Cell<Option<(usize, usize, usize)>>,
+}
+
+impl Default for Buffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for Buffer {
+    /// The buffer's contents, each line ending with a newline.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in &self.rows {
+            writeln!(f, "{}", row.chars)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Buffer {
+    pub fn new() -> Buffer {
+        Buffer {
+            rows: Vec::new(),
+            tabstop: KILO_TAB_STOP,
+            crlf: false,
+            final_newline: true,
+            offsets: RefCell::new(None),
+            indents: Cell::new(None),
+        }
+    }
+
+    /// A buffer holding the contents of a file, which needn't be valid UTF-8 or free of
+    /// control characters; `to_bytes` gives back the same bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Buffer {
+        Buffer::from_text(&decode_bytes(bytes))
+    }
+
+    /// The buffer's contents as file bytes, with the line endings of the text it was made
+    /// from.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.encode_rows(self.rows.len(), self.final_newline)
+    }
+
+    /// A buffer holding the lines of `text`, which keeps whether they end with `\r\n` and
+    /// whether the last one ends at all.
+    pub fn from_text(text: &str) -> Buffer {
+        let (lines, crlf, final_newline) = split_lines(text);
+        let mut buffer = Buffer { crlf, final_newline, ..Buffer::new() };
+        buffer.rows = lines.into_iter().map(Row::new).collect();
+        for at in 0..buffer.rows.len() {
+            buffer.update_render(at);
+        }
+        buffer
+    }
+
+    /// The line ending written after each line.
+    pub(crate) fn line_ending(&self) -> &'static str {
+        if self.crlf { "\r\n" } else { "\n" }
+    }
+
+    /// The first `end` rows as file bytes with the buffer's line endings, the last one
+    /// ending with a line ending if `final_newline` is set.
+    pub(crate) fn encode_rows(&self, end: usize, final_newline: bool) -> Vec<u8> {
+        let mut text = String::new();
+        for (i, row) in self.rows[..end].iter().enumerate() {
+            text.push_str(&row.chars);
+            if i + 1 < end || final_newline {
+                text.push_str(self.line_ending());
+            }
+        }
+        encode_text(&text)
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn line(&self, at: usize) -> Option<&str> {
+        self.rows.get(at).map(|row| row.chars.as_str())
+    }
+
+    pub fn row(&self, at: usize) -> Option<&Row> {
+        self.rows.get(at)
+    }
+
+    pub fn tabstop(&self) -> usize {
+        self.tabstop
+    }
+
+    pub fn set_tabstop(&mut self, tabstop: usize) {
+        self.tabstop = tabstop.max(1);
+        for at in 0..self.rows.len() {
+            self.update_render(at);
+        }
+    }
+
+    /// Inserts `text`, which may span several lines, at `(line, col)` and returns the
+    /// position just after it.
+    pub fn insert(&mut self, line: usize, col: usize, text: &str) -> (usize, usize) {
+        let line = line.min(self.rows.len());
+        if line == self.rows.len() {
+            self.insert_row(line, "");
+        }
+
+        let at = self.byte_index(line, col);
+        let tail = self.rows[line].chars.split_off(at);
+        let mut parts = text.split('\n');
+        self.rows[line].chars.push_str(parts.next().unwrap_or(""));
+
+        // The new rows go in with one splice, so pasting many lines doesn't shift the rows
+        // after them once per line.
+        let added = parts.map(Row::new).collect::<Vec<Row>>();
+        let cy = line + added.len();
+        if !added.is_empty() {
+            let count = added.len();
+            self.rows.splice((line + 1)..(line + 1), added);
+            self.rows_replaced(line + 1, 0, count);
+        }
+
+        let cx = self.rows[cy].chars.chars().count();
+        self.rows[cy].chars.push_str(&tail);
+        for at in line..=cy {
+            self.update_render(at);
+        }
+
+        (cy, cx)
+    }
+
+    /// Removes the text from `start` up to (not including) `end` and returns it.
+    pub fn delete_range(&mut self, start: (usize, usize), end: (usize, usize)) -> String {
+        let (start, end) = (cmp::min(start, end), cmp::max(start, end));
+        if start.0 >= self.rows.len() {
+            return String::new();
+        }
+        let end = match end.0 < self.rows.len() {
+            true => end,
+            false => (self.rows.len() - 1, usize::MAX),
+        };
+        let start = Position { cx: start.1, cy: start.0 };
+        let end = Position { cx: end.1, cy: end.0 };
+        let text = self.text_range(start, end);
+
+        let head = self.byte_index(start.cy, start.cx);
+        let tail_at = self.byte_index(end.cy, end.cx);
+        let tail = self.rows[end.cy].chars[tail_at..].to_string();
+
+        self.rows[start.cy].chars.truncate(head);
+        self.rows[start.cy].chars.push_str(&tail);
+        self.rows.drain(start.cy + 1..end.cy + 1);
+        self.rows_replaced(start.cy + 1, end.cy - start.cy, 0);
+        self.update_render(start.cy);
+
+        text
+    }
+
+    pub(crate) fn insert_row(&mut self, at: usize, chars: &str) {
+        self.rows.insert(at, Row::new(chars));
+        self.rows_replaced(at, 0, 1);
+        self.update_render(at);
+    }
+
+    pub(crate) fn update_render(&mut self, at: usize) {
+        let render = self.render_text(&self.rows[at].chars);
+        self.rows[at].render = render;
+        self.rows[at].stale = false;
+        self.row_edited(at);
+    }
+
+    /// Keeps the offset index and the indentation counts up to date after the text of row
+    /// `at` changed.
+    pub(crate) fn row_edited(&mut self, at: usize) {
+        let indent = leading_indent(&self.rows[at].chars);
+        let counted = mem::replace(&mut self.rows[at].indent, indent);
+        if let Some((rows, mut tabs, mut spaces)) = self.indents.get() {
+            for (c, delta) in [(counted, usize::MAX), (indent, 1)] {
+                match c {
+                    Some('\t') => tabs = tabs.wrapping_add(delta),
+                    Some(_) => spaces = spaces.wrapping_add(delta),
+                    None => {}
+                }
+            }
+            self.indents.set(Some((rows, tabs, spaces)));
+        }
+
+        let len = encoded_len(&self.rows[at].chars) + self.line_ending().len();
+        let rows = self.rows.len();
+        if let Some(ref mut offsets) = *self.offsets.borrow_mut() {
+            match offsets.len() == rows {
+                true => offsets.set(at, len),
+                false => *offsets = OffsetIndex::new(&self.rows, self.line_ending().len()),
+            }
+        }
+    }
+
+    /// Updates the offset index after `removed` rows at `at` were replaced by `added` new
+    /// ones, or drops it when that would take longer than rebuilding it. Drops the
+    /// indentation counts.
+    pub(crate) fn rows_replaced(&mut self, at: usize, removed: usize, added: usize) {
+        let ending = self.line_ending().len();
+        let mut offsets = self.offsets.borrow_mut();
+        match *offsets {
+            Some(ref mut index)
+                if index.len() + added == self.rows.len() + removed
+                    && removed + added <= OFFSET_INDEX_CHUNK =>
+            {
+                for _ in 0..removed {
+                    index.remove(at);
+                }
+                for (i, row) in self.rows[at..(at + added)].iter().enumerate() {
+                    index.insert(at + i, encoded_len(&row.chars) + ending);
+                }
+            }
+            _ => *offsets = None,
+        }
+        self.indents.set(None);
+    }
+
+    /// Drops the offset index and the indentation counts after all the rows were replaced.
+    pub(crate) fn rows_changed(&mut self) {
+        *self.offsets.borrow_mut() = None;
+        self.indents.set(None);
+    }
+
+    /// The character most rows are indented with, if any are.
+    pub(crate) fn indent_style(&self) -> Option<char> {
+        let (tabs, spaces) = match self.indents.get() {
+            Some((rows, tabs, spaces)) if rows == self.rows.len() => (tabs, spaces),
+            _ => {
+                let indents = self.rows.iter().map(|row| row.indent);
+                let tabs = indents.clone().filter(|&c| c == Some('\t')).count();
+                let spaces = indents.filter(|&c| c == Some(' ')).count();
+                self.indents.set(Some((self.rows.len(), tabs, spaces)));
+                (tabs, spaces)
+            }
+        };
+
+        match (tabs, spaces) {
+            (0, 0) => None,
+            _ if tabs >= spaces => Some('\t'),
+            _ => Some(' '),
+        }
+    }
+
+    /// The offset index, built if it isn't current. Counting the rows catches changes to
+    /// them that weren't reported.
+    fn offset_index(&self) -> RefMut<'_, OffsetIndex> {
+        let mut offsets = self.offsets.borrow_mut();
+        if offsets.as_ref().is_none_or(|o| o.len() != self.rows.len()) {
+            *offsets = Some(OffsetIndex::new(&self.rows, self.line_ending().len()));
+        }
+        RefMut::map(offsets, |o| o.as_mut().unwrap())
+    }
+
+    /// The offset in the file at which row `at` starts.
+    pub(crate) fn row_offset(&self, at: usize) -> usize {
+        self.offset_index().offset(at)
+    }
+
+    /// The size of the file the rows make up.
+    pub(crate) fn byte_len(&self) -> usize {
+        let unended = !self.final_newline && !self.rows.is_empty();
+        self.row_offset(self.rows.len()) - if unended { self.line_ending().len() } else { 0 }
+    }
+
+    /// The row that byte `offset` of the file is on, and the offset within the row.
+    pub(crate) fn row_at_offset(&self, offset: usize) -> (usize, usize) {
+        self.offset_index().find(offset)
+    }
+
+    /// `chars` as displayed with the buffer's tab stop.
+    pub(crate) fn render_text(&self, chars: &str) -> String {
+        render_text(chars, self.tabstop)
+    }
+
+    /// The byte index in row `cy` of character `cx`, or the row's length past its end.
+    pub(crate) fn byte_index(&self, cy: usize, cx: usize) -> usize {
+        let chars = &self.rows[cy].chars;
+        chars.char_indices().nth(cx).map_or(chars.len(), |(i, _)| i)
+    }
+
+    pub(crate) fn text_range(&self, start: Position, end: Position) -> String {
+        if start.cy >= self.rows.len() {
+            return String::new();
+        }
+
+        let head = self.byte_index(start.cy, start.cx);
+        if start.cy == end.cy {
+            let tail = self.byte_index(end.cy, end.cx);
+            return self.rows[start.cy].chars[head..tail].to_string();
+        }
+
+        let mut text = self.rows[start.cy].chars[head..].to_string();
+        for cy in (start.cy + 1)..end.cy.min(self.rows.len()) {
+            text.push('\n');
+            text.push_str(&self.rows[cy].chars);
+        }
+        text.push('\n');
+        if end.cy < self.rows.len() {
+            let tail = self.byte_index(end.cy, end.cx);
+            text.push_str(&self.rows[end.cy].chars[..tail]);
+        }
+
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(buffer: &Buffer) -> Vec<&str> {
+        (0..buffer.line_count()).filter_map(|at| buffer.line(at)).collect()
+    }
+
+    #[test]
+    fn from_text_keeps_line_endings() {
+        let buffer = Buffer::from_text("a\r\nb\r\n");
+        assert_eq!(lines(&buffer), ["a", "b"]);
+        assert!(buffer.crlf && buffer.final_newline);
+        assert_eq!(buffer.to_bytes(), b"a\r\nb\r\n");
+
+        let buffer = Buffer::from_text("a\nb");
+        assert_eq!(lines(&buffer), ["a", "b"]);
+        assert!(!buffer.final_newline);
+        assert_eq!(buffer.to_bytes(), b"a\nb");
+
+        // With only some lines ending in `\r\n` the `\r` stays in them.
+        let buffer = Buffer::from_text("a\r\nb\n");
+        assert_eq!(lines(&buffer), ["a\r", "b"]);
+        assert_eq!(buffer.to_bytes(), b"a\r\nb\n");
+
+        assert_eq!(Buffer::from_text("").line_count(), 0);
+    }
+
+    #[test]
+    fn from_bytes_round_trips_invalid_utf8() {
+        let bytes = b"ok \xff\xfe\n\xf4\x8f\xbf\xbd\n";
+        let buffer = Buffer::from_bytes(bytes);
+        assert_eq!(buffer.line_count(), 2);
+        assert_eq!(buffer.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn render_expands_tabs_and_placeholders() {
+        let mut buffer = Buffer::from_bytes(b"\tx\x01\xff\n");
+        assert_eq!(buffer.row(0).map(Row::render), Some("        x^A<ff>"));
+
+        buffer.set_tabstop(4);
+        assert_eq!(buffer.tabstop(), 4);
+        assert_eq!(buffer.row(0).map(Row::render), Some("    x^A<ff>"));
+    }
+
+    #[test]
+    fn insert() {
+        let mut buffer = Buffer::from_text("héllo\nworld\n");
+        assert_eq!(buffer.insert(0, 2, "XY"), (0, 4));
+        assert_eq!(lines(&buffer), ["héXYllo", "world"]);
+
+        assert_eq!(buffer.insert(1, 1, "1\n2\n3"), (3, 1));
+        assert_eq!(lines(&buffer), ["héXYllo", "w1", "2", "3orld"]);
+
+        assert_eq!(buffer.insert(9, 0, "end\n"), (5, 0));
+        assert_eq!(lines(&buffer), ["héXYllo", "w1", "2", "3orld", "end", ""]);
+        assert_eq!(buffer.to_string(), "héXYllo\nw1\n2\n3orld\nend\n\n");
+    }
+
+    #[test]
+    fn insert_counts_indentation() {
+        let mut buffer = Buffer::from_text("a\n");
+        assert_eq!(buffer.indent_style(), None);
+
+        buffer.insert(0, 1, "\n\tb\n\tc");
+        assert_eq!(buffer.indent_style(), Some('\t'));
+
+        buffer.insert(2, 2, "\n  d\n  e\n  f");
+        assert_eq!(lines(&buffer), ["a", "\tb", "\tc", "  d", "  e", "  f"]);
+        assert_eq!(buffer.indent_style(), Some(' '));
+    }
+
+    #[test]
+    fn delete_range() {
+        let mut buffer = Buffer::from_text("one\ntwo\nthree\n");
+        assert_eq!(buffer.delete_range((1, 1), (0, 2)), "e\nt");
+        assert_eq!(lines(&buffer), ["onwo", "three"]);
+
+        assert_eq!(buffer.delete_range((1, 2), (7, 0)), "ree");
+        assert_eq!(lines(&buffer), ["onwo", "th"]);
+
+        assert_eq!(buffer.delete_range((5, 0), (6, 0)), "");
+        assert_eq!(lines(&buffer), ["onwo", "th"]);
+    }
+
+    #[test]
+    fn offsets_follow_edits() {
+        let mut buffer = Buffer::from_text("ab\ncd\r\né\n");
+        assert_eq!(buffer.byte_len(), 10);
+        assert_eq!(buffer.row_offset(2), 7);
+        assert_eq!(buffer.row_at_offset(8), (2, 1));
+
+        buffer.insert(0, 0, "x\ny");
+        assert_eq!(buffer.row_offset(3), 10);
+        assert_eq!(buffer.row_at_offset(10), (3, 0));
+        assert_eq!(buffer.byte_len(), 13);
+
+        buffer.delete_range((0, 0), (1, 1));
+        assert_eq!(buffer.row_offset(2), 7);
+        assert_eq!(buffer.byte_len(), 10);
+    }
+
+    #[test]
+    fn offset_index() {
+        let rows = ["a", "", "bcd", "é"].map(Row::new);
+        let mut index = OffsetIndex::new(&rows, 1);
+        assert_eq!(index.len(), 4);
+        assert_eq!((0..=4).map(|at| index.offset(at)).collect::<Vec<usize>>(), [0, 2, 3, 7, 10]);
+        assert_eq!(index.find(0), (0, 0));
+        assert_eq!(index.find(2), (1, 0));
+        assert_eq!(index.find(5), (2, 2));
+        assert_eq!(index.find(10), (4, 0));
+        assert_eq!(index.find(99), (4, 89));
+
+        index.set(1, 5);
+        assert_eq!(index.offset(2), 7);
+        assert_eq!(index.offset(4), 14);
+        assert_eq!(index.find(7), (2, 0));
+        assert_eq!(index.find(6), (1, 4));
+    }
+
+    #[test]
+    fn offset_index_follows_added_and_removed_rows() {
+        let text = (0..2500).map(|i| format!("{}\n", i)).collect::<String>();
+        let mut buffer = Buffer::from_text(&text);
+        assert_eq!(buffer.row_offset(2500), text.len());
+
+        buffer.insert_row(0, "abc");
+        buffer.insert_row(2048, "");
+        buffer.delete_range((1023, 0), (1025, 0));
+        buffer.delete_range((2047, 0), (2500, 0));
+        buffer.insert(10, 0, "x\ny\nz\n");
+
+        let index = buffer.offsets.borrow_mut().take().expect("index dropped");
+        let rebuilt = OffsetIndex::new(&buffer.rows, 1);
+        assert_eq!((index.lens, index.sums), (rebuilt.lens, rebuilt.sums));
+    }
+}
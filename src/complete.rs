@@ -0,0 +1,213 @@
+//! Completion: Ctrl-N offers the words in the open files that start with the one before the
+//! cursor, in a popup next to it, and Tab after a path completes it from the directory.
+
+use super::*;
+
+/// The completions on offer for the word that starts at `start`.
+pub(crate) struct Completion {
+    pub(crate) start: Position,
+    pub(crate) items: Vec<String>,
+    pub(crate) selected: usize,
+}
+
+/// The distinct words in `lines` that start with `prefix` and are longer than it, in the
+/// order they first appear.
+pub(crate) fn completion_words<'a, I>(lines: I, prefix: &str) -> Vec<String>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let mut seen = HashSet::new();
+    let mut words = Vec::new();
+
+    for line in lines {
+        for word in line.split(|c: char| !is_word_char(c)) {
+            if word.len() > prefix.len() && word.starts_with(prefix) && seen.insert(word) {
+                words.push(word.to_string());
+            }
+        }
+    }
+
+    words
+}
+
+/// The names in directory `dir` that start with `prefix`, sorted, with a `/` after those of
+/// directories. Hidden entries are only included if `prefix` starts with a dot.
+pub(crate) fn path_completions(dir: &Path, prefix: &str) -> Vec<String> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut names = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            // Follow symlinks, so a link to a directory completes like one.
+            match fs::metadata(entry.path()).is_ok_and(|meta| meta.is_dir()) {
+                true => Some(format!("{}/", name)),
+                false => Some(name),
+            }
+        })
+        .collect::<Vec<String>>();
+    names.sort();
+
+    names
+}
+
+/// The longest text every one of `items` starts with.
+fn common_prefix(items: &[String]) -> &str {
+    let first = match items.first() {
+        Some(first) => first.as_str(),
+        None => return "",
+    };
+
+    let len = items[1..].iter().fold(first.len(), |len, item| {
+        let same = first.char_indices().zip(item.chars()).take_while(|&((_, a), b)| a == b);
+        same.last().map_or(0, |((i, c), _)| cmp::min(len, i + c.len_utf8()))
+    });
+
+    &first[..len]
+}
+
+impl Kilo {
+    /// The words `prefix` can be completed to: those after the cursor first, then those
+    /// before it, then those in the other file of a comparison.
+    fn editor_completion_words(&self, prefix: &str) -> Vec<String> {
+        let rows = &self.buffer.rows;
+        let cy = cmp::min(self.cy, rows.len());
+        let others = self.comparison.iter().flat_map(|comparison| comparison.rows.iter());
+        let lines = rows[cy..].iter().chain(&rows[..cy]).chain(others);
+
+        completion_words(lines.map(|row| row.chars.as_str()), prefix)
+    }
+
+    /// Completes the word before the cursor from a popup of the words it could become. Up
+    /// and Down (or Ctrl-N and Ctrl-P) choose one, Enter or Tab takes it, and typing more of
+    /// the word narrows the choice down.
+    pub(crate) fn editor_complete_word(&mut self) -> io::Result<()> {
+        let before = match self.buffer.rows.get(self.cy) {
+            Some(row) => row.chars.chars().take(self.cx).collect::<Vec<char>>(),
+            None => Vec::new(),
+        };
+        let cx = before.iter().rposition(|&c| !is_word_char(c)).map_or(0, |i| i + 1);
+        let mut prefix = before[cx..].iter().collect::<String>();
+        if prefix.is_empty() {
+            self.editor_set_status_message("No word before the cursor to complete");
+            return Ok(());
+        }
+
+        let mut words = self.editor_completion_words(&prefix);
+        let start = Position { cx, cy: self.cy };
+        let mut accepted = None;
+
+        loop {
+            let items = words
+                .iter()
+                .filter(|word| word.starts_with(&prefix) && word.len() > prefix.len())
+                .cloned()
+                .collect::<Vec<String>>();
+            if items.is_empty() {
+                self.editor_set_status_message(&format!("No completions for {}", prefix));
+                break;
+            }
+            let selected = self.completion.as_ref().map_or(0, |c| c.selected);
+            let selected = cmp::min(selected, items.len() - 1);
+            self.completion = Some(Completion { start, items, selected });
+            self.editor_refresh_screen()?;
+
+            let key = self.editor_wait_key()?;
+            let completion = match self.completion {
+                Some(ref mut completion) => completion,
+                None => break,
+            };
+            let len = completion.items.len();
+
+            match key {
+                ArrowDown => completion.selected = (completion.selected + 1) % len,
+                ArrowUp => completion.selected = (completion.selected + len - 1) % len,
+                Char(c) if c == ctrl_key('n') => {
+                    completion.selected = (completion.selected + 1) % len;
+                }
+                Char(c) if c == ctrl_key('p') => {
+                    completion.selected = (completion.selected + len - 1) % len;
+                }
+                Char(b'\r') | Char(b'\t') => {
+                    accepted = Some(completion.items[completion.selected].clone());
+                    break;
+                }
+                Char(c) if c == 127 || c == ctrl_key('h') => {
+                    self.editor_del_char();
+                    prefix.pop();
+                    if prefix.is_empty() {
+                        break;
+                    }
+                    words = self.editor_completion_words(&prefix);
+                }
+                Char(c) if is_word_char(c as char) && c.is_ascii_graphic() => {
+                    self.editor_insert_char(c as char);
+                    prefix.push(c as char);
+                }
+                Text(c) if is_word_char(c) => {
+                    self.editor_insert_char(c);
+                    prefix.push(c);
+                }
+                Text(c) => {
+                    self.editor_insert_char(c);
+                    break;
+                }
+                // Anything else, such as a space or punctuation, ends the word.
+                Char(c) if (b' '..=b'~').contains(&c) => {
+                    self.editor_insert_char(c as char);
+                    break;
+                }
+                _ => break,
+            }
+        }
+
+        self.completion = None;
+        if let Some(word) = accepted {
+            self.editor_insert_str(&word[prefix.len()..]);
+        }
+
+        Ok(())
+    }
+
+    /// Completes the path before the cursor, a word with a `/` in it, as far as the entries
+    /// of its directory agree, listing them in the message bar if there are several. Relative
+    /// paths are from the working directory and `~/` is the home directory. Returns false if
+    /// there is no such path or nothing it could be completed to.
+    pub(crate) fn editor_complete_path(&mut self) -> bool {
+        let before = match self.buffer.rows.get(self.cy) {
+            Some(row) => row.chars.chars().take(self.cx).collect::<Vec<char>>(),
+            None => return false,
+        };
+        let delimiter = |c: char| c.is_whitespace() || "\"'`()[]{}<>=,;|".contains(c);
+        let start = before.iter().rposition(|&c| delimiter(c)).map_or(0, |i| i + 1);
+        let path = before[start..].iter().collect::<String>();
+
+        let (dir, name) = match path.rfind('/') {
+            Some(_) if path.chars().all(|c| c == '/') => return false,
+            Some(i) => (&path[..(i + 1)], &path[(i + 1)..]),
+            None => return false,
+        };
+        let dir = match (dir.strip_prefix("~/"), env::var_os("HOME")) {
+            (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+            _ => PathBuf::from(dir),
+        };
+
+        let names = path_completions(&dir, name);
+        if names.is_empty() {
+            return false;
+        }
+        let common = common_prefix(&names);
+        self.editor_insert_str(&common[name.len()..]);
+        if names.len() > 1 {
+            self.editor_set_status_message(&names.join("  "));
+        }
+
+        true
+    }
+}
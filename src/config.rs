@@ -0,0 +1,630 @@
+//! The kilorc configuration file, modelines and `.editorconfig` support.
+
+use super::*;
+
+pub(crate) fn xdg_dir(var: &str, fallback: &str) -> Option<PathBuf> {
+    match env::var_os(var) {
+        Some(dir) => Some(PathBuf::from(dir).join("kilo")),
+        None => env::var_os("HOME").map(|home| PathBuf::from(home).join(fallback).join("kilo")),
+    }
+}
+
+pub(crate) fn config_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+pub(crate) fn state_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_STATE_HOME", ".local/state")
+}
+
+/// Option `key=value` (or bare flag) pairs from a vim `vim: ...` or emacs `-*- ... -*-`
+/// modeline, with emacs `key: value` entries turned into the same shape.
+pub(crate) fn parse_modeline(line: &str) -> Vec<(String, String)> {
+    let mut options = Vec::new();
+
+    if let Some(start) = line.find("-*-") {
+        let rest = &line[(start + 3)..];
+        if let Some(end) = rest.find("-*-") {
+            for entry in rest[..end].split(';') {
+                if let Some(colon) = entry.find(':') {
+                    let key = entry[..colon].trim().to_lowercase();
+                    options.push((key, entry[(colon + 1)..].trim().to_string()));
+                }
+            }
+        }
+        return options;
+    }
+
+    let start = ["vim:", "vi:", "ex:"].iter().find_map(|marker| {
+        line.find(marker)
+            .filter(|&i| i == 0 || line[..i].ends_with(char::is_whitespace))
+            .map(|i| i + marker.len())
+    });
+    let mut rest = match start {
+        Some(start) => line[start..].trim(),
+        None => return options,
+    };
+
+    // The `set ...:` form ends at the next colon, the plain form is split on colons too.
+    let set_form = rest.starts_with("set ") || rest.starts_with("se ");
+    if set_form {
+        rest = rest.split_once(' ').map_or("", |(_, r)| r);
+        rest = rest.split(':').next().unwrap_or("");
+    }
+
+    for option in rest.split(|c: char| c.is_whitespace() || (!set_form && c == ':')) {
+        match option.split_once('=') {
+            Some((key, value)) => options.push((key.to_string(), value.to_string())),
+            None if !option.is_empty() => options.push((option.to_string(), String::new())),
+            None => {}
+        }
+    }
+
+    options
+}
+
+/// Matches `text` against a shell-style pattern supporting `*` and `?`.
+pub(crate) fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<char>>();
+    let text = text.chars().collect::<Vec<char>>();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((bp, bt)) => {
+                    backtrack = Some((bp, bt + 1));
+                    p = bp + 1;
+                    t = bt + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Expands `{a,b}` alternatives in an EditorConfig section pattern.
+pub(crate) fn expand_braces(pattern: &str) -> Vec<String> {
+    let (open, close) = match (pattern.find('{'), pattern.find('}')) {
+        (Some(open), Some(close)) if open < close => (open, close),
+        _ => return vec![pattern.to_string()],
+    };
+
+    pattern[(open + 1)..close]
+        .split(',')
+        .flat_map(|alternative| {
+            let (head, tail) = (&pattern[..open], &pattern[(close + 1)..]);
+            expand_braces(&format!("{}{}{}", head, alternative, tail))
+        })
+        .collect()
+}
+
+/// The EditorConfig properties for `path`, read from `.editorconfig` files in its directory
+/// and its parents up to one marked `root = true`, nearest settings last.
+pub(crate) fn editorconfig(path: &Path) -> Vec<(String, String)> {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let name = path.file_name().map_or(String::new(), |n| n.to_string_lossy().into_owned());
+
+    let mut files = Vec::new();
+    let mut dir = path.parent();
+    while let Some(d) = dir {
+        if let Ok(contents) = fs::read_to_string(d.join(".editorconfig")) {
+            let root = contents
+                .lines()
+                .map(|l| l.replace(' ', "").to_lowercase())
+                .take_while(|l| !l.starts_with('['))
+                .any(|l| l == "root=true");
+            files.push((d.to_path_buf(), contents));
+            if root {
+                break;
+            }
+        }
+        dir = d.parent();
+    }
+
+    let mut settings = Vec::new();
+    for (dir, contents) in files.iter().rev() {
+        let relative = path.strip_prefix(dir).map_or(String::new(), |p| p.display().to_string());
+        let mut matched = false;
+
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            if line.starts_with('[') && line.ends_with(']') {
+                matched = expand_braces(&line[1..(line.len() - 1)]).iter().any(|p| {
+                    match p.contains('/') {
+                        true => glob_match(p.trim_start_matches('/'), &relative),
+                        false => glob_match(p, &name),
+                    }
+                });
+            } else if let (true, Some(pos)) = (matched, line.find('=')) {
+                let key = line[..pos].trim().to_lowercase();
+                settings.push((key, line[(pos + 1)..].trim().to_lowercase()));
+            }
+        }
+    }
+
+    settings
+}
+
+#[derive(Clone)]
+pub(crate) enum Scope {
+    Filetype(String),
+    Glob(String),
+}
+
+impl Scope {
+    /// Parses a `[filetype.NAME]` or `[glob."PATTERN"]` section header.
+    pub(crate) fn parse(header: &str) -> Option<Scope> {
+        let (kind, name) = header.split_once('.')?;
+        let name = name.trim().trim_matches('"').to_string();
+
+        match kind.trim() {
+            "filetype" => Some(Scope::Filetype(name)),
+            "glob" => Some(Scope::Glob(name)),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn matches(&self, filetype: Option<&str>, filename: &str) -> bool {
+        match *self {
+            Scope::Filetype(ref name) => filetype.is_some_and(|ft| ft.eq_ignore_ascii_case(name)),
+            Scope::Glob(ref pattern) => {
+                let base = Path::new(filename).file_name().map(|n| n.to_string_lossy());
+                glob_match(pattern, filename) || base.is_some_and(|b| glob_match(pattern, &b))
+            }
+        }
+    }
+}
+
+/// What `editor_ring_bell` does.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Bell {
+    Off,
+    Audible,
+    Visual,
+}
+
+/// How Ctrl-Q makes sure unsaved changes aren't lost by accident.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum QuitConfirm {
+    /// Ask whether to save them first.
+    Prompt,
+    /// Quit only when Ctrl-Q is pressed this many times in a row.
+    Presses(usize),
+}
+
+#[derive(Clone)]
+pub(crate) struct Config {
+    pub(crate) highlight_current_line: bool,
+    pub(crate) ruler: Option<usize>,
+    pub(crate) spell_check: bool,
+    pub(crate) spell_dictionary: Option<PathBuf>,
+    pub(crate) lint: HashMap<String, String>,
+    pub(crate) lsp: HashMap<String, String>,
+    pub(crate) hooks: HashMap<String, String>,
+    pub(crate) snippets: HashMap<String, String>,
+    pub(crate) modelines: bool,
+    pub(crate) tabstop: usize,
+    /// Columns an indentation level takes up, or `0` to use `tabstop`.
+    pub(crate) shiftwidth: usize,
+    /// Whether Tab indents with spaces instead of a tab character.
+    pub(crate) expandtab: bool,
+    pub(crate) text_width: usize,
+    pub(crate) format_indent: usize,
+    pub(crate) smart_home: bool,
+    pub(crate) smart_end: bool,
+    pub(crate) indent_warnings: bool,
+    pub(crate) insert_final_newline: bool,
+    pub(crate) editorconfig: bool,
+    pub(crate) show_offset: bool,
+    pub(crate) terminal_title: bool,
+    pub(crate) persist_registers: bool,
+    pub(crate) vi: bool,
+    pub(crate) sudo_command: String,
+    pub(crate) print_command: String,
+    pub(crate) grep_command: String,
+    pub(crate) make_command: String,
+    /// `None` to decide from the terminal's environment.
+    pub(crate) hyperlinks: Option<bool>,
+    pub(crate) bell: Bell,
+    /// Seconds a message stays in the message bar, or `0` to keep it until the next key.
+    pub(crate) message_timeout: u64,
+    pub(crate) quit_confirm: QuitConfirm,
+    /// Whether `quit_confirm` also applies to unnamed buffers, such as scratch buffers.
+    pub(crate) quit_confirm_unnamed: bool,
+    pub(crate) sections: Vec<(Scope, Vec<(String, String)>)>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            highlight_current_line: false,
+            ruler: None,
+            spell_check: false,
+            spell_dictionary: None,
+            lint: HashMap::new(),
+            lsp: HashMap::new(),
+            hooks: HashMap::new(),
+            snippets: HashMap::new(),
+            modelines: true,
+            tabstop: KILO_TAB_STOP,
+            shiftwidth: 0,
+            expandtab: false,
+            text_width: KILO_TEXT_WIDTH,
+            format_indent: KILO_FORMAT_INDENT,
+            smart_home: true,
+            smart_end: false,
+            indent_warnings: true,
+            insert_final_newline: true,
+            editorconfig: true,
+            show_offset: false,
+            terminal_title: true,
+            persist_registers: false,
+            vi: false,
+            sudo_command: KILO_SUDO_COMMAND.to_string(),
+            print_command: KILO_PRINT_COMMAND.to_string(),
+            grep_command: KILO_GREP_COMMAND.to_string(),
+            make_command: KILO_MAKE_COMMAND.to_string(),
+            hyperlinks: None,
+            bell: Bell::Off,
+            message_timeout: KILO_MESSAGE_TIMEOUT,
+            quit_confirm: QuitConfirm::Prompt,
+            quit_confirm_unnamed: true,
+            sections: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    /// The settings in kilorc, and a description of each line that couldn't be read.
+    pub(crate) fn load() -> io::Result<(Self, Vec<String>)> {
+        let mut config = Config::default();
+        let mut errors = Vec::new();
+
+        let path = match config_dir() {
+            Some(dir) => dir.join("kilorc"),
+            None => return Ok((config, errors)),
+        };
+
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok((config, errors)),
+            Err(e) => return Err(e),
+        };
+
+        // Settings after a section header only apply to buffers matching its scope.
+        let mut section: Option<Vec<(String, String)>> = None;
+        let mut scope = None;
+
+        for (i, line) in BufReader::new(file).split(b'\n').enumerate() {
+            let line = match String::from_utf8(line?) {
+                Ok(line) => line,
+                Err(_) => {
+                    errors.push(format!("kilorc line {}: not valid UTF-8", i + 1));
+                    continue;
+                }
+            };
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if line.starts_with('[') && line.ends_with(']') {
+                if let (Some(scope), Some(settings)) = (scope.take(), section.take()) {
+                    config.sections.push((scope, settings));
+                }
+                scope = Scope::parse(&line[1..(line.len() - 1)]);
+                section = Some(Vec::new());
+                continue;
+            }
+
+            if let Some(pos) = line.find('=') {
+                let (key, value) = (line[..pos].trim(), line[(pos + 1)..].trim());
+                match section {
+                    Some(ref mut settings) => settings.push((key.to_string(), value.to_string())),
+                    None => config.set(key, value),
+                }
+            }
+        }
+
+        if let (Some(scope), Some(settings)) = (scope, section) {
+            config.sections.push((scope, settings));
+        }
+
+        Ok((config, errors))
+    }
+
+    /// The settings in effect for a buffer, with matching sections applied in file order.
+    pub(crate) fn scoped(&self, filetype: Option<&str>, filename: &str) -> Config {
+        let mut config = self.clone();
+
+        for (scope, settings) in &self.sections {
+            if scope.matches(filetype, filename) {
+                for (key, value) in settings {
+                    config.set(key, value);
+                }
+            }
+        }
+
+        config
+    }
+
+    /// The columns an indentation level takes up.
+    pub(crate) fn indent_width(&self) -> usize {
+        match self.shiftwidth {
+            0 => self.tabstop,
+            width => width,
+        }
+    }
+
+    pub(crate) fn set(&mut self, key: &str, value: &str) {
+        match key {
+            "highlight_current_line" => {
+                if let Ok(v) = value.parse() {
+                    self.highlight_current_line = v;
+                }
+            }
+            "spell_check" => {
+                if let Ok(v) = value.parse() {
+                    self.spell_check = v;
+                }
+            }
+            "spell_dictionary" => self.spell_dictionary = Some(PathBuf::from(value)),
+            "sudo_command" => self.sudo_command = value.to_string(),
+            "print_command" => self.print_command = value.to_string(),
+            "grep_command" => self.grep_command = value.to_string(),
+            "make_command" => self.make_command = value.to_string(),
+            "hyperlinks" => match value {
+                "auto" => self.hyperlinks = None,
+                _ => {
+                    if let Ok(v) = value.parse() {
+                        self.hyperlinks = Some(v);
+                    }
+                }
+            },
+            "bell" => match value {
+                "off" | "none" => self.bell = Bell::Off,
+                "audible" => self.bell = Bell::Audible,
+                "visual" => self.bell = Bell::Visual,
+                _ => {}
+            },
+            "modelines" => {
+                if let Ok(v) = value.parse() {
+                    self.modelines = v;
+                }
+            }
+            "terminal_title" => {
+                if let Ok(v) = value.parse() {
+                    self.terminal_title = v;
+                }
+            }
+            "smart_home" => {
+                if let Ok(v) = value.parse() {
+                    self.smart_home = v;
+                }
+            }
+            "smart_end" => {
+                if let Ok(v) = value.parse() {
+                    self.smart_end = v;
+                }
+            }
+            "indent_warnings" => {
+                if let Ok(v) = value.parse() {
+                    self.indent_warnings = v;
+                }
+            }
+            "insert_final_newline" => {
+                if let Ok(v) = value.parse() {
+                    self.insert_final_newline = v;
+                }
+            }
+            "editorconfig" => {
+                if let Ok(v) = value.parse() {
+                    self.editorconfig = v;
+                }
+            }
+            "show_offset" => {
+                if let Ok(v) = value.parse() {
+                    self.show_offset = v;
+                }
+            }
+            "persist_registers" => {
+                if let Ok(v) = value.parse() {
+                    self.persist_registers = v;
+                }
+            }
+            "vi" => {
+                if let Ok(v) = value.parse() {
+                    self.vi = v;
+                }
+            }
+            "message_timeout" => {
+                if let Ok(v) = value.parse() {
+                    self.message_timeout = v;
+                }
+            }
+            "quit_confirm" => match value {
+                "prompt" => self.quit_confirm = QuitConfirm::Prompt,
+                "presses" => self.quit_confirm = QuitConfirm::Presses(KILO_QUIT_TIMES),
+                _ => {
+                    if let Ok(v) = value.parse::<usize>() {
+                        self.quit_confirm = QuitConfirm::Presses(v.max(1));
+                    }
+                }
+            },
+            "quit_confirm_unnamed" => {
+                if let Ok(v) = value.parse() {
+                    self.quit_confirm_unnamed = v;
+                }
+            }
+            "tabstop" => {
+                if let Ok(v) = value.parse::<usize>() {
+                    if v > 0 {
+                        self.tabstop = v;
+                    }
+                }
+            }
+            "shiftwidth" => {
+                if let Ok(v) = value.parse() {
+                    self.shiftwidth = v;
+                }
+            }
+            "expandtab" => {
+                if let Ok(v) = value.parse() {
+                    self.expandtab = v;
+                }
+            }
+            "format_indent" => {
+                if let Ok(v) = value.parse() {
+                    self.format_indent = v;
+                }
+            }
+            "text_width" => {
+                if let Ok(v) = value.parse::<usize>() {
+                    if v > 0 {
+                        self.text_width = v;
+                    }
+                }
+            }
+            _ if key.starts_with("lint.") => {
+                self.lint.insert(key["lint.".len()..].to_string(), value.to_string());
+            }
+            _ if key.starts_with("lsp.") => {
+                self.lsp.insert(key["lsp.".len()..].to_string(), value.to_string());
+            }
+            _ if key.starts_with("hook.") => {
+                self.hooks.insert(key["hook.".len()..].to_string(), value.to_string());
+            }
+            _ if key.starts_with("snippet.") => {
+                self.snippets.insert(key["snippet.".len()..].to_string(), value.to_string());
+            }
+            "ruler" => {
+                if let Ok(v) = value.parse::<usize>() {
+                    self.ruler = if v > 0 { Some(v) } else { None };
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Kilo {
+    /// Applies the indentation and text width options of modelines, warning about the
+    /// options that aren't supported.
+    pub(crate) fn editor_apply_modelines(&mut self, lines: &[&str]) {
+        let head = lines.iter().take(KILO_MODELINE_ROWS);
+        let tail = lines.iter().skip(KILO_MODELINE_ROWS).rev().take(KILO_MODELINE_ROWS);
+        let mut unsupported = Vec::new();
+
+        for line in head.chain(tail) {
+            for (key, value) in parse_modeline(line) {
+                let number = value.parse::<usize>().ok().filter(|&n| n > 0);
+                match (key.as_str(), value.as_str()) {
+                    ("ts" | "tabstop" | "tab-width", _) if number.is_some() => {
+                        self.buffer.tabstop = number.unwrap_or(KILO_TAB_STOP);
+                        self.config.tabstop = self.buffer.tabstop;
+                    }
+                    ("sw" | "shiftwidth" | "c-basic-offset", _) if number.is_some() => {
+                        self.config.set("shiftwidth", &value);
+                    }
+                    ("tw" | "textwidth" | "fill-column", _) if number.is_some() => {
+                        self.config.set("text_width", &value);
+                    }
+                    ("et" | "expandtab", "") | ("indent-tabs-mode", "nil") => {
+                        self.config.expandtab = true;
+                    }
+                    ("noet" | "noexpandtab", "") | ("indent-tabs-mode", "t") => {
+                        self.config.expandtab = false;
+                    }
+                    // The encoding doesn't matter: files are read as bytes.
+                    ("coding", _) => {}
+                    _ if !unsupported.contains(&key) => unsupported.push(key),
+                    _ => {}
+                }
+            }
+        }
+
+        if !unsupported.is_empty() {
+            let message = format!("Unsupported modeline options: {}", unsupported.join(", "));
+            self.editor_set_status_message_with(Severity::Warning, &message);
+        }
+    }
+
+    pub(crate) fn editor_apply_editorconfig(&mut self) {
+        let settings = editorconfig(Path::new(&self.filename));
+        let get = |key: &str| settings.iter().rev().find(|s| s.0 == key).map(|s| s.1.as_str());
+
+        if let Some(value) = get("insert_final_newline") {
+            self.config.set("insert_final_newline", value);
+        }
+        if let Some(width) = get("tab_width").or(get("indent_size").filter(|&v| v != "tab")) {
+            self.config.set("tabstop", width);
+        }
+        match get("indent_size") {
+            Some("tab") => self.config.shiftwidth = 0,
+            Some(size) => self.config.set("shiftwidth", size),
+            None => {}
+        }
+        match get("indent_style") {
+            Some("space") => self.config.expandtab = true,
+            Some("tab") => self.config.expandtab = false,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(line: &str) -> Vec<String> {
+        let options = parse_modeline(line).into_iter();
+        options.map(|(key, value)| format!("{}={}", key, value)).collect()
+    }
+
+    #[test]
+    fn vim_modelines() {
+        assert_eq!(options("# vim: ts=4 et"), ["ts=4", "et="]);
+        assert_eq!(options("// vi:ts=2:noet:"), ["ts=2", "noet="]);
+        assert_eq!(options("/* vim: set ts=8 sw=8: */"), ["ts=8", "sw=8"]);
+        assert_eq!(options("ex: ft=rust"), ["ft=rust"]);
+        assert!(options("evim: ts=4").is_empty());
+        assert!(options("no modeline here").is_empty());
+    }
+
+    #[test]
+    fn emacs_modelines() {
+        let line = ";; -*- Mode: Lisp; tab-width: 4 -*-";
+        assert_eq!(options(line), ["mode=Lisp", "tab-width=4"]);
+        assert!(options("-*- unterminated").is_empty());
+    }
+
+    #[test]
+    fn glob_matching() {
+        assert!(glob_match("*.rs", "main.rs"));
+        assert!(glob_match("*.rs", ".rs"));
+        assert!(!glob_match("*.rs", "main.rsx"));
+        assert!(glob_match("Makefile", "Makefile"));
+        assert!(glob_match("?ake*", "Makefile"));
+        assert!(!glob_match("?ake", "ake"));
+        assert!(glob_match("*a*b*", "xxaxxbxx"));
+        assert!(!glob_match("*a*b", "xxbxxa"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("é*", "école"));
+    }
+}
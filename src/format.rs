@@ -0,0 +1,1009 @@
+//! Reformatting and re-encoding text: the JSON and XML pretty-printers behind Alt-F, and the
+//! base64, URL and hex encodings the selection can be transformed with.
+
+use super::*;
+
+pub(crate) enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    Str(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub(crate) fn parse(s: &str) -> Option<Json> {
+        let chars = s.chars().collect::<Vec<char>>();
+        let mut pos = 0;
+        let value = Json::parse_value(&chars, &mut pos)?;
+
+        Json::skip_whitespace(&chars, &mut pos);
+        if pos == chars.len() {
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    /// The character offset at which `s` stops being valid JSON, if it isn't.
+    fn error_offset(s: &str) -> Option<usize> {
+        let chars = s.chars().collect::<Vec<char>>();
+        let mut pos = 0;
+
+        if Json::parse_value(&chars, &mut pos).is_none() {
+            return Some(pos.min(chars.len()));
+        }
+        Json::skip_whitespace(&chars, &mut pos);
+
+        if pos == chars.len() {
+            None
+        } else {
+            Some(pos)
+        }
+    }
+
+    fn skip_whitespace(chars: &[char], pos: &mut usize) {
+        while *pos < chars.len() && chars[*pos].is_whitespace() {
+            *pos += 1;
+        }
+    }
+
+    fn parse_value(chars: &[char], pos: &mut usize) -> Option<Json> {
+        Json::skip_whitespace(chars, pos);
+
+        match *chars.get(*pos)? {
+            'n' => Json::parse_literal(chars, pos, "null", Json::Null),
+            't' => Json::parse_literal(chars, pos, "true", Json::Bool(true)),
+            'f' => Json::parse_literal(chars, pos, "false", Json::Bool(false)),
+            '"' => Json::parse_string(chars, pos).map(Json::Str),
+            '[' => {
+                *pos += 1;
+                let mut items = Vec::new();
+
+                Json::skip_whitespace(chars, pos);
+                if chars.get(*pos) == Some(&']') {
+                    *pos += 1;
+                    return Some(Json::Array(items));
+                }
+
+                loop {
+                    items.push(Json::parse_value(chars, pos)?);
+                    Json::skip_whitespace(chars, pos);
+
+                    match *chars.get(*pos)? {
+                        ',' => *pos += 1,
+                        ']' => {
+                            *pos += 1;
+                            return Some(Json::Array(items));
+                        }
+                        _ => return None,
+                    }
+                }
+            }
+            '{' => {
+                *pos += 1;
+                let mut members = Vec::new();
+
+                Json::skip_whitespace(chars, pos);
+                if chars.get(*pos) == Some(&'}') {
+                    *pos += 1;
+                    return Some(Json::Object(members));
+                }
+
+                loop {
+                    Json::skip_whitespace(chars, pos);
+                    let key = Json::parse_string(chars, pos)?;
+
+                    Json::skip_whitespace(chars, pos);
+                    if chars.get(*pos) != Some(&':') {
+                        return None;
+                    }
+                    *pos += 1;
+
+                    members.push((key, Json::parse_value(chars, pos)?));
+                    Json::skip_whitespace(chars, pos);
+
+                    match *chars.get(*pos)? {
+                        ',' => *pos += 1,
+                        '}' => {
+                            *pos += 1;
+                            return Some(Json::Object(members));
+                        }
+                        _ => return None,
+                    }
+                }
+            }
+            _ => {
+                let start = *pos;
+                while *pos < chars.len() && "+-0123456789.eE".contains(chars[*pos]) {
+                    *pos += 1;
+                }
+                chars[start..*pos]
+                    .iter()
+                    .collect::<String>()
+                    .parse()
+                    .ok()
+                    .map(Json::Number)
+            }
+        }
+    }
+
+    fn parse_literal(chars: &[char], pos: &mut usize, literal: &str, value: Json) -> Option<Json> {
+        let end = *pos + literal.len();
+
+        if end <= chars.len() && chars[*pos..end].iter().cloned().eq(literal.chars()) {
+            *pos = end;
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    fn parse_hex4(chars: &[char], pos: &mut usize) -> Option<u32> {
+        let hex = chars.get((*pos + 1)..(*pos + 5))?.iter().collect::<String>();
+        *pos += 4;
+        u32::from_str_radix(&hex, 16).ok()
+    }
+
+    fn parse_string(chars: &[char], pos: &mut usize) -> Option<String> {
+        if chars.get(*pos) != Some(&'"') {
+            return None;
+        }
+        *pos += 1;
+
+        let mut s = String::new();
+
+        loop {
+            match *chars.get(*pos)? {
+                '"' => {
+                    *pos += 1;
+                    return Some(s);
+                }
+                '\\' => {
+                    *pos += 1;
+                    match *chars.get(*pos)? {
+                        'n' => s.push('\n'),
+                        't' => s.push('\t'),
+                        'r' => s.push('\r'),
+                        'b' => s.push('\x08'),
+                        'f' => s.push('\x0c'),
+                        'u' => {
+                            let mut code = Json::parse_hex4(chars, pos)?;
+                            let surrogate = (0xd800..0xdc00).contains(&code);
+                            if surrogate && chars.get(*pos + 1) == Some(&'\\') {
+                                *pos += 2;
+                                let low = Json::parse_hex4(chars, pos)? & 0x3ff;
+                                code = 0x10000 + ((code - 0xd800) << 10) + low;
+                            }
+                            s.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                        }
+                        c => s.push(c),
+                    }
+                }
+                c => s.push(c),
+            }
+            *pos += 1;
+        }
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<&Json> {
+        match *self {
+            Json::Object(ref members) => members.iter().find(|m| m.0 == key).map(|m| &m.1),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match *self {
+            Json::Str(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Json::Bool(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_array(&self) -> Option<&[Json]> {
+        match *self {
+            Json::Array(ref items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_usize(&self) -> Option<usize> {
+        match *self {
+            Json::Number(n) if n >= 0.0 => Some(n as usize),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn object(members: Vec<(&str, Json)>) -> Json {
+        Json::Object(members.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+    }
+
+    pub(crate) fn string(s: &str) -> Json {
+        Json::Str(s.to_string())
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::Str(ref s) => {
+                write!(f, "\"")?;
+                for c in s.chars() {
+                    match c {
+                        '"' => write!(f, "\\\"")?,
+                        '\\' => write!(f, "\\\\")?,
+                        '\n' => write!(f, "\\n")?,
+                        '\r' => write!(f, "\\r")?,
+                        '\t' => write!(f, "\\t")?,
+                        c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+                        c => write!(f, "{}", c)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            Json::Array(ref items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(ref members) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}:{}", Json::Str(key.clone()), value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+const BASE64_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut out = String::new();
+
+    for chunk in data.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    out
+}
+
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut n = 0u32;
+    let mut bits = 0;
+
+    for c in text.bytes().filter(|c| !c.is_ascii_whitespace() && *c != b'=') {
+        let value = BASE64_ALPHABET.iter().position(|&a| a == c)? as u32;
+        n = n << 6 | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((n >> bits) as u8);
+        }
+    }
+
+    Some(out)
+}
+
+/// Percent-encodes everything except unreserved URL characters.
+pub(crate) fn url_encode(data: &[u8]) -> String {
+    data.iter()
+        .map(|&b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            b => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+pub(crate) fn url_decode(text: &str) -> Option<Vec<u8>> {
+    let bytes = text.as_bytes();
+    let mut out = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = text.get((i + 1)..(i + 3))?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    Some(out)
+}
+
+fn hex_encode(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn hex_decode(text: &str) -> Option<Vec<u8>> {
+    let digits = text.chars().filter(|c| !c.is_whitespace()).collect::<Vec<char>>();
+    if digits.len() % 2 != 0 {
+        return None;
+    }
+
+    digits
+        .chunks(2)
+        .map(|pair| u8::from_str_radix(&pair.iter().collect::<String>(), 16).ok())
+        .collect()
+}
+
+fn push_indent(out: &mut String, indent: Option<usize>, depth: usize) {
+    if let Some(width) = indent {
+        out.push('\n');
+        out.extend(iter::repeat_n(' ', width * depth));
+    }
+}
+
+/// Re-indents valid JSON by `indent` spaces per level, or minifies it with `None`. Scalars
+/// are copied verbatim, so numbers keep their original spelling.
+fn format_json(text: &str, indent: Option<usize>) -> String {
+    let chars = text.chars().collect::<Vec<char>>();
+    let mut out = String::new();
+    let mut depth = 0;
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '"' => {
+                let start = i;
+                i += 1;
+                while i < chars.len() && chars[i] != '"' {
+                    i += if chars[i] == '\\' { 2 } else { 1 };
+                }
+                out.extend(&chars[start..(i + 1).min(chars.len())]);
+            }
+            c @ ('{' | '[') => {
+                out.push(c);
+                let next = (i + 1..chars.len()).find(|&j| !chars[j].is_whitespace());
+                if next.is_some_and(|j| chars[j] == '}' || chars[j] == ']') {
+                    i = next.unwrap_or(i);
+                    out.push(chars[i]);
+                } else {
+                    depth += 1;
+                    push_indent(&mut out, indent, depth);
+                }
+            }
+            c @ ('}' | ']') => {
+                depth -= 1;
+                push_indent(&mut out, indent, depth);
+                out.push(c);
+            }
+            ',' => {
+                out.push(',');
+                push_indent(&mut out, indent, depth);
+            }
+            ':' => out.push_str(if indent.is_some() { ": " } else { ":" }),
+            c if c.is_whitespace() => {}
+            c => out.push(c),
+        }
+        i += 1;
+    }
+
+    if indent.is_some() {
+        out.push('\n');
+    }
+    out
+}
+
+#[derive(PartialEq)]
+enum XmlToken {
+    Open,
+    Close,
+    /// Self-closing tags, comments, CDATA sections, processing instructions and doctypes.
+    Empty,
+    Text,
+}
+
+/// Splits XML into tokens and their source text, checking that tags are closed
+/// and balanced. Errors carry the byte offset and a message.
+fn xml_tokens(text: &str) -> Result<Vec<(XmlToken, &str)>, (usize, String)> {
+    let mut tokens = Vec::new();
+    let mut open = Vec::new();
+    let mut i = 0;
+
+    while i < text.len() {
+        let rest = &text[i..];
+        if !rest.starts_with('<') {
+            let end = rest.find('<').map_or(text.len(), |n| i + n);
+            tokens.push((XmlToken::Text, &text[i..end]));
+            i = end;
+            continue;
+        }
+
+        let terminator = match () {
+            _ if rest.starts_with("<!--") => "-->",
+            _ if rest.starts_with("<![CDATA[") => "]]>",
+            _ if rest.starts_with("<?") => "?>",
+            _ => ">",
+        };
+        let mut quote = None;
+        let end = if terminator == ">" {
+            rest.char_indices().find(|&(_, c)| {
+                match quote {
+                    Some(q) if c == q => quote = None,
+                    None if c == '"' || c == '\'' => quote = Some(c),
+                    None => return c == '>',
+                    _ => {}
+                }
+                false
+            })
+        } else {
+            rest.find(terminator).map(|n| (n + terminator.len() - 1, '>'))
+        };
+        let end = match end {
+            Some((n, _)) => i + n + 1,
+            None => return Err((i, "unterminated tag".to_string())),
+        };
+
+        let tag = &text[i..end];
+        let name = |tag: &str| {
+            let inner = tag.trim_start_matches(['<', '/']);
+            let end = inner.find(|c: char| c.is_whitespace() || c == '/' || c == '>');
+            inner[..end.unwrap_or(inner.len())].to_string()
+        };
+        let token = if terminator != ">" || tag.starts_with("<!") || tag.ends_with("/>") {
+            XmlToken::Empty
+        } else if tag.starts_with("</") {
+            let name = name(tag);
+            match open.pop() {
+                Some((ref expected, _)) if *expected == name => {}
+                Some((expected, _)) => {
+                    return Err((i, format!("expected </{}>, found </{}>", expected, name)))
+                }
+                None => return Err((i, format!("unexpected </{}>", name))),
+            }
+            XmlToken::Close
+        } else {
+            open.push((name(tag), i));
+            XmlToken::Open
+        };
+
+        tokens.push((token, tag));
+        i = end;
+    }
+
+    match open.pop() {
+        Some((name, offset)) => Err((offset, format!("unclosed <{}>", name))),
+        None => Ok(tokens),
+    }
+}
+
+/// Puts each tag on its own line indented by `indent` spaces per level, keeping elements
+/// holding only text on one line, or minifies with `None` by dropping blank text.
+fn format_xml(tokens: &[(XmlToken, &str)], indent: Option<usize>) -> String {
+    let width = match indent {
+        Some(width) => width,
+        None => {
+            return tokens
+                .iter()
+                .filter(|t| !t.1.trim().is_empty())
+                .map(|t| if t.0 == XmlToken::Text { t.1 } else { t.1.trim() })
+                .collect();
+        }
+    };
+
+    let mut out = String::new();
+    let mut depth = 0;
+    let mut i = 0;
+
+    while i < tokens.len() {
+        let (ref token, source) = tokens[i];
+        i += 1;
+
+        if source.trim().is_empty() {
+            continue;
+        }
+        if let XmlToken::Close = *token {
+            depth -= 1;
+        }
+        out.extend(iter::repeat_n(' ', width * depth));
+        out.push_str(source.trim());
+
+        if let XmlToken::Open = *token {
+            match (tokens.get(i), tokens.get(i + 1)) {
+                (Some(&(XmlToken::Close, close)), _) => {
+                    out.push_str(close);
+                    i += 1;
+                }
+                (Some(&(XmlToken::Text, inner)), Some(&(XmlToken::Close, close))) => {
+                    out.push_str(inner.trim());
+                    out.push_str(close);
+                    i += 2;
+                }
+                _ => depth += 1,
+            }
+        }
+        out.push('\n');
+    }
+
+    out
+}
+
+/// The 1-based line and column of byte `offset` in `text`.
+fn line_col(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset];
+    let line = before.matches('\n').count() + 1;
+    let col = before.rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1;
+
+    (line, col)
+}
+
+/// Finds the first number ending after column `cx`, returning its span (including a leading
+/// `-` or `0x`) and whether it is hexadecimal.
+pub(crate) fn find_number(chars: &[char], cx: usize) -> Option<(usize, usize, bool)> {
+    let mut i = 0;
+
+    while i < chars.len() {
+        let hex = chars[i] == '0'
+            && chars.get(i + 1).is_some_and(|&c| c == 'x' || c == 'X')
+            && chars.get(i + 2).is_some_and(|c| c.is_ascii_hexdigit());
+        let end = if hex {
+            (i + 2..chars.len()).find(|&j| !chars[j].is_ascii_hexdigit())
+        } else if chars[i].is_ascii_digit() {
+            (i..chars.len()).find(|&j| !chars[j].is_ascii_digit())
+        } else {
+            i += 1;
+            continue;
+        };
+        let end = end.unwrap_or(chars.len());
+
+        if end > cx {
+            let negative = !hex && i > 0 && chars[i - 1] == '-';
+            let start = if negative { i - 1 } else { i };
+            return Some((start, end, hex));
+        }
+        i = end;
+    }
+
+    None
+}
+
+/// Adds `delta` to a number found by `find_number`, keeping the width of zero-padded and
+/// hexadecimal numbers and the case of hex digits.
+pub(crate) fn add_to_number(number: &str, hex: bool, delta: i64) -> Option<String> {
+    if hex {
+        let digits = &number[2..];
+        let value = u64::from_str_radix(digits, 16).ok()?.wrapping_add(delta as u64);
+        let width = digits.len();
+        return Some(if digits.chars().any(|c| c.is_ascii_uppercase()) {
+            format!("{}{:0width$X}", &number[..2], value, width = width)
+        } else {
+            format!("{}{:0width$x}", &number[..2], value, width = width)
+        });
+    }
+
+    let value = number.parse::<i64>().ok()?.checked_add(delta)?;
+    let digits = number.trim_start_matches('-');
+
+    if digits.len() > 1 && digits.starts_with('0') {
+        let sign = if value < 0 { "-" } else { "" };
+        Some(format!("{}{:0width$}", sign, value.unsigned_abs(), width = digits.len()))
+    } else {
+        Some(value.to_string())
+    }
+}
+
+/// Pads the fields of `lines` separated by `delimiter` into columns; lines without the
+/// delimiter are left as they are.
+pub(crate) fn align_lines(lines: &[String], delimiter: &str) -> Vec<String> {
+    let fields = lines
+        .iter()
+        .map(|line| {
+            let mut fields = line.split(delimiter).collect::<Vec<&str>>();
+            for (i, field) in fields.iter_mut().enumerate() {
+                // A blank first field is the indentation of a line starting with the delimiter.
+                if i > 0 || !field.trim().is_empty() {
+                    *field = if i == 0 { field.trim_end() } else { field.trim() };
+                }
+            }
+            fields
+        })
+        .collect::<Vec<Vec<&str>>>();
+
+    let mut widths = Vec::new();
+    for line in &fields {
+        for (i, field) in line.iter().enumerate().take(line.len() - 1) {
+            if widths.len() <= i {
+                widths.push(0);
+            }
+            widths[i] = widths[i].max(field.chars().count());
+        }
+    }
+
+    lines
+        .iter()
+        .zip(&fields)
+        .map(|(line, fields)| {
+            if fields.len() < 2 {
+                return line.clone();
+            }
+
+            let mut aligned = String::new();
+            for (i, field) in fields.iter().enumerate() {
+                aligned.push_str(field);
+                if i + 1 < fields.len() {
+                    let padding = widths[i] - field.chars().count();
+                    aligned.extend(iter::repeat_n(' ', padding));
+                    if i > 0 || !field.trim().is_empty() {
+                        aligned.push(' ');
+                    }
+                    aligned.push_str(delimiter);
+                    aligned.push(' ');
+                }
+            }
+            aligned.trim_end().to_string()
+        })
+        .collect()
+}
+
+/// Comments out `lines` with `prefix` and a space at their common indentation, or removes
+/// the prefix (and a space after it) if every line already has it. Blank lines are left
+/// alone either way.
+pub(crate) fn toggle_comment_lines(lines: &[String], prefix: &str) -> Vec<String> {
+    let indent = |line: &String| line.chars().take_while(|c| c.is_whitespace()).count();
+    let text = lines.iter().filter(|line| !line.trim().is_empty());
+    let commented = text.clone().all(|line| line.trim_start().starts_with(prefix));
+    let common = text.map(indent).min().unwrap_or(0);
+
+    lines
+        .iter()
+        .map(|line| {
+            if line.trim().is_empty() {
+                return line.clone();
+            }
+            let at = if commented { indent(line) } else { common };
+            let (head, rest) = line.split_at(line.char_indices().nth(at).map_or(0, |(i, _)| i));
+            match commented {
+                true => {
+                    let rest = &rest[prefix.len()..];
+                    format!("{}{}", head, rest.strip_prefix(' ').unwrap_or(rest))
+                }
+                false => format!("{}{} {}", head, prefix, rest),
+            }
+        })
+        .collect()
+}
+
+/// The indentation and comment leader (`// `, `# `, ` * `, `> `...) starting a line.
+pub(crate) fn line_leader(line: &str) -> &str {
+    let end = line
+        .char_indices()
+        .find(|&(_, c)| !(c.is_whitespace() || "/#*>;-!%".contains(c)))
+        .map_or(line.len(), |(i, _)| i);
+    &line[..end]
+}
+
+/// Re-wraps the words of `lines` to `width` columns, starting each line with `leader`.
+pub(crate) fn reflow_lines(lines: &[String], leader: &str, width: usize) -> Vec<String> {
+    let words = lines.iter().flat_map(|line| line[line_leader(line).len()..].split_whitespace());
+
+    let mut wrapped = Vec::new();
+    let mut line = String::new();
+
+    for word in words {
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+            wrapped.push(line);
+            line = String::new();
+        }
+        if line.is_empty() {
+            line.push_str(leader);
+        } else {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        wrapped.push(line);
+    }
+
+    wrapped
+}
+
+/// `text` with each tab replaced by the spaces up to the next multiple of `width` columns,
+/// for text that starts at column `col`.
+pub(crate) fn expand_tabs(text: &str, col: usize, width: usize) -> String {
+    let mut expanded = String::new();
+    for c in text.chars() {
+        match c {
+            '\t' => {
+                let spaces = width - (col + expanded.chars().count()) % width;
+                expanded.extend(iter::repeat_n(' ', spaces));
+            }
+            c => expanded.push(c),
+        }
+    }
+    expanded
+}
+
+/// Whether a line's indentation has a space before a tab, or isn't in the file's dominant
+/// `style`. Spaces after leading tabs are allowed for alignment.
+pub(crate) fn mixed_indent(line: &str, style: Option<char>) -> bool {
+    let leading = &line[..(line.len() - line.trim_start().len())];
+    if leading.len() == line.len() {
+        return false;
+    }
+
+    leading.contains(" \t")
+        || match style {
+            Some('\t') => leading.starts_with(' '),
+            Some(_) => leading.contains('\t'),
+            None => false,
+        }
+}
+
+impl Kilo {
+    /// Encodes or decodes the selection in place as base64, URL percent-encoding or hex.
+    pub(crate) fn editor_transform_selection(&mut self) -> io::Result<()> {
+        let (first, last) = match self.editor_selection() {
+            Some(selection) if !self.block && selection.0.cy < self.buffer.rows.len() => selection,
+            _ => {
+                self.editor_set_status_message("No selection");
+                return Ok(());
+            }
+        };
+
+        let prompt = "Encode: (b)ase64 (u)rl (h)ex, uppercase to decode";
+        let mode = match self.editor_read_mark_name(prompt)? {
+            Some(mode) if "buhBUH".contains(mode) => mode,
+            _ => return Ok(()),
+        };
+
+        let text = self.editor_text_range(first, last);
+        let decoded = match mode {
+            'b' => Some(base64_encode(&encode_text(&text)).into_bytes()),
+            'u' => Some(url_encode(&encode_text(&text)).into_bytes()),
+            'h' => Some(hex_encode(&encode_text(&text)).into_bytes()),
+            'B' => base64_decode(&text),
+            'U' => url_decode(&text),
+            _ => hex_decode(&text),
+        };
+
+        // Decoded bytes that aren't text are shown as placeholders, as in a file.
+        match decoded {
+            Some(result) => {
+                self.editor_delete_selection(first, last);
+                self.editor_insert_str(&decode_bytes(&result));
+            }
+            None => self.editor_set_status_message("Selection isn't validly encoded"),
+        }
+
+        Ok(())
+    }
+
+    /// Pretty-prints the buffer as JSON or XML with `format_indent` spaces per level, or
+    /// minifies it. The format comes from the file extension or filetype, falling back to
+    /// the first non-blank character.
+    pub(crate) fn editor_format(&mut self, minify: bool) {
+        let text = self.editor_rows_to_string();
+        let filetype = self.editor_syntax_filetype().map(|ft| ft.to_lowercase());
+        let xml = match filetype.as_deref() {
+            Some("json") => false,
+            Some("xml" | "svg" | "xhtml" | "xsd" | "xsl" | "plist") => true,
+            _ => match text.trim_start().chars().next() {
+                Some('{' | '[') => false,
+                Some('<') => true,
+                _ => {
+                    self.editor_set_status_message("Not a JSON or XML buffer");
+                    return;
+                }
+            },
+        };
+        let indent = if minify { None } else { Some(self.config.format_indent) };
+
+        let result = if xml {
+            xml_tokens(&text).map(|tokens| format_xml(&tokens, indent))
+        } else {
+            match Json::error_offset(&text) {
+                Some(offset) => {
+                    let at = text.char_indices().nth(offset).map_or(text.len(), |(i, _)| i);
+                    Err((at, "invalid JSON".to_string()))
+                }
+                None => Ok(format_json(&text, indent)),
+            }
+        };
+
+        match result {
+            Ok(formatted) => {
+                self.editor_set_contents(&formatted);
+                let kind = if xml { "XML" } else { "JSON" };
+                let done = if minify { "Minified" } else { "Formatted" };
+                self.editor_set_status_message(&format!("{} {}", done, kind));
+            }
+            Err((offset, message)) => {
+                let (line, col) = line_col(&text, offset);
+                let message =
+                    format!("Parse error at line {}, column {}: {}", line, col, message);
+                self.editor_set_status_message_with(Severity::Error, &message);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reparse(s: &str) -> Option<String> {
+        Json::parse(s).map(|json| json.to_string())
+    }
+
+    #[test]
+    fn json_values() {
+        assert_eq!(reparse(" null "), Some("null".to_string()));
+        assert_eq!(reparse("[true, false, -1.5e2]"), Some("[true,false,-150]".to_string()));
+        let json = r#"{"a": {"b": []}, "c": {}}"#;
+        assert_eq!(reparse(json), Some(r#"{"a":{"b":[]},"c":{}}"#.to_string()));
+
+        let json = Json::parse(r#"{"name": "x", "line": 3, "ok": true}"#).unwrap();
+        assert_eq!(json.get("name").and_then(Json::as_str), Some("x"));
+        assert_eq!(json.get("line").and_then(Json::as_usize), Some(3));
+        assert_eq!(json.get("ok").and_then(Json::as_bool), Some(true));
+        assert!(json.get("missing").is_none());
+    }
+
+    #[test]
+    fn json_string_escapes() {
+        let json = Json::parse(r#""a\"b\\c\né😀""#).unwrap();
+        assert_eq!(json.as_str(), Some("a\"b\\c\né😀"));
+        assert_eq!(Json::string("tab\there\u{1}").to_string(), r#""tab\there\u0001""#);
+    }
+
+    #[test]
+    fn json_errors() {
+        assert!(Json::parse("").is_none());
+        assert!(Json::parse("[1, 2").is_none());
+        assert!(Json::parse(r#"{"a" 1}"#).is_none());
+        assert!(Json::parse("nul").is_none());
+        assert!(Json::parse("[] []").is_none());
+
+        assert_eq!(Json::error_offset("[1, 2]"), None);
+        assert_eq!(Json::error_offset("[1, x]"), Some(4));
+        assert_eq!(Json::error_offset("{} x"), Some(3));
+    }
+
+    #[test]
+    fn json_formatting() {
+        let pretty = "{\n  \"a\": [\n    1,\n    2\n  ],\n  \"b\": {}\n}\n";
+        assert_eq!(format_json(r#"{"a":[1,2],"b":{}}"#, Some(2)), pretty);
+        assert_eq!(format_json("{ \"a, b\" : [ 1 ] }", None), r#"{"a, b":[1]}"#);
+    }
+
+    fn kinds(text: &str) -> Result<Vec<(&'static str, &str)>, (usize, String)> {
+        let tokens = xml_tokens(text)?;
+        let kind = |token: &XmlToken| match *token {
+            XmlToken::Open => "open",
+            XmlToken::Close => "close",
+            XmlToken::Empty => "empty",
+            XmlToken::Text => "text",
+        };
+        Ok(tokens.iter().map(|&(ref token, text)| (kind(token), text)).collect())
+    }
+
+    #[test]
+    fn xml_tokenizing() {
+        assert_eq!(
+            kinds("<?xml?><a x=\"1>2\">hi<br/><!-- <b> --></a>"),
+            Ok(vec![
+                ("empty", "<?xml?>"),
+                ("open", "<a x=\"1>2\">"),
+                ("text", "hi"),
+                ("empty", "<br/>"),
+                ("empty", "<!-- <b> -->"),
+                ("close", "</a>"),
+            ])
+        );
+        assert_eq!(kinds(""), Ok(vec![]));
+    }
+
+    #[test]
+    fn xml_errors() {
+        assert_eq!(kinds("<a><b></a>"), Err((6, "expected </b>, found </a>".to_string())));
+        assert_eq!(kinds("</a>"), Err((0, "unexpected </a>".to_string())));
+        assert_eq!(kinds("x<a>"), Err((1, "unclosed <a>".to_string())));
+        assert_eq!(kinds("<a"), Err((0, "unterminated tag".to_string())));
+    }
+
+    #[test]
+    fn base64() {
+        let cases = [("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v")];
+        for (data, text) in cases {
+            assert_eq!(base64_encode(data.as_bytes()), text);
+            assert_eq!(base64_decode(text), Some(data.as_bytes().to_vec()));
+        }
+        assert_eq!(base64_decode("Zm9v\nYmFy"), Some(b"foobar".to_vec()));
+        assert_eq!(base64_decode("Zm9v!"), None);
+    }
+
+    #[test]
+    fn url() {
+        assert_eq!(url_encode("a b/c~é".as_bytes()), "a%20b%2Fc~%C3%A9");
+        assert_eq!(url_decode("a%20b%2fc"), Some(b"a b/c".to_vec()));
+        assert_eq!(url_decode("100%"), None);
+        assert_eq!(url_decode("%zz"), None);
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!(hex_encode(b"\x00\xffA"), "00ff41");
+        assert_eq!(hex_decode("00 ff\n41"), Some(b"\x00\xffA".to_vec()));
+        assert_eq!(hex_decode("abc"), None);
+        assert_eq!(hex_decode("zz"), None);
+    }
+
+    fn strings(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn find_numbers() {
+        let chars = "a -12 b 0x1F".chars().collect::<Vec<char>>();
+        assert_eq!(find_number(&chars, 0), Some((2, 5, false)));
+        assert_eq!(find_number(&chars, 4), Some((2, 5, false)));
+        assert_eq!(find_number(&chars, 5), Some((8, 12, true)));
+        assert_eq!(find_number(&chars, 12), None);
+
+        let chars = "v10-x0x".chars().collect::<Vec<char>>();
+        assert_eq!(find_number(&chars, 0), Some((1, 3, false)));
+        assert_eq!(find_number(&chars, 3), Some((5, 6, false)));
+    }
+
+    #[test]
+    fn add_to_numbers() {
+        assert_eq!(add_to_number("41", false, 1).as_deref(), Some("42"));
+        assert_eq!(add_to_number("9", false, -10).as_deref(), Some("-1"));
+        assert_eq!(add_to_number("007", false, 1).as_deref(), Some("008"));
+        assert_eq!(add_to_number("-01", false, 2).as_deref(), Some("01"));
+        assert_eq!(add_to_number("0x0f", true, 1).as_deref(), Some("0x10"));
+        assert_eq!(add_to_number("0xFF", true, 1).as_deref(), Some("0x100"));
+    }
+
+    #[test]
+    fn align() {
+        let lines = strings(&["a = 1", "bcd=22", "no delimiter"]);
+        assert_eq!(align_lines(&lines, "="), ["a   = 1", "bcd = 22", "no delimiter"]);
+
+        let lines = strings(&["  | x | y", "  | long | z"]);
+        assert_eq!(align_lines(&lines, "|"), ["  | x    | y", "  | long | z"]);
+    }
+
+    #[test]
+    fn reflow() {
+        let lines = strings(&["// aaa bbb", "//   ccc ddd eee"]);
+        assert_eq!(reflow_lines(&lines, "// ", 12), ["// aaa bbb", "// ccc ddd", "// eee"]);
+        assert_eq!(reflow_lines(&strings(&["abcdefghij x"]), "", 4), ["abcdefghij", "x"]);
+        assert!(reflow_lines(&strings(&["", "  "]), "", 4).is_empty());
+    }
+}
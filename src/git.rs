@@ -0,0 +1,642 @@
+//! Git and diffs: the branch and HEAD contents shown in the status bar and gutter, blame,
+//! merge conflicts and the side-by-side comparison of two files.
+
+use super::*;
+
+pub(crate) struct GitStatus {
+    pub(crate) branch: String,
+    pub(crate) dirty: bool,
+    /// Contents of the file at HEAD, if it is tracked.
+    head: Option<Vec<String>>,
+}
+
+fn git_output(dir: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    Some(String::from_utf8_lossy(&output.stdout).trim_end().to_string())
+}
+
+/// Formats a Unix timestamp as a `YYYY-MM-DD` date (UTC).
+pub(crate) fn format_date(secs: i64) -> String {
+    // Civil-from-days conversion, counting eras of 400 years from 0000-03-01.
+    let days = secs.div_euclid(86_400) + 719_468;
+    let era = days.div_euclid(146_097);
+    let doe = days - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Summarises `git blame --porcelain` output for a single line.
+fn parse_blame(output: &str) -> Option<String> {
+    let mut lines = output.lines();
+    let hash = lines.next()?.split_whitespace().next()?;
+
+    if hash.chars().all(|c| c == '0') {
+        return Some("Not committed yet".to_string());
+    }
+
+    let (mut author, mut time, mut summary) = ("", 0, "");
+    for line in lines {
+        if let Some(rest) = line.strip_prefix("author ") {
+            author = rest;
+        } else if let Some(rest) = line.strip_prefix("author-time ") {
+            time = rest.parse().unwrap_or(0);
+        } else if let Some(rest) = line.strip_prefix("summary ") {
+            summary = rest;
+        }
+    }
+
+    Some(format!("{:.8} {} {} {}", hash, author, format_date(time), summary))
+}
+
+impl GitStatus {
+    fn query(dir: &Path, name: &str) -> Option<GitStatus> {
+        let mut branch = git_output(dir, &["rev-parse", "--abbrev-ref", "HEAD"])?;
+        if branch.trim() == "HEAD" {
+            branch = git_output(dir, &["rev-parse", "--short", "HEAD"])?;
+        }
+        branch.truncate(branch.trim_end().len());
+
+        let changes = git_output(dir, &["status", "--porcelain", "--untracked-files=no"])?;
+
+        let head = git_output(dir, &["show", &format!("HEAD:./{}", name)])
+            .map(|contents| contents.lines().map(String::from).collect());
+
+        Some(GitStatus {
+            branch,
+            dirty: !changes.trim().is_empty(),
+            head,
+        })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum LineChange {
+    Added,
+    Modified,
+    /// Lines were deleted just before this one.
+    Deleted,
+}
+
+impl LineChange {
+    pub(crate) fn marker(self) -> &'static str {
+        match self {
+            LineChange::Added => "\x1b[32m+\x1b[m",
+            LineChange::Modified => "\x1b[34m~\x1b[m",
+            LineChange::Deleted => "\x1b[31m-\x1b[m",
+        }
+    }
+}
+
+/// Pairs of `(old, new)` line indices left unchanged by the longest common subsequence.
+fn diff_matches<A: AsRef<str>, B: AsRef<str>>(old: &[A], new: &[B]) -> Vec<(usize, usize)> {
+    let same = |i: usize, j: usize| old[i].as_ref() == new[j].as_ref();
+
+    let prefix = (0..old.len().min(new.len())).take_while(|&i| same(i, i)).count();
+    let suffix = (0..(old.len().min(new.len()) - prefix))
+        .take_while(|&k| same(old.len() - 1 - k, new.len() - 1 - k))
+        .count();
+    let (n, m) = (old.len() - prefix - suffix, new.len() - prefix - suffix);
+
+    // Longest common subsequence of the differing middle; large rewrites are treated as one hunk.
+    let mut lcs = vec![vec![0u32; m + 1]; if n * m <= KILO_DIFF_LIMIT { n + 1 } else { 0 }];
+    for i in (0..lcs.len().saturating_sub(1)).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if same(prefix + i, prefix + j) {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut matches = (0..prefix).map(|i| (i, i)).collect::<Vec<(usize, usize)>>();
+    let (mut i, mut j) = (0, 0);
+
+    while !lcs.is_empty() && i < n && j < m {
+        if same(prefix + i, prefix + j) {
+            matches.push((prefix + i, prefix + j));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+
+    matches.extend((0..suffix).map(|k| (prefix + n + k, prefix + m + k)));
+    matches
+}
+
+/// Compares `new` against `old` line by line, returning the change (if any) for each new line.
+fn diff_lines<A: AsRef<str>, B: AsRef<str>>(old: &[A], new: &[B]) -> Vec<Option<LineChange>> {
+    let mut changes = vec![None; new.len()];
+    let mut previous = (0, 0);
+
+    let mut matches = diff_matches(old, new);
+    matches.push((old.len(), new.len()));
+
+    for (i, j) in matches {
+        let (deleted, inserted) = (i - previous.0, j - previous.1);
+
+        for (k, change) in changes[previous.1..j].iter_mut().enumerate() {
+            *change = Some(if k < deleted {
+                LineChange::Modified
+            } else {
+                LineChange::Added
+            });
+        }
+
+        if deleted > inserted && !new.is_empty() {
+            let at = j.min(new.len() - 1);
+            if changes[at].is_none() {
+                changes[at] = Some(LineChange::Deleted);
+            }
+        }
+
+        previous = (i + 1, j + 1);
+    }
+
+    changes
+}
+
+/// Row indices of the markers delimiting a merge conflict.
+#[derive(Clone, Copy)]
+pub(crate) struct Conflict {
+    pub(crate) start: usize,
+    pub(crate) base: Option<usize>,
+    pub(crate) middle: usize,
+    pub(crate) end: usize,
+}
+
+pub(crate) fn find_conflicts(rows: &[Row]) -> Vec<Conflict> {
+    let mut conflicts = Vec::new();
+    let mut open: Option<(usize, Option<usize>, Option<usize>)> = None;
+
+    for (i, row) in rows.iter().enumerate() {
+        let line = &row.chars;
+
+        if line.starts_with("<<<<<<<") {
+            open = Some((i, None, None));
+        } else if let Some((start, base, middle)) = open {
+            if line.starts_with("|||||||") && middle.is_none() {
+                open = Some((start, Some(i), None));
+            } else if line.starts_with("=======") && middle.is_none() {
+                open = Some((start, base, Some(i)));
+            } else if line.starts_with(">>>>>>>") {
+                if let Some(middle) = middle {
+                    conflicts.push(Conflict { start, base, middle, end: i });
+                }
+                open = None;
+            }
+        }
+    }
+
+    conflicts
+}
+
+/// The differences between `old` and `new` as the hunks of a unified diff, each line
+/// starting with ` `, `-` or `+` and each hunk with an `@@ -start,len +start,len @@` header.
+/// Hunks show up to `context` unchanged lines around the changes; hunks whose context would
+/// overlap are merged.
+pub(crate) fn unified_diff<A, B>(old: &[A], new: &[B], context: usize) -> Vec<String>
+where
+    A: AsRef<str>,
+    B: AsRef<str>,
+{
+    let mut matches = diff_matches(old, new);
+    matches.push((old.len(), new.len()));
+
+    // Each change as the ranges of old and new lines it replaces, in order.
+    let mut changes = Vec::new();
+    let mut previous = (0, 0);
+    for (i, j) in matches {
+        if i > previous.0 || j > previous.1 {
+            changes.push((previous.0..i, previous.1..j));
+        }
+        previous = (i + 1, j + 1);
+    }
+
+    let mut lines = Vec::new();
+    let mut k = 0;
+    while k < changes.len() {
+        // Take in the following changes while the unchanged lines between them would be
+        // shown as context anyway.
+        let mut last = k;
+        while last + 1 < changes.len()
+            && changes[last + 1].0.start - changes[last].0.end <= 2 * context
+        {
+            last += 1;
+        }
+
+        let start = (
+            changes[k].0.start.saturating_sub(context),
+            changes[k].1.start.saturating_sub(context),
+        );
+        let end = (
+            cmp::min(changes[last].0.end + context, old.len()),
+            cmp::min(changes[last].1.end + context, new.len()),
+        );
+        // Empty ranges are numbered from the line before them, as diff does.
+        let number = |start: usize, len: usize| if len == 0 { start } else { start + 1 };
+        lines.push(format!(
+            "@@ -{},{} +{},{} @@",
+            number(start.0, end.0 - start.0),
+            end.0 - start.0,
+            number(start.1, end.1 - start.1),
+            end.1 - start.1
+        ));
+
+        let mut i = start.0;
+        for change in &changes[k..=last] {
+            for line in &old[i..change.0.start] {
+                lines.push(format!(" {}", line.as_ref()));
+            }
+            for line in &old[change.0.clone()] {
+                lines.push(format!("-{}", line.as_ref()));
+            }
+            for line in &new[change.1.clone()] {
+                lines.push(format!("+{}", line.as_ref()));
+            }
+            i = change.0.end;
+        }
+        for line in &old[i..end.0] {
+            lines.push(format!(" {}", line.as_ref()));
+        }
+
+        k = last + 1;
+    }
+
+    lines
+}
+
+/// A read-only view of a diff, scrolled to show the lines from `top` on.
+pub(crate) struct DiffView {
+    pub(crate) lines: Vec<String>,
+    pub(crate) top: usize,
+}
+
+/// The second file of a `--diff` comparison, shown in the right-hand pane.
+pub(crate) struct Comparison {
+    pub(crate) filename: String,
+    pub(crate) rows: Vec<Row>,
+    /// Screen lines pairing left and right rows; unmatched rows face a blank line.
+    pub(crate) lines: Vec<(Option<usize>, Option<usize>)>,
+    /// Index into `lines` of each left row, plus one past the end.
+    pub(crate) left_lines: Vec<usize>,
+    pub(crate) changed_left: Vec<bool>,
+    pub(crate) changed_right: Vec<bool>,
+}
+
+impl Comparison {
+    fn new(filename: &str, left: &[Row], rows: Vec<Row>) -> Comparison {
+        let left_text = left.iter().map(|r| r.chars.as_str()).collect::<Vec<&str>>();
+        let right_text = rows.iter().map(|r| r.chars.as_str()).collect::<Vec<&str>>();
+
+        let mut matches = diff_matches(&left_text, &right_text);
+        matches.push((left.len(), rows.len()));
+
+        let mut lines = Vec::new();
+        let mut left_lines = Vec::new();
+        let mut changed_left = vec![true; left.len()];
+        let mut changed_right = vec![true; rows.len()];
+        let mut previous = (0, 0);
+
+        for (i, j) in matches {
+            let (deleted, inserted) = (i - previous.0, j - previous.1);
+
+            for k in 0..deleted.max(inserted) {
+                if k < deleted {
+                    left_lines.push(lines.len());
+                }
+                lines.push((
+                    if k < deleted { Some(previous.0 + k) } else { None },
+                    if k < inserted { Some(previous.1 + k) } else { None },
+                ));
+            }
+
+            if i < left.len() {
+                changed_left[i] = false;
+                changed_right[j] = false;
+                left_lines.push(lines.len());
+                lines.push((Some(i), Some(j)));
+            }
+
+            previous = (i + 1, j + 1);
+        }
+        left_lines.push(lines.len());
+
+        Comparison {
+            filename: filename.to_string(),
+            rows,
+            lines,
+            left_lines,
+            changed_left,
+            changed_right,
+        }
+    }
+}
+
+/// What git says about the open file, refreshed in the background.
+pub(crate) struct GitState {
+    pub(crate) status: Option<GitStatus>,
+    pub(crate) query: Option<Receiver<Option<GitStatus>>>,
+    pub(crate) checked: Option<Instant>,
+    pub(crate) changes: Vec<Option<LineChange>>,
+    /// Whether the buffer was edited since `changes` was last worked out. Diffing against
+    /// HEAD is redone once typing pauses rather than on every keystroke.
+    pub(crate) changes_stale: bool,
+}
+
+impl Kilo {
+    pub(crate) fn editor_compare(&mut self, left: &str, right: &str) -> io::Result<()> {
+        self.editor_open(left)?;
+
+        let contents = decode_bytes(&read_file(right)?.0);
+        let name = uncompressed_name(right);
+        let syntax = self.highlight.syntaxes.iter().position(|s| s.matches(name));
+        let mut state = HighlightState::default();
+        let mut rows = Vec::new();
+
+        for line in split_lines(&contents).0 {
+            let mut row = Row::new(line);
+            self.editor_update_row(&mut row);
+
+            let render = row.render.chars().collect::<Vec<char>>();
+            row.hl = match syntax {
+                Some(i) => {
+                    let (hl, next) = highlight_line(&render, &self.highlight.syntaxes, i, state);
+                    state = next;
+                    hl
+                }
+                None => vec![Highlight::Normal; render.len()],
+            };
+            rows.push(row);
+        }
+
+        let comparison = Comparison::new(right, &self.buffer.rows, rows);
+
+        let differing = comparison.changed_left.iter().filter(|&&c| c).count()
+            + comparison.changed_right.iter().filter(|&&c| c).count();
+        self.editor_set_status_message(&format!(
+            "Comparing with {}: {} differing lines",
+            right, differing
+        ));
+        self.comparison = Some(comparison);
+
+        Ok(())
+    }
+
+    /// Shows the changes made to the buffer since the file was last saved, as a unified diff
+    /// that the arrow and page keys scroll and any other key closes.
+    pub(crate) fn editor_diff_saved(&mut self) -> io::Result<()> {
+        if self.filename.is_empty() || self.file.remote.is_some() {
+            self.editor_set_status_message("No saved file to compare with");
+            return Ok(());
+        }
+
+        let saved = match read_file(&self.filename) {
+            Ok((bytes, _)) => decode_bytes(&bytes),
+            Err(ref e) if e.kind() == ErrorKind::NotFound => String::new(),
+            Err(e) => {
+                let message = format!("Can't read {}: {}", self.filename, e);
+                self.editor_set_status_message_with(Severity::Error, &message);
+                return Ok(());
+            }
+        };
+        let old = split_lines(&saved).0;
+        let new = self.buffer.rows.iter().map(|row| row.chars.as_str()).collect::<Vec<&str>>();
+
+        let lines = unified_diff(&old, &new, KILO_DIFF_CONTEXT);
+        if lines.is_empty() {
+            let message = format!("No changes since {} was saved", self.filename);
+            self.editor_set_status_message(&message);
+            return Ok(());
+        }
+        let count = |prefix: char| lines.iter().filter(|line| line.starts_with(prefix)).count();
+        let message = format!(
+            "Changes since {} was saved: {} added, {} removed; any other key closes",
+            self.filename,
+            count('+'),
+            count('-')
+        );
+        self.diff_view = Some(DiffView { lines, top: 0 });
+
+        loop {
+            self.editor_set_status_message(&message);
+            self.editor_refresh_screen()?;
+
+            let key = self.editor_wait_key()?;
+            let page = self.screenrows.saturating_sub(1);
+            let view = match self.diff_view {
+                Some(ref mut view) => view,
+                None => break,
+            };
+            let bottom = view.lines.len().saturating_sub(self.screenrows);
+
+            view.top = match key {
+                ArrowUp => view.top.saturating_sub(1),
+                ArrowDown => view.top + 1,
+                PageUp => view.top.saturating_sub(page),
+                PageDown => view.top + page,
+                HomeKey => 0,
+                EndKey => bottom,
+                _ => break,
+            };
+            view.top = cmp::min(view.top, bottom);
+        }
+
+        self.diff_view = None;
+        self.editor_set_status_message("");
+        Ok(())
+    }
+
+    pub(crate) fn editor_refresh_git(&mut self) {
+        if let Some(ref query) = self.git.query {
+            match query.try_recv() {
+                Ok(status) => {
+                    self.git.status = status;
+                    self.editor_update_git_changes();
+                }
+                Err(mpsc::TryRecvError::Empty) => return,
+                Err(mpsc::TryRecvError::Disconnected) => {}
+            }
+            self.git.query = None;
+        }
+
+        let fresh = self.git.checked.is_some_and(|t| t.elapsed() < GIT_REFRESH_INTERVAL);
+        if self.filename.is_empty() || fresh {
+            return;
+        }
+        self.git.checked = Some(Instant::now());
+
+        // Querying git can take a while in large repositories, so it runs off the input loop.
+        let path = Path::new(&self.filename);
+        let dir = match path.parent() {
+            Some(dir) if dir != Path::new("") => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let name = path.file_name().map_or(String::new(), |n| n.to_string_lossy().into_owned());
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = sender.send(GitStatus::query(&dir, &name));
+        });
+        self.git.query = Some(receiver);
+    }
+
+    pub(crate) fn editor_update_git_changes(&mut self) {
+        self.git.changes_stale = false;
+        self.git.changes = match self.git.status.as_ref().and_then(|git| git.head.as_ref()) {
+            Some(head) => {
+                let rows = self.buffer.rows.iter();
+                let lines = rows.map(|r| r.chars.as_str()).collect::<Vec<&str>>();
+                diff_lines(head, &lines)
+            }
+            None => Vec::new(),
+        };
+    }
+
+    pub(crate) fn editor_blame(&mut self) {
+        let path = Path::new(&self.filename);
+        let dir = match path.parent() {
+            Some(dir) if dir != Path::new("") => dir,
+            _ => Path::new("."),
+        };
+        let name = path.file_name().map_or(String::new(), |n| n.to_string_lossy().into_owned());
+        let range = format!("{},{}", self.cy + 1, self.cy + 1);
+
+        let blame = git_output(dir, &["blame", "--porcelain", "-L", &range, "--", &name]);
+        match blame.as_ref().and_then(|output| parse_blame(output)) {
+            Some(message) => self.editor_set_status_message(&message),
+            None => self.editor_set_status_message("No blame information for this line"),
+        }
+    }
+
+    pub(crate) fn editor_next_conflict(&mut self, forward: bool) {
+        let target = if forward {
+            self.conflicts.iter().find(|c| c.start > self.cy).or(self.conflicts.first())
+        } else {
+            let before = self.conflicts.iter().rev().find(|c| c.start < self.cy);
+            before.or(self.conflicts.last())
+        };
+
+        match target {
+            Some(&conflict) => self.editor_jump_to(Position { cx: 0, cy: conflict.start }),
+            None => self.editor_set_status_message("No merge conflicts"),
+        }
+    }
+
+    pub(crate) fn editor_resolve_conflict(&mut self, ours: bool, theirs: bool) {
+        let cy = self.cy;
+        let conflict = match self.conflicts.iter().find(|c| c.start <= cy && cy <= c.end) {
+            Some(&conflict) => conflict,
+            None => {
+                self.editor_set_status_message("Not inside a merge conflict");
+                return;
+            }
+        };
+
+        let ours_end = conflict.base.unwrap_or(conflict.middle);
+        let keep = |i: usize| {
+            (ours && i > conflict.start && i < ours_end)
+                || (theirs && i > conflict.middle && i < conflict.end)
+        };
+
+        for i in (conflict.start..(conflict.end + 1)).rev() {
+            if !keep(i) {
+                self.editor_del_row(i);
+            }
+        }
+
+        self.conflicts = find_conflicts(&self.buffer.rows);
+        self.editor_text_changed();
+        self.editor_move_to(Position { cx: 0, cy: conflict.start });
+    }
+
+    pub(crate) fn editor_next_hunk(&mut self, forward: bool) {
+        let starts = (0..self.git.changes.len())
+            .filter(|&i| {
+                self.git.changes[i].is_some() && (i == 0 || self.git.changes[i - 1].is_none())
+            })
+            .collect::<Vec<usize>>();
+
+        let target = if forward {
+            starts.iter().find(|&&i| i > self.cy).or(starts.first())
+        } else {
+            starts.iter().rev().find(|&&i| i < self.cy).or(starts.last())
+        };
+
+        match target {
+            Some(&cy) => self.editor_jump_to(Position { cx: 0, cy }),
+            None => self.editor_set_status_message("No changes"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The changes to `new` as one letter per line: `+` added, `~` modified, `-` after a
+    /// deletion and `.` unchanged.
+    fn changes(old: &[&str], new: &[&str]) -> String {
+        let letter = |change: Option<LineChange>| match change {
+            Some(LineChange::Added) => '+',
+            Some(LineChange::Modified) => '~',
+            Some(LineChange::Deleted) => '-',
+            None => '.',
+        };
+        diff_lines(old, new).into_iter().map(letter).collect()
+    }
+
+    #[test]
+    fn diff_lines_marks_changes() {
+        assert_eq!(changes(&["a", "b"], &["a", "b"]), "..");
+        assert_eq!(changes(&["a", "c"], &["a", "b", "c"]), ".+.");
+        assert_eq!(changes(&["a", "b", "c"], &["a", "x", "c"]), ".~.");
+        assert_eq!(changes(&["a", "b", "c"], &["a", "c"]), ".-");
+        assert_eq!(changes(&["a", "b"], &["a"]), "-");
+        assert_eq!(changes(&[], &["a", "b"]), "++");
+        assert_eq!(changes(&["a"], &[]), "");
+        assert_eq!(changes(&["a", "b", "c", "d"], &["a", "x", "y", "z", "d"]), ".~~+.");
+    }
+
+    #[test]
+    fn diff_matches_finds_the_longest_common_subsequence() {
+        let old = ["a", "b", "c", "d", "e"];
+        let new = ["x", "b", "d", "y", "e"];
+        assert_eq!(diff_matches(&old, &new), [(1, 1), (3, 2), (4, 4)]);
+    }
+
+    #[test]
+    fn unified_diff_joins_nearby_changes_into_hunks() {
+        let old = ["a", "b", "c", "d", "e", "f", "g", "h"];
+        let new = ["a", "x", "c", "d", "e", "f", "g"];
+        assert!(unified_diff(&old, &old, 3).is_empty());
+        assert_eq!(
+            unified_diff(&old, &new, 1),
+            ["@@ -1,3 +1,3 @@", " a", "-b", "+x", " c", "@@ -7,2 +7,1 @@", " g", "-h"]
+        );
+        assert_eq!(unified_diff(&old, &new, 3)[0], "@@ -1,8 +1,7 @@");
+        assert_eq!(unified_diff(&old, &new, 3).len(), 10);
+        assert_eq!(unified_diff(&[] as &[&str], &["a"], 3), ["@@ -0,0 +1,1 @@", "+a"]);
+    }
+}
@@ -0,0 +1,960 @@
+//! Key decoding, prompts, pickers and keypress dispatch.
+
+use super::*;
+
+#[inline]
+pub(crate) fn ctrl_key(k: char) -> u8 {
+    (k as u8) & 0x1f
+}
+
+pub(crate) fn fuzzy_match(query: &str, candidate: &str) -> bool {
+    let mut candidate = candidate.chars().flat_map(|c| c.to_lowercase());
+
+    query
+        .chars()
+        .flat_map(|c| c.to_lowercase())
+        .all(|q| candidate.any(|c| c == q))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EditorKey {
+    ArrowLeft,
+    ArrowRight,
+    ArrowUp,
+    ArrowDown,
+    InsertKey,
+    DelKey,
+    HomeKey,
+    EndKey,
+    PageUp,
+    PageDown,
+    Alt(u8),
+    Char(u8),
+    /// A character that isn't ASCII, decoded from the UTF-8 the terminal sends for it.
+    Text(char),
+    /// The terminal window gained focus.
+    FocusIn,
+    /// The terminal window lost focus.
+    FocusOut,
+    /// Text pasted in one go, which `editor_read_key` keeps in `pasted`.
+    Paste,
+}
+
+pub(crate) struct Picker {
+    pub(crate) title: String,
+    pub(crate) items: Vec<String>,
+    pub(crate) query: String,
+    pub(crate) selected: usize,
+}
+
+impl Picker {
+    pub(crate) fn matches(&self) -> Vec<usize> {
+        (0..self.items.len())
+            .filter(|&i| fuzzy_match(&self.query, &self.items[i]))
+            .collect()
+    }
+}
+
+/// The answer to an `editor_confirm` question.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Answer {
+    Yes,
+    No,
+    All,
+    Cancel,
+}
+
+/// What a key did to a `Prompt`.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum PromptAction {
+    Continue,
+    Accept,
+    Cancel,
+}
+
+/// A line of input being edited in the message bar.
+pub(crate) struct Prompt {
+    pub(crate) input: Vec<char>,
+    pub(crate) cursor: usize,
+    /// Index of the history entry being shown, while browsing the history.
+    pub(crate) recalled: Option<usize>,
+    /// The input typed before browsing the history, restored when browsing past its end.
+    pub(crate) draft: Vec<char>,
+}
+
+impl Prompt {
+    pub(crate) fn new() -> Prompt {
+        Prompt {
+            input: Vec::new(),
+            cursor: 0,
+            recalled: None,
+            draft: Vec::new(),
+        }
+    }
+
+    pub(crate) fn text(&self) -> String {
+        self.input.iter().collect()
+    }
+
+    pub(crate) fn set_input(&mut self, input: Vec<char>) {
+        self.cursor = input.len();
+        self.input = input;
+    }
+
+    /// Applies `key` to the input; Up and Down recall entries from `history`, oldest first.
+    pub(crate) fn handle_key(&mut self, key: EditorKey, history: &[String]) -> PromptAction {
+        match key {
+            Char(b'\r') => return PromptAction::Accept,
+            Char(b'\x1b') => return PromptAction::Cancel,
+            Char(c) if (c == 127 || c == ctrl_key('h')) && self.cursor > 0 => {
+                self.cursor -= 1;
+                self.input.remove(self.cursor);
+            }
+            Char(c) if c == ctrl_key('d') && self.cursor < self.input.len() => {
+                self.input.remove(self.cursor);
+            }
+            DelKey if self.cursor < self.input.len() => {
+                self.input.remove(self.cursor);
+            }
+            Char(c) if c == ctrl_key('u') => self.set_input(Vec::new()),
+            Char(c) if c == ctrl_key('a') => self.cursor = 0,
+            Char(c) if c == ctrl_key('e') => self.cursor = self.input.len(),
+            HomeKey => self.cursor = 0,
+            EndKey => self.cursor = self.input.len(),
+            ArrowLeft => self.cursor = self.cursor.saturating_sub(1),
+            ArrowRight => self.cursor = (self.cursor + 1).min(self.input.len()),
+            ArrowUp => {
+                let older = match self.recalled {
+                    Some(i) => i.checked_sub(1),
+                    None => history.len().checked_sub(1),
+                };
+                if let Some(i) = older {
+                    if self.recalled.is_none() {
+                        self.draft = self.input.clone();
+                    }
+                    self.recalled = Some(i);
+                    self.set_input(history[i].chars().collect());
+                }
+            }
+            ArrowDown => match self.recalled {
+                Some(i) if i + 1 < history.len() => {
+                    self.recalled = Some(i + 1);
+                    self.set_input(history[i + 1].chars().collect());
+                }
+                Some(_) => {
+                    self.recalled = None;
+                    let draft = mem::take(&mut self.draft);
+                    self.set_input(draft);
+                }
+                None => {}
+            },
+            Char(c) if c.is_ascii() && !c.is_ascii_control() => {
+                self.input.insert(self.cursor, c as char);
+                self.cursor += 1;
+            }
+            Text(c) => {
+                self.input.insert(self.cursor, c);
+                self.cursor += 1;
+            }
+            _ => {}
+        }
+
+        PromptAction::Continue
+    }
+}
+
+/// Where `editor_read_key` is within an escape sequence.
+#[derive(Clone, Copy)]
+pub(crate) enum KeyState {
+    Ground,
+    Escape,
+    Csi,
+    Ss3,
+}
+
+/// Whether `key` runs a command that changes the text, which read-only buffers refuse.
+fn edits_text(key: EditorKey) -> bool {
+    let commands = [
+        ctrl_key('k'),
+        ctrl_key('y'),
+        ctrl_key('w'),
+        ctrl_key('a'),
+        ctrl_key('x'),
+        ctrl_key('t'),
+        ctrl_key('v'),
+        ctrl_key('h'),
+        ctrl_key('n'),
+        b'\t',
+        b'\r',
+        127,
+    ];
+    match key {
+        Char(c) => commands.contains(&c) || (b' '..=b'~').contains(&c),
+        Alt(c) => b"kyYISq;=fmeiotTa%".contains(&c),
+        Paste | DelKey | Text(_) => true,
+        _ => false,
+    }
+}
+
+/// Bytes as they'd be typed: `ESC` and `^X` for control characters, `\xNN` for bytes that
+/// aren't ASCII.
+pub(crate) fn describe_bytes(bytes: &[u8]) -> String {
+    let names: Vec<String> = bytes
+        .iter()
+        .map(|&b| match b {
+            b'\x1b' => "ESC".to_string(),
+            b' ' => "SPC".to_string(),
+            0x7f => "DEL".to_string(),
+            0..=0x1f => format!("^{}", (b + b'@') as char),
+            0x21..=0x7e => (b as char).to_string(),
+            _ => format!("\\x{:02x}", b),
+        })
+        .collect();
+    names.join(" ")
+}
+
+/// The key for a CSI sequence (`ESC [ params final`), ignoring modifier parameters.
+pub(crate) fn csi_key(params: &str, terminator: u8) -> Option<EditorKey> {
+    match terminator {
+        b'A' => Some(ArrowUp),
+        b'B' => Some(ArrowDown),
+        b'C' => Some(ArrowRight),
+        b'D' => Some(ArrowLeft),
+        b'H' => Some(HomeKey),
+        b'F' => Some(EndKey),
+        b'I' if params.is_empty() => Some(FocusIn),
+        b'O' if params.is_empty() => Some(FocusOut),
+        b'~' => match params.split(';').next() {
+            Some("1") | Some("7") => Some(HomeKey),
+            Some("2") => Some(InsertKey),
+            Some("3") => Some(DelKey),
+            Some("4") | Some("8") => Some(EndKey),
+            Some("5") => Some(PageUp),
+            Some("6") => Some(PageDown),
+            Some("200") => Some(Paste),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The key for an SS3 sequence (`ESC O final`), sent by keypads in application mode.
+pub(crate) fn ss3_key(terminator: u8) -> Option<EditorKey> {
+    match terminator {
+        b'A' => Some(ArrowUp),
+        b'B' => Some(ArrowDown),
+        b'C' => Some(ArrowRight),
+        b'D' => Some(ArrowLeft),
+        b'H' => Some(HomeKey),
+        b'F' => Some(EndKey),
+        _ => None,
+    }
+}
+
+/// The message bar prompt, between and during `editor_prompt` calls.
+pub(crate) struct PromptState {
+    /// Message bar column of the cursor while a prompt is open.
+    pub(crate) cursor: Option<usize>,
+    /// Shown after the input of an open prompt, for callbacks to report on it.
+    pub(crate) note: Option<String>,
+    pub(crate) history: HashMap<&'static str, Vec<String>>,
+}
+
+impl Kilo {
+    /// Reads one key, decoding escape sequences. A lone ESC is told apart from the start of a
+    /// sequence by whether more input follows within `KILO_ESCAPE_TIMEOUT`; unknown or
+    /// truncated sequences are dropped whole and yield `None`, and so do focus events, which
+    /// are recorded here so that every prompt and picker keeps track of them. The text of a
+    /// paste is read here too.
+    pub(crate) fn editor_read_key(&self) -> io::Result<Option<EditorKey>> {
+        let (key, received) = self.editor_read_sequence()?;
+        if received.is_empty() {
+            return Ok(None);
+        }
+
+        let received = String::from_utf8_lossy(&received);
+        match key {
+            Some(key) => self.editor_log(format_args!("key {:?} from {:?}", key, received)),
+            None => self.editor_log(format_args!("unrecognized sequence {:?}", received)),
+        }
+
+        match key {
+            Some(FocusIn) => {
+                self.terminal.focused.set(true);
+                self.terminal.refocused.set(true);
+                Ok(None)
+            }
+            Some(FocusOut) => {
+                self.terminal.focused.set(false);
+                Ok(None)
+            }
+            Some(Paste) => {
+                self.editor_read_paste()?;
+                Ok(Some(Paste))
+            }
+            key => Ok(key),
+        }
+    }
+
+    /// Reads pasted text up to the sequence ending the paste, with the carriage returns
+    /// terminals send for newlines turned back into newlines.
+    fn editor_read_paste(&self) -> io::Result<()> {
+        let mut pasted = Vec::new();
+
+        while !pasted.ends_with(b"\x1b[201~") {
+            match self.editor_read_byte()? {
+                Some(byte) => pasted.push(byte),
+                None if self.editor_input_ready(KILO_ESCAPE_TIMEOUT) => {}
+                None => break,
+            }
+        }
+        if pasted.ends_with(b"\x1b[201~") {
+            pasted.truncate(pasted.len() - 6);
+        }
+
+        let text = String::from_utf8_lossy(&pasted).replace("\r\n", "\n").replace('\r', "\n");
+        self.editor_log(format_args!("pasted {} bytes", text.len()));
+        *self.terminal.pasted.borrow_mut() = text;
+
+        Ok(())
+    }
+
+    /// Reads the bytes of one key press and decodes them. The bytes are empty if no input
+    /// arrived, and the key is `None` for sequences the parser doesn't recognize.
+    pub(crate) fn editor_read_sequence(&self) -> io::Result<(Option<EditorKey>, Vec<u8>)> {
+        let mut state = KeyState::Ground;
+        let mut params = String::new();
+        let mut received = Vec::new();
+
+        let key = loop {
+            let byte = match self.editor_read_byte()? {
+                Some(byte) => byte,
+                None => break None,
+            };
+            received.push(byte);
+
+            state = match (state, byte) {
+                (KeyState::Ground, b'\x1b') => {
+                    if !self.editor_input_ready(KILO_ESCAPE_TIMEOUT) {
+                        break Some(Char(byte));
+                    }
+                    KeyState::Escape
+                }
+                (KeyState::Ground, 0xc2..=0xf4) => break self.editor_read_utf8(&mut received)?,
+                (KeyState::Ground, _) => break Some(Char(byte)),
+                (KeyState::Escape, b'[') => KeyState::Csi,
+                (KeyState::Escape, b'O') => KeyState::Ss3,
+                (KeyState::Escape, _) => break Some(Alt(byte)),
+                (KeyState::Csi, 0x20..=0x3f) => {
+                    params.push(byte as char);
+                    KeyState::Csi
+                }
+                (KeyState::Csi, 0x40..=0x7e) => break csi_key(&params, byte),
+                (KeyState::Csi, _) => break None,
+                (KeyState::Ss3, _) => break ss3_key(byte),
+            };
+        };
+
+        Ok((key, received))
+    }
+
+    /// Reads the rest of the UTF-8 character `received` starts, returning it as a key. A byte
+    /// that can't continue it is left to be read as the next key.
+    fn editor_read_utf8(&self, received: &mut Vec<u8>) -> io::Result<Option<EditorKey>> {
+        let len = match received[0] {
+            0xf0.. => 4,
+            0xe0.. => 3,
+            _ => 2,
+        };
+
+        while received.len() < len {
+            match self.editor_read_byte()? {
+                Some(byte @ 0x80..=0xbf) => received.push(byte),
+                Some(byte) => {
+                    self.terminal.input.borrow_mut().push_front(byte);
+                    break;
+                }
+                None => break,
+            }
+        }
+
+        let text = std::str::from_utf8(received).ok();
+        Ok(text.and_then(|text| text.chars().next()).map(Text))
+    }
+
+    /// Shows the bytes and decoded key of every key press in the message bar until Ctrl-Q.
+    pub(crate) fn editor_show_keys(&mut self) -> io::Result<()> {
+        self.editor_set_status_message("Press keys to see what the terminal sends; Ctrl-Q quits");
+
+        loop {
+            self.editor_refresh_screen()?;
+
+            let (key, received) = self.editor_read_sequence()?;
+            if received.is_empty() {
+                continue;
+            }
+            if key == Some(Char(ctrl_key('q'))) {
+                return Ok(());
+            }
+
+            let hex: Vec<String> = received.iter().map(|b| format!("{:02x}", b)).collect();
+            let decoded = match key {
+                Some(key) => format!("{:?}", key),
+                None => "unrecognized".to_string(),
+            };
+            let bytes = describe_bytes(&received);
+            self.editor_set_status_message(&format!("{} ({}) = {}", bytes, hex.join(" "), decoded));
+        }
+    }
+
+    /// Reads a line of input in the message bar, where `{}` in `prompt` stands for it.
+    /// Accepted input is added to the named `history`, which Up and Down browse.
+    pub(crate) fn editor_prompt(
+        &mut self,
+        prompt: &str,
+        history: &'static str,
+    ) -> io::Result<Option<String>> {
+        self.editor_prompt_with(prompt, history, |_, _, _| {})
+    }
+
+    /// Like `editor_prompt`, calling `callback` with the input after every key so callers can
+    /// react as it is typed. Returns `None` if the prompt is cancelled with Escape.
+    pub(crate) fn editor_prompt_with<F>(
+        &mut self,
+        prompt: &str,
+        history: &'static str,
+        mut callback: F,
+    ) -> io::Result<Option<String>>
+    where
+        F: FnMut(&mut Kilo, &str, EditorKey),
+    {
+        let entries = self.prompt.history.get(history).cloned().unwrap_or_default();
+        let prefix = prompt.find("{}").map_or(prompt.len(), |i| str_width(&prompt[..i]));
+        let mut input = Prompt::new();
+
+        let result = loop {
+            let message = match self.prompt.note {
+                Some(ref note) => prompt.replace("{}", &format!("{} [{}]", input.text(), note)),
+                None => prompt.replace("{}", &input.text()),
+            };
+            self.editor_set_status_message(&message);
+            self.prompt.cursor = Some(prefix + input.cursor);
+            self.editor_refresh_screen()?;
+
+            let key = self.editor_wait_key()?;
+            if key == Paste {
+                // Only the first line of a paste fits in a prompt.
+                let pasted = self.terminal.pasted.take();
+                let line = pasted.split('\n').next().unwrap_or("");
+                for c in line.chars().filter(|c| !c.is_control()) {
+                    input.input.insert(input.cursor, c);
+                    input.cursor += 1;
+                }
+            }
+            let action = input.handle_key(key, &entries);
+            let text = input.text();
+            callback(self, &text, key);
+
+            match action {
+                PromptAction::Continue => {}
+                PromptAction::Accept => break Some(text),
+                PromptAction::Cancel => break None,
+            }
+        };
+
+        self.prompt.cursor = None;
+        self.prompt.note = None;
+        self.editor_set_status_message("");
+
+        if let Some(ref text) = result {
+            if !text.is_empty() {
+                let entries = self.prompt.history.entry(history).or_default();
+                entries.retain(|entry| entry != text);
+                entries.push(text.clone());
+                if entries.len() > KILO_PROMPT_HISTORY {
+                    entries.remove(0);
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Asks `question` in the message bar and waits for a single-key answer. `all` also
+    /// offers "all", for questions asked about each of several items.
+    pub(crate) fn editor_confirm(&mut self, question: &str, all: bool) -> io::Result<Answer> {
+        let choices = if all { "(y)es (n)o (a)ll (c)ancel" } else { "(y)es (n)o (c)ancel" };
+        let message = format!("{} {}", question, choices);
+
+        let answer = loop {
+            self.editor_set_status_message(&message);
+            self.prompt.cursor = Some(str_width(&message));
+            self.editor_refresh_screen()?;
+
+            match self.editor_wait_key()? {
+                Char(b'y') | Char(b'Y') => break Answer::Yes,
+                Char(b'n') | Char(b'N') => break Answer::No,
+                Char(b'a') | Char(b'A') if all => break Answer::All,
+                Char(b'c') | Char(b'C') | Char(b'\x1b') => break Answer::Cancel,
+                _ => {}
+            }
+        };
+
+        self.prompt.cursor = None;
+        self.editor_set_status_message("");
+
+        Ok(answer)
+    }
+
+    pub(crate) fn editor_pick(
+        &mut self,
+        title: &str,
+        items: Vec<String>,
+    ) -> io::Result<Option<usize>> {
+        self.picker = Some(Picker {
+            title: title.to_string(),
+            items,
+            query: String::new(),
+            selected: 0,
+        });
+
+        let choice = loop {
+            self.editor_refresh_screen()?;
+
+            let key = self.editor_wait_key()?;
+            let picker = match self.picker {
+                Some(ref mut picker) => picker,
+                None => break None,
+            };
+            let matches = picker.matches();
+
+            match key {
+                Char(b'\r') => break matches.get(picker.selected).cloned(),
+                Char(b'\x1b') => break None,
+                ArrowUp => picker.selected = picker.selected.saturating_sub(1),
+                ArrowDown if picker.selected + 1 < matches.len() => picker.selected += 1,
+                Char(127) => {
+                    picker.query.pop();
+                    picker.selected = 0;
+                }
+                Char(c) if c == ctrl_key('h') => {
+                    picker.query.pop();
+                    picker.selected = 0;
+                }
+                Char(c) if c.is_ascii() && !c.is_ascii_control() => {
+                    picker.query.push(c as char);
+                    picker.selected = 0;
+                }
+                Text(c) => {
+                    picker.query.push(c);
+                    picker.selected = 0;
+                }
+                _ => {}
+            }
+        };
+
+        self.picker = None;
+
+        Ok(choice)
+    }
+
+    pub(crate) fn editor_wait_key(&self) -> io::Result<EditorKey> {
+        loop {
+            if let Some(key) = self.editor_read_key()? {
+                return Ok(key);
+            }
+        }
+    }
+
+    /// Reads the bytes of the next key press, waiting for one.
+    pub(crate) fn editor_wait_sequence(&self) -> io::Result<Vec<u8>> {
+        loop {
+            let (_, received) = self.editor_read_sequence()?;
+            if !received.is_empty() {
+                return Ok(received);
+            }
+        }
+    }
+
+    /// Inserts the bytes of the next key press as text, so control characters and escape
+    /// sequences end up in the buffer instead of running commands. Up to three digits enter a
+    /// character by its decimal code instead, e.g. Ctrl-V 0 2 7 for ESC.
+    pub(crate) fn editor_quoted_insert(&mut self, count: usize) -> io::Result<()> {
+        self.editor_set_status_message("Insert literally: (key, or decimal code)");
+        self.editor_refresh_screen()?;
+
+        let received = self.editor_wait_sequence()?;
+        let text: Vec<char> = if received[0].is_ascii_digit() {
+            let mut code = u32::from(received[0] - b'0');
+            for _ in 1..3 {
+                self.editor_set_status_message(&format!("Insert literally: {}", code));
+                self.editor_refresh_screen()?;
+
+                let next = self.editor_wait_sequence()?;
+                match next[..] {
+                    [digit] if digit.is_ascii_digit() => code = code * 10 + u32::from(digit - b'0'),
+                    _ => {
+                        // Any other key ends the code and is then handled as usual.
+                        let mut input = self.terminal.input.borrow_mut();
+                        for &byte in next.iter().rev() {
+                            input.push_front(byte);
+                        }
+                        break;
+                    }
+                }
+            }
+
+            if code > 0xff {
+                self.editor_set_status_message_with(
+                    Severity::Warning,
+                    &format!("Invalid character code: {}", code),
+                );
+                return Ok(());
+            }
+            vec![char::from(code as u8)]
+        } else if received.is_ascii() {
+            received.iter().map(|&b| char::from(b)).collect()
+        } else {
+            self.editor_set_status_message_with(
+                Severity::Warning,
+                "Only ASCII keys can be inserted literally",
+            );
+            return Ok(());
+        };
+
+        self.editor_set_status_message("");
+        for _ in 0..count {
+            for &c in &text {
+                match c {
+                    '\n' => self.editor_insert_newline(),
+                    _ => self.editor_insert_char(c),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads the register name and the command it applies to after an Alt-" prefix.
+    pub(crate) fn editor_read_register(&mut self) -> io::Result<Option<(EditorKey, char)>> {
+        let name = match self.editor_read_mark_name("Register: (a-z, A-Z = append)")? {
+            Some(name) if name != '\'' => name,
+            _ => {
+                self.editor_set_status_message_with(Severity::Warning, "Invalid register name");
+                return Ok(None);
+            }
+        };
+
+        self.editor_set_status_message(&format!("Register '{}':", name));
+        self.editor_refresh_screen()?;
+        let key = self.editor_wait_key()?;
+        self.editor_set_status_message("");
+
+        Ok(Some((key, name)))
+    }
+
+    pub(crate) fn editor_read_mark_name(&mut self, prompt: &str) -> io::Result<Option<char>> {
+        self.editor_set_status_message(prompt);
+        self.editor_refresh_screen()?;
+
+        let name = match self.editor_wait_key()? {
+            Char(c) if c.is_ascii_alphabetic() || c == b'\'' => Some(c as char),
+            _ => None,
+        };
+
+        self.editor_set_status_message("");
+
+        Ok(name)
+    }
+
+    pub(crate) fn editor_read_digit_argument(
+        &mut self,
+        first: u8,
+    ) -> io::Result<(EditorKey, usize)> {
+        let mut count = (first - b'0') as usize;
+
+        loop {
+            self.editor_set_status_message(&format!("Count: {}", count));
+            self.editor_refresh_screen()?;
+
+            match self.editor_wait_key()? {
+                Alt(d) if d.is_ascii_digit() => {
+                    count = count.saturating_mul(10).saturating_add((d - b'0') as usize);
+                }
+                key => {
+                    self.editor_set_status_message("");
+                    return Ok((key, count.max(1)));
+                }
+            }
+        }
+    }
+
+    pub(crate) fn editor_read_universal_argument(&mut self) -> io::Result<(EditorKey, usize)> {
+        let mut count: Option<usize> = None;
+        let mut multiplier: usize = 4;
+
+        loop {
+            let prompt = match count {
+                Some(count) => format!("C-u {}", count),
+                None => format!("C-u ({})", multiplier),
+            };
+            self.editor_set_status_message(&prompt);
+            self.editor_refresh_screen()?;
+
+            match self.editor_wait_key()? {
+                Char(d) if d.is_ascii_digit() => {
+                    let n = count.unwrap_or(0);
+                    count = Some(n.saturating_mul(10).saturating_add((d - b'0') as usize));
+                }
+                Char(c) if c == ctrl_key('u') && count.is_none() => {
+                    multiplier = multiplier.saturating_mul(4);
+                }
+                key => {
+                    self.editor_set_status_message("");
+                    return Ok((key, count.unwrap_or(multiplier).max(1)));
+                }
+            }
+        }
+    }
+
+    pub(crate) fn editor_process_keypress(&mut self) -> io::Result<bool> {
+        self.editor_poll_lsp();
+
+        let c = match self.editor_read_key()? {
+            Some(c) => c,
+            None => {
+                self.editor_idle()?;
+                return Ok(true);
+            }
+        };
+
+        self.last_key_time = Instant::now();
+        self.word_highlight = None;
+        self.editor_clear_status_message(c == Char(b'\x1b'));
+
+        if self.editor_run_plugins(|plugin, editor| plugin.on_key(editor, c)) {
+            return Ok(true);
+        }
+        if self.editor_vi_key(c)? {
+            return Ok(true);
+        }
+
+        let (c, register) = match c {
+            Alt(b'"') => match self.editor_read_register()? {
+                Some((key, name)) => (key, Some(name)),
+                None => return Ok(true),
+            },
+            c => (c, None),
+        };
+
+        let (c, count) = match c {
+            Char(k) if k == ctrl_key('u') => self.editor_read_universal_argument()?,
+            Alt(d) if d.is_ascii_digit() => self.editor_read_digit_argument(d)?,
+            c => (c, 1),
+        };
+
+        if edits_text(c) && self.editor_refuse_if_read_only() {
+            return Ok(true);
+        }
+
+        let append = self.clipboard.last_kill;
+        self.clipboard.last_kill = false;
+        if c != Char(ctrl_key('q')) {
+            self.quit_presses = 0;
+        }
+        let last_yank = self.clipboard.last_yank.take();
+
+        match c {
+            Char(c) if c == ctrl_key('q') => return Ok(!self.editor_confirm_quit()?),
+            Char(c) if c == ctrl_key('s') => self.editor_save()?,
+            Char(c) if c == ctrl_key('n') => self.editor_complete_word()?,
+            Char(c) if c == ctrl_key('k') => {
+                for i in 0..count {
+                    self.editor_kill_to_eol(append || i > 0, register);
+                }
+                self.clipboard.last_kill = true;
+            }
+            Alt(b'k') => {
+                for i in 0..count {
+                    self.editor_kill_line(append || i > 0, register);
+                }
+                self.clipboard.last_kill = true;
+            }
+            Char(c) if c == ctrl_key('y') => {
+                for _ in 0..count {
+                    self.editor_yank(register);
+                }
+            }
+            Alt(b'w') if self.anchor.is_some() => self.editor_copy_selection(register),
+            Alt(b'w') => self.editor_copy_lines(count, register),
+            Char(c) if c == ctrl_key('w') => self.editor_kill_selection(register),
+            Alt(b'v') => self.editor_toggle_selection(false),
+            Alt(b'V') => self.editor_toggle_selection(true),
+            Alt(b'Y') => self.editor_yank_rectangle(),
+            Alt(b'I') => self.editor_block_insert()?,
+            Alt(b'S') => self.editor_sort_lines()?,
+            Alt(b'=') => self.editor_align()?,
+            Alt(b'q') => self.editor_reflow(),
+            Alt(b';') => self.editor_toggle_comment(),
+            Char(c) if c == ctrl_key('g') => self.editor_stats(),
+            Alt(b'g') => self.editor_goto_offset()?,
+            Alt(b'f') => self.editor_format(false),
+            Alt(b'm') => self.editor_format(true),
+            Alt(b'e') => self.editor_transform_selection()?,
+            Alt(b'i') => self.editor_convert_indent()?,
+            Alt(b'M') => self.editor_next_mixed_indent(),
+            Alt(b'(') => self.editor_indent_block_edge(false),
+            Alt(b')') => self.editor_indent_block_edge(true),
+            Alt(b'+') => self.editor_next_sibling(true),
+            Alt(b'-') => self.editor_next_sibling(false),
+            Alt(b'}') | Alt(b'{') => {
+                for _ in 0..count {
+                    self.editor_next_paragraph(c == Alt(b'}'));
+                }
+            }
+            Alt(b'F') | Alt(b'B') => {
+                for _ in 0..count {
+                    self.editor_next_block(c == Alt(b'F'));
+                }
+            }
+            Alt(b'y') => self.editor_yank_pop(last_yank)?,
+            Alt(b'r') => self.editor_revert()?,
+            Char(0) => self.editor_set_mark()?,
+            Char(c) if c == ctrl_key('b') => self.editor_goto_mark()?,
+            Char(c) if c == ctrl_key('r') => self.editor_open_recent()?,
+            Char(c) if c == ctrl_key('e') => self.editor_open_prompt()?,
+            Char(c) if c == ctrl_key('f') => self.editor_find()?,
+            Char(c) if c == ctrl_key('l') => self.editor_goto_line()?,
+            Alt(b'!') => self.editor_shell_command()?,
+            Alt(b':') => self.editor_command_line()?,
+            Alt(b'u') => self.editor_open_url()?,
+            Alt(b'%') => self.editor_replace(true)?,
+            Alt(b's') => self.editor_next_misspelling(),
+            Alt(b'l') => self.editor_lint(),
+            Alt(b'd') => self.editor_show_diagnostic(),
+            Alt(b'.') => self.editor_lsp_request("textDocument/definition")?,
+            Alt(b'h') => self.editor_lsp_request("textDocument/hover")?,
+            Alt(b'b') => self.editor_blame(),
+            Alt(b'n') => self.editor_next_hunk(true),
+            Alt(b'p') => self.editor_next_hunk(false),
+            Alt(b'c') => self.editor_next_conflict(true),
+            Alt(b'C') => self.editor_next_conflict(false),
+            Alt(b'o') => self.editor_resolve_conflict(true, false),
+            Alt(b'T') => self.editor_resolve_conflict(false, true),
+            Alt(b't') => {
+                for _ in 0..count {
+                    self.editor_transpose_words();
+                }
+            }
+            Char(c) if c == ctrl_key('a') => self.editor_increment(count as i64),
+            Char(c) if c == ctrl_key('x') => self.editor_increment(-(count as i64)),
+            Char(c) if c == ctrl_key('t') => {
+                for _ in 0..count {
+                    self.editor_transpose_chars();
+                }
+            }
+            Alt(b'a') => self.editor_resolve_conflict(true, true),
+            Char(c) if c == ctrl_key('o') => {
+                for _ in 0..count {
+                    self.editor_jump_back();
+                }
+            }
+            // Ctrl-I is the byte Tab sends, so jumping forward has a key of its own.
+            Alt(b'j') => {
+                for _ in 0..count {
+                    self.editor_jump_forward();
+                }
+            }
+            HomeKey => self.editor_home(),
+            EndKey => self.editor_end(),
+            PageUp | PageDown => {
+                self.editor_record_jump();
+
+                for _ in 0..count {
+                    self.editor_scroll();
+
+                    if c == PageUp {
+                        self.cy = self.rowoff;
+                    } else if c == PageDown {
+                        self.cy = self.rowoff + self.screenrows - 1;
+                        if self.cy > self.buffer.rows.len() {
+                            self.cy = self.buffer.rows.len();
+                        }
+                    }
+                    for _ in 0..self.screenrows {
+                        self.editor_move_cursor(if c == PageUp { ArrowUp } else { ArrowDown });
+                    }
+                }
+            }
+            ArrowUp | ArrowDown | ArrowLeft | ArrowRight => {
+                for _ in 0..count {
+                    self.editor_move_cursor(c);
+                }
+            }
+            InsertKey => self.editor_toggle_overwrite()?,
+            Paste => {
+                let text = self.terminal.pasted.take();
+                self.editor_insert_str(&text);
+            }
+            Char(c) if c == ctrl_key('v') => self.editor_quoted_insert(count)?,
+            Char(b'\r') => {
+                for _ in 0..count {
+                    self.editor_insert_newline();
+                }
+            }
+            Char(c) if c == 127 || c == ctrl_key('h') => {
+                for _ in 0..count {
+                    self.editor_del_char();
+                }
+            }
+            DelKey => {
+                for _ in 0..count {
+                    self.editor_del_char_forward();
+                }
+            }
+            Char(b'\t') if self.editor_expand_snippet()? => {}
+            Char(b'\t') if self.editor_complete_path() => {}
+            Char(b'\t') => {
+                for _ in 0..count {
+                    self.editor_insert_tab();
+                }
+            }
+            Char(c) if (b' '..=b'~').contains(&c) => {
+                for _ in 0..count {
+                    self.editor_insert_char(c as char);
+                }
+            }
+            Text(c) => {
+                for _ in 0..count {
+                    self.editor_insert_char(c);
+                }
+            }
+            _ => {}
+        }
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csi_keys() {
+        assert_eq!(csi_key("", b'A'), Some(ArrowUp));
+        assert_eq!(csi_key("1;5", b'D'), Some(ArrowLeft));
+        assert_eq!(csi_key("", b'H'), Some(HomeKey));
+        assert_eq!(csi_key("7", b'~'), Some(HomeKey));
+        assert_eq!(csi_key("4", b'~'), Some(EndKey));
+        assert_eq!(csi_key("3;2", b'~'), Some(DelKey));
+        assert_eq!(csi_key("5", b'~'), Some(PageUp));
+        assert_eq!(csi_key("6", b'~'), Some(PageDown));
+        assert_eq!(csi_key("200", b'~'), Some(Paste));
+        assert_eq!(csi_key("", b'I'), Some(FocusIn));
+        assert_eq!(csi_key("", b'O'), Some(FocusOut));
+        assert_eq!(csi_key("1", b'I'), None);
+        assert_eq!(csi_key("201", b'~'), None);
+        assert_eq!(csi_key("", b'Z'), None);
+    }
+}
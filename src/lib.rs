@@ -1,54 +1,372 @@
 extern crate libc;
 extern crate termios;
 
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell, RefMut};
 use std::char;
-use std::fs::File;
+use std::cmp;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::env;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::{self, File};
 use std::io;
 use std::io::prelude::*;
 use std::io::{BufReader, ErrorKind};
+use std::iter;
+use std::mem;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
 use std::os::unix::io::{AsRawFd, RawFd};
-use std::time::{Duration, Instant};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, ExitStatus, Output, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
 use libc::{TIOCGWINSZ, ioctl, winsize};
 use termios::*;
 
+mod buffer;
+mod complete;
+mod config;
+mod format;
+mod git;
+mod input;
+mod log;
+mod lsp;
+mod overlay;
+mod quickfix;
+mod render;
+mod search;
+mod snippet;
+mod spell;
+mod storage;
+mod syntax;
+mod terminal;
+mod vi;
+
+pub use buffer::{Buffer, Row};
+pub use input::EditorKey;
+pub use terminal::{TerminalWriter, clear_screen};
+
+use buffer::*;
+use complete::*;
+use config::*;
+use format::*;
+use git::*;
+use input::*;
+use log::*;
+use lsp::*;
+use overlay::*;
+use quickfix::*;
+use render::*;
+use spell::*;
+use storage::*;
+use syntax::*;
+use terminal::*;
+use vi::*;
+
 const KILO_VERSION: Option<&'static str> = option_env!("CARGO_PKG_VERSION");
 const KILO_TAB_STOP: usize = 8;
+const KILO_MODELINE_ROWS: usize = 5;
+const KILO_KILL_RING_SIZE: usize = 30;
+const KILO_PROMPT_HISTORY: usize = 100;
+const KILO_TEXT_WIDTH: usize = 80;
+const KILO_FORMAT_INDENT: usize = 2;
+
+const CURRENT_LINE_STYLE: &str = "\x1b[48;5;236m";
+const RULER_STYLE: &str = "\x1b[48;5;239m";
+const WORD_HIGHLIGHT_STYLE: &str = "\x1b[48;5;58m";
+const MISSPELLING_STYLE: &str = "\x1b[4;31m";
+const URL_STYLE: &str = "\x1b[4m";
+const DIAGNOSTIC_STYLE: &str = "\x1b[4;33m";
+const CONFLICT_MARKER_STYLE: &str = "\x1b[48;5;52m";
+const CONFLICT_OURS_STYLE: &str = "\x1b[48;5;22m";
+const CONFLICT_BASE_STYLE: &str = "\x1b[48;5;237m";
+const CONFLICT_THEIRS_STYLE: &str = "\x1b[48;5;17m";
+const DIFF_CHANGED_STYLE: &str = "\x1b[48;5;94m";
+const DIFF_ADDED_STYLE: &str = "\x1b[32m";
+const DIFF_REMOVED_STYLE: &str = "\x1b[31m";
+const DIFF_HUNK_STYLE: &str = "\x1b[36m";
+const SELECTION_STYLE: &str = "\x1b[48;5;24m";
+const SEARCH_MATCH_STYLE: &str = "\x1b[48;5;136m";
+const COMPLETION_STYLE: &str = "\x1b[48;5;238m";
+const COMPLETION_SELECTED_STYLE: &str = "\x1b[7m";
+const MIXED_INDENT_STYLE: &str = "\x1b[48;5;130m";
+const TRUNCATION_STYLE: &str = "\x1b[7m";
+const CONTROL_CHAR_STYLE: &str = "\x1b[7m";
+const WARNING_STYLE: &str = "\x1b[33m";
+const ERROR_STYLE: &str = "\x1b[1;31m";
+const GUTTER_WIDTH: usize = 2;
+const KILO_JUMPLIST_SIZE: usize = 100;
+const KILO_POSITIONS_SIZE: usize = 1000;
+const KILO_RECENT_FILES_SIZE: usize = 50;
+const KILO_WELCOME_RECENT: usize = 5;
+const KILO_DICTIONARY: &str = "/usr/share/dict/words";
+const KILO_SUDO_COMMAND: &str = "sudo tee %";
+const KILO_PRINT_COMMAND: &str = "lpr";
+const KILO_GREP_COMMAND: &str = "grep -rnI --";
+const KILO_MAKE_COMMAND: &str = "make";
+const KILO_PAGE_LINES: usize = 66;
+const KILO_SUGGESTIONS: usize = 5;
+const KILO_COMPLETION_ITEMS: usize = 8;
+const KILO_FRAME_INTERVAL: Duration = Duration::from_millis(16);
+const KILO_ESCAPE_TIMEOUT: Duration = Duration::from_millis(50);
+const WORD_HIGHLIGHT_DELAY: Duration = Duration::from_millis(500);
+const GIT_REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+const GIT_DIFF_DELAY: Duration = Duration::from_millis(300);
+const LSP_CHANGE_DELAY: Duration = Duration::from_millis(300);
+const DISK_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+const VISUAL_BELL_DURATION: Duration = Duration::from_millis(100);
+const KILO_MESSAGE_TIMEOUT: u64 = 5;
+const KILO_QUIT_TIMES: usize = 3;
+const KILO_DIFF_LIMIT: usize = 1_000_000;
+const KILO_DIFF_CONTEXT: usize = 3;
+const KILO_HIGHLIGHT_BATCH: usize = 2000;
+const OFFSET_INDEX_CHUNK: usize = 1024;
+const CELL_RUN_GAP: usize = 8;
 
 #[inline]
-fn ctrl_key(k: char) -> u8 {
-    (k as u8) & 0x1f
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn find_word_occurrences(line: &[char], word: &[char]) -> Vec<usize> {
+    let mut occurrences = Vec::new();
+
+    if word.is_empty() || line.len() < word.len() {
+        return occurrences;
+    }
+
+    for start in 0..(line.len() - word.len() + 1) {
+        if &line[start..(start + word.len())] != word {
+            continue;
+        }
+        if start > 0 && is_word_char(line[start - 1]) {
+            continue;
+        }
+        if let Some(&c) = line.get(start + word.len()) {
+            if is_word_char(c) {
+                continue;
+            }
+        }
+        occurrences.push(start);
+    }
+
+    occurrences
+}
+
+/// A `file://` URI for `path`, which must exist.
+fn file_uri(path: &str) -> Option<String> {
+    let path = fs::canonicalize(path).ok()?;
+
+    Some(path_to_uri(&path, &hostname()))
+}
+
+/// A `file://` URI for absolute `path` on `host`, which is left empty for language servers.
+fn path_to_uri(path: &Path, host: &str) -> String {
+    let segments = path.as_os_str().as_bytes().split(|&b| b == b'/').map(url_encode);
+
+    format!("file://{}{}", host, segments.collect::<Vec<String>>().join("/"))
+}
+
+fn hostname() -> String {
+    let mut name = [0u8; 256];
+    // SAFETY: the buffer is valid for its whole length, which is what gethostname is told.
+    if unsafe { libc::gethostname(name.as_mut_ptr() as *mut libc::c_char, name.len()) } != 0 {
+        return String::new();
+    }
+    let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+
+    String::from_utf8_lossy(&name[..len]).into_owned()
+}
+
+struct Diagnostic {
+    line: usize,
+    col: usize,
+    message: String,
+}
+
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+fn run_shell(command: &str, input: Option<&[u8]>) -> io::Result<Output> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // Feed the input from another thread so a command producing lots of output can't block.
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        let input = input.to_vec();
+        thread::spawn(move || {
+            let _ = stdin.write_all(&input);
+        });
+    }
+
+    child.wait_with_output()
+}
+
+/// Runs `command` with `input` on stdin and the terminal as its stderr and controlling
+/// terminal, so it can prompt the user, e.g. for a password. Its output is discarded.
+fn run_with_terminal(command: &str, input: &[u8]) -> io::Result<ExitStatus> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()?;
+
+    let written = match child.stdin.take() {
+        Some(mut stdin) => stdin.write_all(input),
+        None => Ok(()),
+    };
+    let status = child.wait()?;
+    written?;
+
+    Ok(status)
 }
 
-pub fn clear_screen() -> io::Result<()> {
-    io::stdout().write("\x1b[2J".as_bytes())?;
-    io::stdout().write("\x1b[H".as_bytes())?;
-    io::stdout().flush()?;
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+fn parse_diagnostics(output: &str, filename: &str) -> Vec<Diagnostic> {
+    parse_locations(output)
+        .into_iter()
+        .filter(|l| same_file(Path::new(&l.filename), Path::new(filename)))
+        .map(|l| Diagnostic { line: l.line, col: l.col, message: l.message })
+        .collect()
+}
+
+#[derive(Default)]
+struct Args {
+    filename: Option<String>,
+    session: Option<PathBuf>,
+    diff: Option<(String, String)>,
+    debug: bool,
+    show_keys: bool,
+    vi: bool,
+}
+
+impl Args {
+    fn parse<I: Iterator<Item = String>>(mut argv: I) -> io::Result<Self> {
+        let mut args = Args::default();
+
+        while let Some(arg) = argv.next() {
+            match arg.as_str() {
+                "--session" => match argv.next() {
+                    Some(path) => args.session = Some(PathBuf::from(path)),
+                    None => return Err(invalid_input("--session requires a file argument")),
+                },
+                "--diff" => match (argv.next(), argv.next()) {
+                    (Some(a), Some(b)) => args.diff = Some((a, b)),
+                    _ => return Err(invalid_input("--diff requires two file arguments")),
+                },
+                "--debug" => args.debug = true,
+                "--show-keys" => args.show_keys = true,
+                "--vi" => args.vi = true,
+                _ if arg.starts_with("--") => {
+                    return Err(invalid_input(&format!("unknown option: {}", arg)));
+                }
+                _ => args.filename = Some(arg),
+            }
+        }
+
+        Ok(args)
+    }
+}
+
+fn parse_pair(s: &str) -> Option<(usize, usize)> {
+    let mut parts = s.split(' ').map(|n| n.parse().ok());
+
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(Some(a)), Some(Some(b)), None) => Some((a, b)),
+        _ => None,
+    }
+}
+
+fn invalid_input(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidInput, message)
+}
+
+/// Extension hooks for downstream crates, registered with `Kilo::register_plugin`.
+///
+/// Every method has a default that does nothing, so a plugin only implements the hooks it
+/// needs. Commands are bound to keys by handling them in `on_key`, or named for the Alt-:
+/// command line by `register_commands`.
+pub trait Plugin {
+    /// Called after a file has been opened.
+    fn on_open(&mut self, _editor: &mut Kilo) {}
+
+    /// Called after the buffer has been written to disk.
+    fn on_save(&mut self, _editor: &mut Kilo) {}
+
+    /// Called for every key before the built-in bindings; return `true` to consume it.
+    fn on_key(&mut self, _editor: &mut Kilo, _key: EditorKey) -> bool {
+        false
+    }
+
+    /// Extra styling for a rendered row, as `(start, len, SGR escape sequence)` spans.
+    fn on_render_row(&self, _row: usize, _render: &str) -> Vec<(usize, usize, String)> {
+        Vec::new()
+    }
+
+    /// A segment shown on the right of the status bar.
+    fn status(&self, _editor: &Kilo) -> Option<String> {
+        None
+    }
+
+    /// Names of the commands the plugin adds to the Alt-: command line, asked for once when
+    /// it is registered. Built-in commands of the same name take precedence.
+    fn register_commands(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Runs command `name`, one of those from `register_commands`, with the text typed after
+    /// it.
+    fn run_command(&mut self, _editor: &mut Kilo, _name: &str, _argument: &str) {}
+}
 
-    Ok(())
+/// The registered plugins and the commands they provide.
+struct Plugins {
+    loaded: Vec<Box<dyn Plugin>>,
+    /// The plugin that runs each command registered by one, by its index in `loaded`.
+    commands: HashMap<String, usize>,
+    in_hook: bool,
 }
 
-#[derive(PartialEq)]
-enum EditorKey {
-    ArrowLeft,
-    ArrowRight,
-    ArrowUp,
-    ArrowDown,
-    DelKey,
-    HomeKey,
-    EndKey,
-    PageUp,
-    PageDown,
-    Char(u8),
+/// Text that was killed or copied, for yanking back.
+struct Clipboard {
+    kill_ring: Vec<String>,
+    /// Whether the previous command was a kill, so the next one appends to it.
+    last_kill: bool,
+    /// Where the previous command yanked text and from which kill ring entry.
+    last_yank: Option<(Position, usize)>,
+    /// Named clipboards selected with the Alt-" prefix, independent of the kill ring.
+    registers: HashMap<char, String>,
+    /// The last rectangle cut or copied, one entry per row.
+    rectangle: Vec<String>,
 }
 
-struct Row {
-    chars: String,
-    render: String,
+/// Positions to go back to: marks and the jump list.
+struct Jumps {
+    marks: HashMap<char, Position>,
+    last_position: Option<Position>,
+    list: Vec<Position>,
+    index: usize,
 }
 
 pub struct Kilo {
-    stdin_fd: RawFd,
+    terminal: TerminalState,
+    frame: Frame,
     cx: usize,
     cy: usize,
     rx: usize,
@@ -56,11 +374,47 @@ pub struct Kilo {
     coloff: usize,
     screenrows: usize,
     screencols: usize,
-    rows: Vec<Row>,
+    buffer: Buffer,
+    dirty: bool,
+    file: FileState,
+    conflicts: Vec<Conflict>,
+    comparison: Option<Comparison>,
+    plugins: Plugins,
+    base_config: Config,
+    overwrite: bool,
+    /// The vi mode keys are read in, or `None` for the default bindings.
+    vi_mode: Option<ViMode>,
+    clipboard: Clipboard,
+    /// Ctrl-Q presses in a row, for `quit_confirm` set to a number of presses.
+    quit_presses: usize,
     filename: String,
-    statusmsg: String,
-    statusmsg_time: Instant,
-    orig_termios: Termios,
+    message: StatusMessage,
+    prompt: PromptState,
+    /// Recently opened files, as of startup, for the welcome screen.
+    recent_files: Vec<String>,
+    /// Start and end of the match found by an ongoing search.
+    search_match: Option<(Position, Position)>,
+    last_key_time: Instant,
+    word_highlight: Option<Vec<char>>,
+    /// The other end of the selection, which extends from here to the cursor.
+    anchor: Option<Position>,
+    /// Whether the selection is a rectangle between the anchor's and the cursor's columns.
+    block: bool,
+    jumps: Jumps,
+    picker: Option<Picker>,
+    /// The diff shown by the `diff` command while it is open.
+    diff_view: Option<DiffView>,
+    completion: Option<Completion>,
+    dictionary: Option<Dictionary>,
+    diagnostics: Vec<Diagnostic>,
+    /// Highlights drawn over the text, by the feature that owns them.
+    overlays: BTreeMap<Overlay, HighlightGroup>,
+    quickfix: Option<Quickfix>,
+    lsp: Option<LspClient>,
+    git: GitState,
+    highlight: Highlighting,
+    config: Config,
+    log: Option<Logger>,
 }
 
 use EditorKey::*;
@@ -78,8 +432,38 @@ impl Kilo {
         let stdin_fd = io::stdin().as_raw_fd();
         let orig_termios = Termios::from_fd(stdin_fd)?;
 
-        Ok(Kilo {
-            stdin_fd,
+        let mut kilo = Self::with_terminal(stdin_fd, Some(orig_termios));
+        kilo.terminal.hyperlinks = terminal_supports_hyperlinks();
+
+        Ok(kilo)
+    }
+
+    /// Creates an editor that isn't attached to a terminal, for driving it programmatically
+    /// (e.g. in benchmarks) with `open`, `feed` and `render`.
+    pub fn headless(rows: usize, cols: usize) -> Self {
+        let mut kilo = Self::with_terminal(-1, None);
+        kilo.screenrows = rows.saturating_sub(2);
+        kilo.screencols = cols;
+        kilo
+    }
+
+    fn with_terminal(stdin_fd: RawFd, orig_termios: Option<Termios>) -> Self {
+        Kilo {
+            terminal: TerminalState {
+                stdin_fd,
+                input: RefCell::new(VecDeque::new()),
+                orig_termios,
+                hyperlinks: false,
+                focused: Cell::new(true),
+                refocused: Cell::new(false),
+                pasted: RefCell::new(String::new()),
+            },
+            frame: Frame {
+                refreshed: Instant::now(),
+                lines: Vec::new(),
+                cols: 0,
+                title: String::new(),
+            },
             cx: 0,
             cy: 0,
             rx: 0,
@@ -87,406 +471,2892 @@ impl Kilo {
             coloff: 0,
             screenrows: 0,
             screencols: 0,
-            rows: Vec::new(),
+            buffer: Buffer::new(),
+            dirty: false,
+            file: FileState {
+                compression: None,
+                remote: None,
+                remote_fetch: None,
+                disk_mtime: None,
+                disk_checked: Instant::now(),
+            },
+            conflicts: Vec::new(),
+            comparison: None,
+            plugins: Plugins {
+                loaded: Vec::new(),
+                commands: HashMap::new(),
+                in_hook: false,
+            },
+            base_config: Config::default(),
+            overwrite: false,
+            vi_mode: None,
+            clipboard: Clipboard {
+                kill_ring: Vec::new(),
+                last_kill: false,
+                last_yank: None,
+                registers: HashMap::new(),
+                rectangle: Vec::new(),
+            },
+            quit_presses: 0,
             filename: String::new(),
-            statusmsg: String::new(),
-            statusmsg_time: Instant::now(),
-            orig_termios,
-        })
+            message: StatusMessage {
+                text: String::new(),
+                time: Instant::now(),
+                severity: Severity::Info,
+                sticky: false,
+            },
+            prompt: PromptState {
+                cursor: None,
+                note: None,
+                history: HashMap::new(),
+            },
+            recent_files: Vec::new(),
+            search_match: None,
+            last_key_time: Instant::now(),
+            word_highlight: None,
+            anchor: None,
+            block: false,
+            jumps: Jumps {
+                marks: HashMap::new(),
+                last_position: None,
+                list: Vec::new(),
+                index: 0,
+            },
+            picker: None,
+            diff_view: None,
+            completion: None,
+            dictionary: None,
+            diagnostics: Vec::new(),
+            overlays: BTreeMap::new(),
+            quickfix: None,
+            lsp: None,
+            git: GitState {
+                status: None,
+                query: None,
+                checked: None,
+                changes: Vec::new(),
+                changes_stale: false,
+            },
+            highlight: Highlighting {
+                syntaxes: EditorSyntax::builtin(),
+                syntax: None,
+                job: None,
+                restart: None,
+            },
+            config: Config::default(),
+            log: None,
+        }
     }
 
-    fn disable_raw_mode(&self) -> io::Result<()> {
-        tcsetattr(self.stdin_fd, TCSAFLUSH, &self.orig_termios)
+    pub fn register_plugin(&mut self, plugin: Box<dyn Plugin>) {
+        for name in plugin.register_commands() {
+            self.plugins.commands.entry(name).or_insert(self.plugins.loaded.len());
+        }
+        self.plugins.loaded.push(plugin);
     }
 
-    fn enable_raw_mode(&self) -> io::Result<()> {
-        let mut raw = self.orig_termios.clone();
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
 
-        raw.c_iflag &= !(BRKINT | ICRNL | INPCK | ISTRIP | IXON);
-        raw.c_oflag &= !(OPOST);
-        raw.c_cflag |= CS8;
-        raw.c_lflag &= !(ECHO | ICANON | IEXTEN | ISIG);
-        raw.c_cc[VMIN] = 0;
-        raw.c_cc[VTIME] = 1;
+    /// Cursor position as `(row, column)`, both zero-based.
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.cy, self.cx)
+    }
 
-        tcsetattr(self.stdin_fd, TCSAFLUSH, &raw)
+    pub fn set_cursor(&mut self, row: usize, col: usize) {
+        self.editor_move_to(Position { cx: col, cy: row });
     }
 
-    fn editor_read_key(&self) -> io::Result<EditorKey> {
-        let mut buffer = [0];
+    pub fn line_count(&self) -> usize {
+        self.buffer.line_count()
+    }
 
-        while let Err(e) = io::stdin().read(&mut buffer) {
-            if e.kind() != ErrorKind::Interrupted {
-                return Err(e);
-            }
-        }
+    pub fn line(&self, at: usize) -> Option<&str> {
+        self.buffer.line(at)
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
 
-        let c = buffer[0];
+    pub fn set_status_message(&mut self, message: &str) {
+        self.editor_set_status_message(message);
+    }
 
-        if c == '\x1b' as u8 {
-            let mut seq = [0; 3];
+    pub fn open(&mut self, filename: &str) -> io::Result<()> {
+        self.editor_open(filename)
+    }
 
-            if io::stdin().read(&mut seq[0..1])? != 1 {
-                return Ok(Char(c));
-            }
+    /// Processes `input` as if it had been typed. Returns `false` if it quit the editor.
+    pub fn feed(&mut self, input: &[u8]) -> io::Result<bool> {
+        self.terminal.input.borrow_mut().extend(input);
 
-            if io::stdin().read(&mut seq[1..2])? != 1 {
-                return Ok(Char(c));
+        while !self.terminal.input.borrow().is_empty() {
+            if !self.editor_process_keypress()? {
+                return Ok(false);
             }
+        }
 
-            if seq[0] == '[' as u8 {
-                if seq[1] >= '0' as u8 && seq[1] <= '9' as u8 {
-                    if io::stdin().read(&mut seq[2..3])? != 1 {
-                        return Ok(Char(c));
-                    }
+        Ok(true)
+    }
 
-                    if seq[2] == '~' as u8 {
-                        match seq[1] as char {
-                            '1' => return Ok(HomeKey),
-                            '3' => return Ok(DelKey),
-                            '4' => return Ok(EndKey),
-                            '5' => return Ok(PageUp),
-                            '6' => return Ok(PageDown),
-                            '7' => return Ok(HomeKey),
-                            '8' => return Ok(EndKey),
-                            _ => return Ok(Char(c)),
-                        }
-                    }
-                } else {
-                    match seq[1] as char {
-                        'A' => return Ok(ArrowUp),
-                        'B' => return Ok(ArrowDown),
-                        'C' => return Ok(ArrowRight),
-                        'D' => return Ok(ArrowLeft),
-                        'H' => return Ok(HomeKey),
-                        'F' => return Ok(EndKey),
-                        _ => return Ok(Char(c)),
-                    }
-                }
-            } else if seq[0] == 'O' as u8 {
-                match seq[1] as char {
-                    'H' => return Ok(HomeKey),
-                    'F' => return Ok(EndKey),
-                    _ => return Ok(Char(c)),
-                }
-            }
+    /// The escape sequences that draw the current screen.
+    pub fn render(&mut self) -> String {
+        self.editor_render_frame()
+    }
 
-            return Ok(Char(c));
-        } else {
-            Ok(Char(c))
-        }
+    /// Runs `hook` on each plugin until one returns `true`, which is returned.
+    fn editor_run_plugins<F>(&mut self, mut hook: F) -> bool
+    where
+        F: FnMut(&mut dyn Plugin, &mut Kilo) -> bool,
+    {
+        // Plugins are moved out while they run so each can borrow the editor mutably.
+        let mut plugins = mem::take(&mut self.plugins.loaded);
+        let handled = plugins.iter_mut().any(|plugin| hook(plugin.as_mut(), self));
+
+        plugins.append(&mut self.plugins.loaded);
+        self.plugins.loaded = plugins;
+
+        handled
     }
 
-    fn get_cursor_position(&self) -> io::Result<(usize, usize)> {
-        io::stdout().write("\x1b[6n".as_bytes())?;
-        io::stdout().flush()?;
+    fn editor_render(&self, chars: &str) -> String {
+        self.buffer.render_text(chars)
+    }
 
-        let mut buffer = [0; 32];
-        let bytes_read = io::stdin().read(&mut buffer)?;
+    fn editor_update_row(&self, row: &mut Row) {
+        row.render = self.editor_render(&row.chars);
+    }
 
-        let s = std::str::from_utf8(&buffer[..bytes_read]).map_err(|_| {
-            io::Error::last_os_error()
-        })?;
+    /// Re-renders row `at` after its text changed and marks the buffer as modified.
+    fn editor_row_changed(&mut self, at: usize) {
+        let render = self.editor_render(&self.buffer.rows[at].chars);
+        self.buffer.rows[at].render = render;
+        self.buffer.rows[at].stale = false;
+        self.editor_row_updated(at, true);
+    }
 
-        if !s.starts_with("\x1b[") || !s.ends_with("R") {
-            return Err(io::Error::last_os_error());
+    /// Updates the state derived from row `at` once its render is current, re-highlighting
+    /// it (and following rows whose multi-line state changes) if `rehighlight` is set.
+    fn editor_row_updated(&mut self, at: usize, rehighlight: bool) {
+        self.buffer.row_edited(at);
+        if rehighlight {
+            self.editor_update_syntax(at);
         }
 
-        let mut parts = s[2..(s.len() - 1)].split(';').map(
-            |i| i.parse().unwrap_or(0),
-        );
-        let rows = parts.next().unwrap_or(0);
-        let cols = parts.next().unwrap_or(0);
-
-        Ok((rows, cols))
+        self.dirty = true;
+        let markers = ["<<<<<<<", "|||||||", "=======", ">>>>>>>"];
+        let marker = markers.iter().any(|m| self.buffer.rows[at].chars.starts_with(m));
+        if marker || !self.conflicts.is_empty() {
+            self.conflicts = find_conflicts(&self.buffer.rows);
+        }
+        self.editor_text_changed();
     }
 
-    fn get_window_size(&self) -> io::Result<(usize, usize)> {
-        unsafe {
-            let ws: winsize = std::mem::uninitialized();
+    fn editor_insert_char(&mut self, c: char) {
+        if self.cy == self.buffer.rows.len() {
+            self.editor_append_row("");
+        }
 
-            if ioctl(self.stdin_fd, TIOCGWINSZ, &ws) == -1 || ws.ws_col == 0 || ws.ws_row == 0 {
-                io::stdout().write("\x1b[999C\x1b[999B".as_bytes())?;
-                io::stdout().flush()?;
+        let (cx, cy) = (self.cx, self.cy);
+        let row = &mut self.buffer.rows[cy];
+        let at = row.chars.char_indices().nth(cx).map_or(row.chars.len(), |(i, _)| i);
+        // Without tabs or placeholders the render is a copy of the text, so the edit can be
+        // spliced into it.
+        let plain = |c: char| c != '\t' && placeholder_width(c) == 0;
+        let splice = !row.stale && plain(c) && row.chars.chars().all(plain);
+        let replace = self.overwrite && at < row.chars.len();
+
+        if replace {
+            row.chars.remove(at);
+        }
+        row.chars.insert(at, c);
 
-                self.get_cursor_position()
-            } else {
-                Ok((ws.ws_row as usize, ws.ws_col as usize))
+        if splice {
+            if replace {
+                row.render.remove(at);
+                if cx < row.hl.len() {
+                    row.hl.remove(cx);
+                }
             }
+            row.render.insert(at, c);
+            row.hl.insert(cx.min(row.hl.len()), Highlight::Normal);
+            self.editor_row_updated(cy, self.highlight.syntax.is_some());
+        } else {
+            self.editor_row_changed(cy);
         }
+        self.cx += 1;
     }
 
-    fn editor_row_cx_to_rx(&self, row: &Row, cx: usize) -> usize {
-        let mut rx = 0;
+    /// Adds `delta` to the number at or after the cursor and leaves the cursor on its last
+    /// digit.
+    fn editor_increment(&mut self, delta: i64) {
+        let chars = match self.buffer.rows.get(self.cy) {
+            Some(row) => row.chars.chars().collect::<Vec<char>>(),
+            None => return,
+        };
 
-        for j in 0..cx {
-            if let Some('\t') = row.chars.chars().nth(j) {
-                rx += (KILO_TAB_STOP - 1) - (rx % KILO_TAB_STOP);
+        let (start, end, hex) = match find_number(&chars, self.cx) {
+            Some(number) => number,
+            None => {
+                self.editor_set_status_message("No number under or after the cursor");
+                return;
             }
-            rx += 1;
-        }
+        };
 
-        rx
-    }
+        let number = chars[start..end].iter().collect::<String>();
+        let replacement = match add_to_number(&number, hex, delta) {
+            Some(replacement) => replacement,
+            None => {
+                self.editor_set_status_message("Number out of range");
+                return;
+            }
+        };
 
-    fn editor_update_row(&self, row: &mut Row) {
-        let spaces = (0..KILO_TAB_STOP).map(|_| ' ').collect::<String>();
-        row.render = row.chars.replace('\t', &spaces);
+        let mut line = chars[..start].iter().collect::<String>();
+        line.push_str(&replacement);
+        line.extend(&chars[end..]);
+        self.buffer.rows[self.cy].chars = line;
+
+        let cy = self.cy;
+        self.editor_row_changed(cy);
+        self.cx = start + replacement.chars().count() - 1;
     }
 
-    fn editor_append_row(&mut self, s: &str) {
-        let mut row = Row {
-            chars: s.to_string(),
-            render: String::new(),
+    /// Moves to the first non-blank character, or to column 0 when already there, with
+    /// `smart_home`.
+    fn editor_home(&mut self) {
+        let indent = match self.buffer.rows.get(self.cy) {
+            Some(row) if self.config.smart_home => {
+                row.chars.chars().take_while(|c| c.is_whitespace()).count()
+            }
+            _ => 0,
         };
 
-        self.editor_update_row(&mut row);
-        self.rows.push(row);
+        self.cx = if self.cx == indent { 0 } else { indent };
     }
 
-    fn editor_open(&mut self, filename: &str) -> io::Result<()> {
-        self.filename = filename.to_string();
+    /// Moves to the end of the line; with `smart_end`, first to its last non-blank character.
+    fn editor_end(&mut self) {
+        let (len, trimmed) = match self.buffer.rows.get(self.cy) {
+            Some(row) => (row.chars.chars().count(), row.chars.trim_end().chars().count()),
+            None => return,
+        };
 
-        let file = File::open(filename)?;
-        let reader = BufReader::new(file);
+        self.cx = if self.config.smart_end && self.cx != trimmed { trimmed } else { len };
+    }
+
+    /// Swaps the characters before and at the cursor, or the last two at the end of a line.
+    fn editor_transpose_chars(&mut self) {
+        let mut chars = match self.buffer.rows.get(self.cy) {
+            Some(row) => row.chars.chars().collect::<Vec<char>>(),
+            None => return,
+        };
 
-        for line in reader.lines() {
-            self.editor_append_row(&line?);
+        if chars.len() < 2 || self.cx == 0 {
+            self.editor_set_status_message("Nothing to transpose");
+            return;
         }
 
-        Ok(())
+        let at = self.cx.min(chars.len() - 1);
+        chars.swap(at - 1, at);
+        self.buffer.rows[self.cy].chars = chars.into_iter().collect();
+
+        let cy = self.cy;
+        self.editor_row_changed(cy);
+        self.cx = at + 1;
     }
 
-    fn editor_scroll(&mut self) {
-        self.rx = 0;
+    /// Swaps the word at (or after) the cursor with the next word on the line, leaving the
+    /// cursor after both.
+    fn editor_transpose_words(&mut self) {
+        let chars = match self.buffer.rows.get(self.cy) {
+            Some(row) => row.chars.chars().collect::<Vec<char>>(),
+            None => return,
+        };
+        let word_end = |mut i: usize| {
+            while i < chars.len() && is_word_char(chars[i]) {
+                i += 1;
+            }
+            i
+        };
+        let next_word = |mut i: usize| {
+            while i < chars.len() && !is_word_char(chars[i]) {
+                i += 1;
+            }
+            i
+        };
 
-        if self.cy < self.rows.len() {
-            self.rx = self.editor_row_cx_to_rx(&self.rows[self.cy], self.cx);
+        let mut start = self.cx.min(chars.len());
+        while start > 0 && is_word_char(chars[start - 1]) {
+            start -= 1;
         }
-
-        if self.cy < self.rowoff {
-            self.rowoff = self.cy;
+        let start = next_word(start);
+        let end = word_end(start);
+        let next_start = next_word(end);
+        let next_end = word_end(next_start);
+
+        if start == end || next_start == next_end {
+            self.editor_set_status_message("No next word to transpose");
+            return;
         }
 
-        if self.cy >= self.rowoff + self.screenrows {
-            self.rowoff = self.cy - self.screenrows + 1;
-        }
+        let mut swapped = chars[..start].to_vec();
+        swapped.extend_from_slice(&chars[next_start..next_end]);
+        swapped.extend_from_slice(&chars[end..next_start]);
+        swapped.extend_from_slice(&chars[start..end]);
+        swapped.extend_from_slice(&chars[next_end..]);
+        self.buffer.rows[self.cy].chars = swapped.into_iter().collect();
 
-        if self.rx < self.coloff {
-            self.coloff = self.rx;
-        }
+        let cy = self.cy;
+        self.editor_row_changed(cy);
+        self.cx = next_end;
+    }
+
+    fn editor_byte_index(&self, cy: usize, cx: usize) -> usize {
+        self.buffer.byte_index(cy, cx)
+    }
 
-        if self.rx >= self.coloff + self.screencols {
-            self.coloff = self.rx - self.screencols + 1;
+    fn editor_insert_row(&mut self, at: usize, s: &str) {
+        let row = Row::new(s);
+
+        self.buffer.rows.insert(at, row);
+        self.buffer.rows_replaced(at, 0, 1);
+        self.editor_shift_overlays(at, 0, 1);
+        self.editor_shift_marks(at, 0, 1);
+        self.editor_row_changed(at);
+    }
+
+    /// Deletes the character before the cursor, joining the row onto the one above when the
+    /// cursor is at its start.
+    fn editor_del_char(&mut self) {
+        if self.cy == self.buffer.rows.len() || (self.cx == 0 && self.cy == 0) {
+            return;
         }
+
+        let end = self.editor_cursor_position();
+        let start = match self.cx {
+            0 => {
+                let cx = self.buffer.rows[self.cy - 1].chars.chars().count();
+                Position { cx, cy: self.cy - 1 }
+            }
+            cx => Position { cx: cx - 1, cy: self.cy },
+        };
+        self.editor_delete_range(start, end);
+    }
+
+    /// Deletes the character under the cursor, joining the next row onto this one when the
+    /// cursor is at its end.
+    fn editor_del_char_forward(&mut self) {
+        let start = self.editor_cursor_position();
+        let end = if start.cy >= self.buffer.rows.len() {
+            return;
+        } else if start.cx < self.editor_row_len(start.cy) {
+            Position { cx: start.cx + 1, cy: start.cy }
+        } else if start.cy + 1 < self.buffer.rows.len() {
+            Position { cx: 0, cy: start.cy + 1 }
+        } else {
+            return;
+        };
+        self.editor_delete_range(start, end);
     }
 
-    fn editor_draw_rows(&self, buffer: &mut String) {
-        for y in 0..self.screenrows {
-            let filerow = y + self.rowoff;
-            if filerow >= self.rows.len() {
-                if self.rows.is_empty() && y == self.screenrows / 3 {
-                    let mut welcome = match KILO_VERSION {
-                        Some(version) => format!("Kilo editor -- version {}", version),
-                        None => "Kilo editor".to_string(),
-                    };
+    /// Inserts a tab, or with `expandtab` the spaces up to the next indentation level.
+    fn editor_insert_tab(&mut self) {
+        if !self.config.expandtab {
+            self.editor_insert_char('\t');
+            return;
+        }
 
-                    welcome.truncate(self.screencols);
+        let rx = match self.buffer.rows.get(self.cy) {
+            Some(row) => self.editor_row_cx_to_rx(row, self.cx),
+            None => 0,
+        };
+        let spaces = expand_tabs("\t", rx, self.config.indent_width());
+        self.editor_insert_str(&spaces);
+    }
 
-                    let mut padding = (self.screencols - welcome.len()) / 2;
+    fn editor_insert_newline(&mut self) {
+        if self.cy == self.buffer.rows.len() {
+            self.editor_append_row("");
+        }
 
-                    if padding > 0 {
-                        buffer.push('~');
-                        padding -= 1;
-                    }
+        let at = self.editor_byte_index(self.cy, self.cx);
+        let tail = self.buffer.rows[self.cy].chars.split_off(at);
 
-                    for _ in 0..padding {
-                        buffer.push(' ');
-                    }
+        let cy = self.cy;
+        self.editor_row_changed(cy);
+        self.editor_insert_row(cy + 1, &tail);
+        self.cy += 1;
+        self.cx = 0;
+    }
 
-                    buffer.push_str(&welcome);
-                } else {
-                    buffer.push('~');
-                }
-            } else {
-                let line = &self.rows[filerow].render;
-                let mut len = line.len().saturating_sub(self.coloff);
-                if len > self.screencols {
-                    len = self.screencols;
-                }
-                if len > 0 {
-                    buffer.push_str(&line[(self.coloff)..(self.coloff + len)]);
-                }
+    /// Inserts `text` at the cursor and leaves the cursor after it. Text of several lines
+    /// replaces the cursor's row with all of its rows at once, so that pasting thousands of
+    /// lines stays quick.
+    fn editor_insert_str(&mut self, text: &str) {
+        if !text.contains('\n') {
+            if text.is_empty() {
+                return;
             }
+            if self.cy == self.buffer.rows.len() {
+                self.editor_append_row("");
+            }
+            let at = self.editor_byte_index(self.cy, self.cx);
+            self.buffer.rows[self.cy].chars.insert_str(at, text);
 
-            buffer.push_str("\x1b[K");
-            buffer.push_str("\r\n");
+            let cy = self.cy;
+            self.editor_row_changed(cy);
+            self.cx += text.chars().count();
+            return;
         }
-    }
 
-    fn editor_draw_status_bar(&self, buffer: &mut String) {
-        buffer.push_str("\x1b[7m");
-        let mut status = format!("{:.20} - {} lines", self.filename, self.rows.len());
-        status.truncate(self.screencols);
-        let rstatus = format!("{}/{}", self.cy + 1, self.rows.len());
-        let mut len = status.len();
-        buffer.push_str(&status);
-        while len < self.screencols {
-            if self.screencols - len == rstatus.len() {
-                buffer.push_str(&rstatus);
-                break;
-            } else {
-                buffer.push_str(" ");
-                len += 1;
+        let cy = self.cy;
+        let end = cmp::min(cy + 1, self.buffer.rows.len());
+        let (head, tail) = match self.buffer.rows.get(cy) {
+            Some(row) => {
+                let at = self.editor_byte_index(cy, self.cx);
+                (row.chars[..at].to_string(), row.chars[at..].to_string())
             }
+            None => (String::new(), String::new()),
+        };
+
+        let mut lines = text.split('\n').map(String::from).collect::<Vec<String>>();
+        let last = lines.len() - 1;
+        let cx = lines[last].chars().count();
+        lines[0].insert_str(0, &head);
+        lines[last].push_str(&tail);
+
+        self.editor_replace_rows(cy, end, &lines);
+        self.cy = cy + last;
+        self.cx = cx;
+    }
+
+    /// Kills from the cursor to the end of the line, or the newline when already there.
+    fn editor_kill_to_eol(&mut self, append: bool, register: Option<char>) {
+        if self.cy >= self.buffer.rows.len() {
+            return;
         }
-        buffer.push_str("\x1b[m");
-        buffer.push_str("\r\n");
+
+        let at = self.editor_byte_index(self.cy, self.cx);
+        let killed = if at < self.buffer.rows[self.cy].chars.len() {
+            self.buffer.rows[self.cy].chars.split_off(at)
+        } else if self.cy + 1 < self.buffer.rows.len() {
+            let next = self.buffer.rows[self.cy + 1].chars.clone();
+            self.buffer.rows[self.cy].chars.push_str(&next);
+            self.editor_del_row(self.cy + 1);
+            "\n".to_string()
+        } else {
+            return;
+        };
+
+        let cy = self.cy;
+        self.editor_row_changed(cy);
+        self.editor_kill(killed, append, register);
     }
 
-    fn editor_draw_message_bar(&mut self, buffer: &mut String) {
-        buffer.push_str("\x1b[K");
-        self.statusmsg.truncate(self.screencols);
-        if self.statusmsg_time.elapsed() < Duration::from_secs(5) {
-            buffer.push_str(&self.statusmsg);
+    fn editor_kill_line(&mut self, append: bool, register: Option<char>) {
+        if self.cy >= self.buffer.rows.len() {
+            return;
+        }
+
+        let mut killed = self.buffer.rows[self.cy].chars.clone();
+        killed.push('\n');
+
+        let cy = self.cy;
+        self.editor_del_row(cy);
+        if cy < self.buffer.rows.len() {
+            self.editor_row_changed(cy);
+        } else {
+            self.conflicts = find_conflicts(&self.buffer.rows);
+            self.editor_text_changed();
         }
+        self.cx = 0;
+        self.editor_kill(killed, append, register);
     }
 
-    fn editor_refresh_screen(&mut self) -> io::Result<()> {
-        self.editor_scroll();
+    /// The selected text as ordered positions, if a selection has been started.
+    fn editor_selection(&self) -> Option<(Position, Position)> {
+        let anchor = self.anchor?;
+        let pos = self.editor_cursor_position();
 
-        let mut buffer = String::new();
+        let anchor = Position {
+            cy: anchor.cy.min(self.buffer.rows.len()),
+            cx: anchor.cx,
+        };
 
-        buffer.push_str("\x1b[?25l");
-        buffer.push_str("\x1b[H");
+        if (anchor.cy, anchor.cx) <= (pos.cy, pos.cx) {
+            Some((anchor, pos))
+        } else {
+            Some((pos, anchor))
+        }
+    }
 
-        self.editor_draw_rows(&mut buffer);
-        self.editor_draw_status_bar(&mut buffer);
-        self.editor_draw_message_bar(&mut buffer);
+    /// The range of rows touched by the selection; a selection ending at the start of a row
+    /// doesn't include that row.
+    fn editor_selected_rows(&self) -> Option<(usize, usize)> {
+        let (first, last) = self.editor_selection()?;
 
-        buffer.push_str(&format!(
-            "\x1b[{};{}H",
-            (self.cy - self.rowoff) + 1,
-            (self.rx - self.coloff) + 1
-        ));
-        buffer.push_str("\x1b[?25h");
+        let end = if last.cx == 0 && last.cy > first.cy { last.cy } else { last.cy + 1 };
+        Some((first.cy, end.min(self.buffer.rows.len())))
+    }
 
-        io::stdout().write(buffer.as_bytes())?;
-        io::stdout().flush()?;
+    /// The rows `top..bottom` and display columns `left..right` of a block selection, so that
+    /// the block stays straight across rows indented with tabs.
+    fn editor_block(&self) -> Option<(usize, usize, usize, usize)> {
+        let anchor = self.anchor?;
+        if !self.block || self.buffer.rows.is_empty() {
+            return None;
+        }
 
-        Ok(())
+        let top = anchor.cy.min(self.cy).min(self.buffer.rows.len() - 1);
+        let bottom = anchor.cy.max(self.cy).min(self.buffer.rows.len() - 1) + 1;
+        let col = |pos: Position| match self.buffer.rows.get(pos.cy) {
+            Some(row) => self.editor_row_cx_to_rx(row, pos.cx),
+            None => 0,
+        };
+        let (from, to) = (col(anchor), col(self.editor_cursor_position()));
+        Some((top, bottom, from.min(to), from.max(to)))
     }
 
-    fn editor_set_status_message(&mut self, message: &str) {
-        self.statusmsg = message.to_string();
-        self.statusmsg_time = Instant::now();
+    /// The characters `from..to` of `row` drawn in display columns `left..right`, and the
+    /// display column at which `from` starts.
+    fn editor_block_span(&self, row: &Row, left: usize, right: usize) -> (usize, usize, usize) {
+        let from = self.editor_row_rx_to_cx(row, left);
+        let to = self.editor_row_rx_to_cx(row, right);
+        (from, to, self.editor_row_cx_to_rx(row, from))
     }
 
-    fn editor_move_cursor(&mut self, key: EditorKey) {
-        let row = self.rows.get(self.cy);
+    fn editor_toggle_selection(&mut self, block: bool) {
+        if self.anchor.take().is_none() {
+            self.anchor = Some(self.editor_cursor_position());
+            self.block = block;
+            self.editor_set_status_message(if block {
+                "Block selection started"
+            } else {
+                "Selection started"
+            });
+        }
+    }
 
-        match key {
-            ArrowLeft => {
-                if self.cx != 0 {
-                    self.cx -= 1;
-                } else if self.cy > 0 {
-                    self.cy -= 1;
-                    self.cx = self.rows[self.cy].chars.len();
-                }
-            }
-            ArrowRight => {
-                if let Some(r) = row {
-                    if self.cx < r.chars.len() {
-                        self.cx += 1;
-                    } else if self.cx == r.chars.len() {
-                        self.cy += 1;
-                        self.cx = 0;
-                    }
-                }
-            }
-            ArrowUp => {
-                if self.cy != 0 {
-                    self.cy -= 1;
-                }
+    /// Copies the block selection into the rectangle, replacing it on each row with `text`
+    /// when given (an empty string cuts it).
+    fn editor_edit_block(&mut self, replacement: Option<&str>) {
+        let (top, bottom, left, right) = match self.editor_block() {
+            Some(block) => block,
+            None => return,
+        };
+
+        let mut rectangle = Vec::new();
+        let mut lines = Vec::new();
+        let mut cx = 0;
+
+        for (cy, row) in self.buffer.rows[top..bottom].iter().enumerate() {
+            let chars = row.chars.chars().collect::<Vec<char>>();
+            let (from, to, rx) = self.editor_block_span(row, left, right);
+            rectangle.push(chars[from..to].iter().collect::<String>());
+
+            let mut line = chars[..from].iter().collect::<String>();
+            if let Some(text) = replacement.filter(|text| !text.is_empty()) {
+                line.extend(iter::repeat_n(' ', left - rx));
+                line.push_str(text);
             }
-            ArrowDown => {
-                if self.cy < self.rows.len() {
-                    self.cy += 1;
-                }
+            if cy == 0 {
+                cx = line.chars().count();
             }
-            _ => {}
+            line.extend(&chars[to..]);
+            lines.push(line);
+        }
+
+        self.clipboard.rectangle = rectangle;
+        if replacement.is_some() {
+            self.editor_replace_rows(top, bottom, &lines);
         }
 
-        let row = self.rows.get(self.cy);
-        let rowlen = if let Some(r) = row { r.chars.len() } else { 0 };
+        self.anchor = None;
+        self.editor_move_to(Position { cx, cy: top });
+    }
+
+    /// Inserts the last rectangle at the cursor column on consecutive rows.
+    fn editor_yank_rectangle(&mut self) {
+        if self.clipboard.rectangle.is_empty() {
+            self.editor_set_status_message("No rectangle to yank");
+            return;
+        }
 
-        if self.cx > rowlen {
-            self.cx = rowlen;
+        let top = self.cy;
+        let left = match self.buffer.rows.get(top) {
+            Some(row) => self.editor_row_cx_to_rx(row, self.cx),
+            None => 0,
+        };
+        let bottom = top + self.clipboard.rectangle.len();
+        while self.buffer.rows.len() < bottom {
+            let at = self.buffer.rows.len();
+            self.editor_insert_row(at, "");
         }
+
+        let lines = self.buffer.rows[top..bottom]
+            .iter()
+            .zip(&self.clipboard.rectangle)
+            .map(|(row, text)| {
+                let chars = row.chars.chars().collect::<Vec<char>>();
+                let (at, _, rx) = self.editor_block_span(row, left, left);
+                let mut line = chars[..at].iter().collect::<String>();
+                line.extend(iter::repeat_n(' ', left - rx));
+                line.push_str(text);
+                line.extend(&chars[at..]);
+                line
+            })
+            .collect::<Vec<String>>();
+
+        self.editor_replace_rows(top, bottom, &lines);
     }
 
-    fn editor_process_keypress(&mut self) -> io::Result<bool> {
-        let c = self.editor_read_key()?;
+    /// Replaces the block selection on every row with prompted text; a zero-width block
+    /// inserts it, e.g. to prefix a run of lines.
+    fn editor_block_insert(&mut self) -> io::Result<()> {
+        if self.editor_block().is_none() {
+            self.editor_set_status_message("No block selection");
+            return Ok(());
+        }
 
-        match c {
-            Char(c) if c == ctrl_key('q') => return Ok(false),
-            HomeKey => self.cx = 0,
-            EndKey => {
-                if self.cy < self.rows.len() {
-                    self.cx = self.rows[self.cy].chars.len();
-                }
-            }
-            PageUp | PageDown => {
-                if c == PageUp {
-                    self.cy = self.rowoff;
-                } else if c == PageDown {
-                    self.cy = self.rowoff + self.screenrows - 1;
-                    if self.cy > self.rows.len() {
-                        self.cy = self.rows.len();
-                    }
+        if let Some(text) = self.editor_prompt("Insert on each line: {}", "insert")? {
+            let text = match (self.config.expandtab, self.editor_block()) {
+                (true, Some((_, _, left, _))) => {
+                    expand_tabs(&text, left, self.config.indent_width())
                 }
-                for _ in 0..self.screenrows {
-                    self.editor_move_cursor(if c == PageUp { ArrowUp } else { ArrowDown });
-                }
-            }
-            ArrowUp | ArrowDown | ArrowLeft | ArrowRight => self.editor_move_cursor(c),
-            _ => {}
+                _ => text,
+            };
+            self.editor_edit_block(Some(&text));
         }
 
-        Ok(true)
+        Ok(())
     }
 
-    fn init_editor(&mut self) -> io::Result<()> {
-        let (screenrows, screencols) = self.get_window_size()?;
+    fn editor_text_range(&self, start: Position, end: Position) -> String {
+        self.buffer.text_range(start, end)
+    }
 
-        self.screenrows = screenrows - 2;
-        self.screencols = screencols;
+    fn editor_copy_selection(&mut self, register: Option<char>) {
+        if self.block {
+            self.editor_edit_block(None);
+            self.editor_set_status_message("Copied rectangle");
+            return;
+        }
 
-        Ok(())
+        if let Some((first, last)) = self.editor_selection() {
+            let text = self.editor_text_range(first, last);
+            self.editor_kill(text, false, register);
+            self.anchor = None;
+            self.editor_set_status_message("Copied selection");
+        }
     }
 
-    pub fn run(mut self) -> io::Result<()> {
-        self.enable_raw_mode()?;
-        self.init_editor()?;
+    fn editor_kill_selection(&mut self, register: Option<char>) {
+        if self.block && self.anchor.is_some() {
+            self.editor_edit_block(Some(""));
+            return;
+        }
+
+        let (first, last) = match self.editor_selection() {
+            Some(selection) if selection.0.cy < self.buffer.rows.len() => selection,
+            _ => {
+                self.editor_set_status_message("No selection");
+                return;
+            }
+        };
+
+        let text = self.editor_text_range(first, last);
+        self.editor_delete_selection(first, last);
+        self.editor_kill(text, false, register);
+    }
 
-        let mut argv = std::env::args();
-        argv.next();
+    fn editor_delete_selection(&mut self, first: Position, mut last: Position) {
+        if last.cy >= self.buffer.rows.len() {
+            last = Position {
+                cy: self.buffer.rows.len() - 1,
+                cx: self.buffer.rows[self.buffer.rows.len() - 1].chars.chars().count(),
+            };
+        }
+        self.editor_delete_range(first, last);
+        self.anchor = None;
+    }
 
-        if let Some(filename) = argv.next() {
-            self.editor_open(&filename)?;
+    /// Replaces rows `start..end` with `lines`, updating highlighting and markers once.
+    fn editor_replace_rows(&mut self, start: usize, end: usize, lines: &[String]) {
+        let rows = lines
+            .iter()
+            .map(|line| Row {
+                render: self.editor_render(line),
+                ..Row::new(line)
+            })
+            .collect::<Vec<Row>>();
+        self.buffer.rows.splice(start..end, rows);
+        self.buffer.rows_replaced(start, end - start, lines.len());
+        let kept = cmp::min(end - start, lines.len());
+        self.editor_shift_overlays(start + kept, end - start - kept, lines.len() - kept);
+        self.editor_shift_marks(start + kept, end - start - kept, lines.len() - kept);
+
+        for at in start..(start + lines.len() + 1).min(self.buffer.rows.len()) {
+            self.editor_update_syntax(at);
         }
 
-        self.editor_set_status_message("HELP: Ctrl-Q = quit");
+        self.dirty = true;
+        self.conflicts = find_conflicts(&self.buffer.rows);
+        self.editor_text_changed();
+    }
 
-        loop {
-            self.editor_refresh_screen()?;
-            if !self.editor_process_keypress()? {
-                break;
+    /// Sorts or deduplicates the selected rows, or the whole buffer without a selection.
+    fn editor_sort_lines(&mut self) -> io::Result<()> {
+        let prompt = "Sort lines: (a)scending (d)escending (n/N)umeric (u)nique";
+        let mode = match self.editor_read_mark_name(prompt)? {
+            Some(mode) if "adnNu".contains(mode) => mode,
+            _ => return Ok(()),
+        };
+
+        let (start, end) = self.editor_selected_rows().unwrap_or((0, self.buffer.rows.len()));
+        let mut lines = self.buffer.rows[start..end]
+            .iter()
+            .map(|row| row.chars.clone())
+            .collect::<Vec<String>>();
+        let count = lines.len();
+
+        match mode {
+            'a' => lines.sort(),
+            'd' => lines.sort_by(|a, b| b.cmp(a)),
+            'u' => lines.dedup(),
+            _ => {
+                let number = |line: &String| {
+                    let line = line.trim_start();
+                    let end = line
+                        .char_indices()
+                        .find(|&(i, c)| !(c.is_ascii_digit() || c == '.' || (i == 0 && c == '-')))
+                        .map_or(line.len(), |(i, _)| i);
+                    line[..end].parse::<f64>().ok()
+                };
+                lines.sort_by(|a, b| {
+                    number(a).partial_cmp(&number(b)).unwrap_or(cmp::Ordering::Equal)
+                });
+                if mode == 'N' {
+                    lines.reverse();
+                }
             }
         }
 
-        clear_screen()?;
+        let removed = count - lines.len();
+        self.editor_replace_rows(start, end, &lines);
+        self.anchor = None;
+        self.editor_move_to(Position { cx: 0, cy: start });
+
+        match removed {
+            0 => self.editor_set_status_message(&format!("Sorted {} lines", count)),
+            n => self.editor_set_status_message(&format!("Removed {} duplicate lines", n)),
+        }
 
         Ok(())
     }
+
+    /// Aligns the selected rows, or the block of rows around the cursor containing the
+    /// delimiter, on a prompted delimiter.
+    fn editor_align(&mut self) -> io::Result<()> {
+        let delimiter = match self.editor_prompt("Align on: {}", "align")? {
+            Some(ref d) if !d.is_empty() => d.clone(),
+            _ => return Ok(()),
+        };
+
+        let (start, end) = match self.editor_selected_rows() {
+            Some(rows) => rows,
+            None => {
+                let has = |row: &Row| row.chars.contains(&delimiter);
+                if !self.buffer.rows.get(self.cy).is_some_and(has) {
+                    self.editor_set_status_message(&format!("No '{}' on this line", delimiter));
+                    return Ok(());
+                }
+                let rows = &self.buffer.rows;
+                let start = (0..self.cy).rev().find(|&i| !has(&rows[i])).map_or(0, |i| i + 1);
+                let end = (self.cy..self.buffer.rows.len()).find(|&i| !has(&self.buffer.rows[i]));
+                (start, end.unwrap_or(self.buffer.rows.len()))
+            }
+        };
+
+        let lines = self.buffer.rows[start..end]
+            .iter()
+            .map(|row| row.chars.clone())
+            .collect::<Vec<String>>();
+        let aligned = align_lines(&lines, &delimiter);
+
+        self.editor_replace_rows(start, end, &aligned);
+        self.anchor = None;
+        let pos = self.editor_cursor_position();
+        self.editor_move_to(pos);
+        self.editor_set_status_message(&format!("Aligned {} lines", end - start));
+
+        Ok(())
+    }
+
+    /// Comments out the selected rows, or the cursor's row, with the filetype's line comment
+    /// prefix, or uncomments them if they all have it.
+    fn editor_toggle_comment(&mut self) {
+        let syntaxes = &self.highlight.syntaxes;
+        let prefix = match self.highlight.syntax.map(|i| &syntaxes[i].singleline_comment_start) {
+            Some(prefix) if !prefix.is_empty() => prefix.clone(),
+            _ => {
+                self.editor_set_status_message("No line comments in this filetype");
+                return;
+            }
+        };
+        let (start, end) = match self.editor_selected_rows() {
+            Some(rows) => rows,
+            None if self.cy < self.buffer.rows.len() => (self.cy, self.cy + 1),
+            None => return,
+        };
+
+        let lines = self.buffer.rows[start..end]
+            .iter()
+            .map(|row| row.chars.clone())
+            .collect::<Vec<String>>();
+        let toggled = toggle_comment_lines(&lines, &prefix);
+
+        // The cursor and the anchor stay on the same text where it moved sideways.
+        let shift = |pos: Position| match pos.cy.checked_sub(start).filter(|&i| i < lines.len()) {
+            Some(i) => {
+                let (old, new) = (lines[i].chars().count(), toggled[i].chars().count());
+                let indent = old - lines[i].trim_start().chars().count();
+                match pos.cx > indent {
+                    true => Position { cx: (pos.cx + new).saturating_sub(old).max(indent), ..pos },
+                    false => pos,
+                }
+            }
+            None => pos,
+        };
+        let pos = shift(self.editor_cursor_position());
+        self.anchor = self.anchor.map(shift);
+
+        self.editor_replace_rows(start, end, &toggled);
+        self.editor_move_to(pos);
+    }
+
+    /// Re-wraps the selected rows, or the paragraph at the cursor, to `text_width` columns.
+    /// A paragraph is a run of non-blank lines sharing the cursor line's comment leader.
+    fn editor_reflow(&mut self) {
+        let (start, end) = match self.editor_selected_rows() {
+            Some(rows) => rows,
+            None => {
+                let line = match self.buffer.rows.get(self.cy) {
+                    Some(row) => row.chars.clone(),
+                    None => return,
+                };
+                let leader = line_leader(&line).trim_end();
+                let same = |row: &Row| {
+                    let own = line_leader(&row.chars);
+                    own.trim_end() == leader && own.len() < row.chars.len()
+                };
+
+                if !same(&self.buffer.rows[self.cy]) {
+                    self.editor_set_status_message("Not in a paragraph");
+                    return;
+                }
+                let rows = &self.buffer.rows;
+                let start = (0..self.cy).rev().find(|&i| !same(&rows[i])).map_or(0, |i| i + 1);
+                let end = (self.cy..self.buffer.rows.len()).find(|&i| !same(&self.buffer.rows[i]));
+                (start, end.unwrap_or(self.buffer.rows.len()))
+            }
+        };
+
+        let lines = self.buffer.rows[start..end]
+            .iter()
+            .map(|row| row.chars.clone())
+            .collect::<Vec<String>>();
+        let leader = lines.first().map_or("", |line| line_leader(line)).to_string();
+        let wrapped = reflow_lines(&lines, &leader, self.config.text_width);
+
+        self.editor_replace_rows(start, end, &wrapped);
+        self.anchor = None;
+        let last = (start + wrapped.len()).saturating_sub(1);
+        let cx = self.buffer.rows.get(last).map_or(0, |row| row.chars.chars().count());
+        self.editor_move_to(Position { cx, cy: last });
+    }
+
+    /// Copies `count` lines starting at the cursor line without removing them.
+    fn editor_copy_lines(&mut self, count: usize, register: Option<char>) {
+        if self.cy >= self.buffer.rows.len() {
+            return;
+        }
+
+        let mut copied = String::new();
+        for row in self.buffer.rows.iter().skip(self.cy).take(count) {
+            copied.push_str(&row.chars);
+            copied.push('\n');
+        }
+
+        let lines = copied.matches('\n').count();
+        self.editor_kill(copied, false, register);
+        self.editor_set_status_message(&format!("Copied {} line(s)", lines));
+    }
+
+    /// Stores killed text in `register`, or the kill ring when none was given. An uppercase
+    /// register name appends to the lowercase register, as in vi.
+    fn editor_kill(&mut self, text: String, append: bool, register: Option<char>) {
+        if let Some(name) = register {
+            let entry = self.clipboard.registers.entry(name.to_ascii_lowercase()).or_default();
+            if !(append || name.is_ascii_uppercase()) {
+                entry.clear();
+            }
+            entry.push_str(&text);
+            return;
+        }
+
+        match self.clipboard.kill_ring.last_mut() {
+            Some(last) if append => last.push_str(&text),
+            _ => self.clipboard.kill_ring.push(text),
+        }
+
+        if self.clipboard.kill_ring.len() > KILO_KILL_RING_SIZE {
+            self.clipboard.kill_ring.remove(0);
+        }
+    }
+
+    /// Deletes the text between two positions, `start` being the earlier one.
+    fn editor_delete_range(&mut self, start: Position, end: Position) {
+        let head = self.editor_byte_index(start.cy, start.cx);
+        let tail_at = self.editor_byte_index(end.cy, end.cx);
+        let tail = self.buffer.rows[end.cy].chars[tail_at..].to_string();
+
+        self.buffer.rows[start.cy].chars.truncate(head);
+        self.buffer.rows[start.cy].chars.push_str(&tail);
+
+        // Marks and jumps in the text after the deletion move up with it.
+        let jumps = &mut self.jumps;
+        let positions = jumps.marks.values_mut().chain(&mut jumps.list);
+        for pos in positions.chain(&mut jumps.last_position) {
+            if end.cy > start.cy && pos.cy == end.cy && pos.cx >= end.cx {
+                *pos = Position { cx: start.cx + pos.cx - end.cx, cy: start.cy };
+            }
+        }
+        for _ in start.cy..end.cy {
+            self.editor_del_row(start.cy + 1);
+        }
+
+        self.editor_row_changed(start.cy);
+        self.editor_move_to(start);
+    }
+
+    /// Inserts kill ring entry `index` and remembers where, so Alt-Y can replace it.
+    fn editor_yank_entry(&mut self, index: usize) {
+        let start = self.editor_cursor_position();
+        let text = self.clipboard.kill_ring[index].clone();
+
+        self.editor_insert_str(&text);
+        self.clipboard.last_yank = Some((start, index));
+    }
+
+    fn editor_yank(&mut self, register: Option<char>) {
+        if let Some(name) = register {
+            match self.clipboard.registers.get(&name.to_ascii_lowercase()).cloned() {
+                Some(text) => self.editor_insert_str(&text),
+                None => self.editor_set_status_message(&format!("Register '{}' is empty", name)),
+            }
+            return;
+        }
+
+        match self.clipboard.kill_ring.len() {
+            0 => self.editor_set_status_message("Nothing to yank"),
+            n => self.editor_yank_entry(n - 1),
+        }
+    }
+
+    fn editor_yank_pop(&mut self, last_yank: Option<(Position, usize)>) -> io::Result<()> {
+        let (start, index) = match last_yank {
+            Some(yank) => yank,
+            None => return self.editor_pick_kill(),
+        };
+
+        let end = self.editor_cursor_position();
+        self.editor_delete_range(start, end);
+
+        let len = self.clipboard.kill_ring.len();
+        let previous = (index + len - 1) % len;
+        self.editor_yank_entry(previous);
+
+        Ok(())
+    }
+
+    fn editor_pick_kill(&mut self) -> io::Result<()> {
+        if self.clipboard.kill_ring.is_empty() {
+            self.editor_set_status_message("Kill ring is empty");
+            return Ok(());
+        }
+
+        let items = self
+            .clipboard.kill_ring
+            .iter()
+            .rev()
+            .map(|text| text.replace('\n', "\\n"))
+            .collect::<Vec<String>>();
+
+        if let Some(i) = self.editor_pick("Kill ring", items)? {
+            let index = self.clipboard.kill_ring.len() - 1 - i;
+            self.editor_yank_entry(index);
+        }
+
+        Ok(())
+    }
+
+    fn editor_toggle_overwrite(&mut self) -> io::Result<()> {
+        self.overwrite = !self.overwrite;
+        self.editor_run_hook("ModeChange")?;
+
+        Ok(())
+    }
+
+    fn editor_append_row(&mut self, s: &str) {
+        let mut row = Row::new(s);
+
+        self.editor_update_row(&mut row);
+        self.buffer.rows.push(row);
+        let at = self.buffer.rows.len() - 1;
+        self.buffer.rows_replaced(at, 0, 1);
+
+        self.editor_update_syntax(at);
+    }
+
+    fn editor_del_row(&mut self, at: usize) {
+        if at >= self.buffer.rows.len() {
+            return;
+        }
+
+        self.buffer.rows.remove(at);
+        self.buffer.rows_replaced(at, 1, 0);
+        self.editor_shift_overlays(at, 1, 0);
+        self.editor_shift_marks(at, 1, 0);
+        if at < self.buffer.rows.len() {
+            self.editor_update_syntax(at);
+        }
+        self.dirty = true;
+    }
+
+    fn editor_open(&mut self, filename: &str) -> io::Result<()> {
+        if let Some(remote) = Remote::parse(filename) {
+            self.editor_fetch_remote(filename, remote);
+            return Ok(());
+        }
+
+        let (bytes, compression) = read_file(filename)?;
+        self.file.remote = None;
+        self.file.remote_fetch = None;
+        self.editor_load(filename, &bytes, compression)
+    }
+
+    /// Warns and returns true if the buffer is read-only, for commands that would change it.
+    pub(crate) fn editor_refuse_if_read_only(&mut self) -> bool {
+        if self.editor_read_only() {
+            let message = format!("{} is read-only", self.filename);
+            self.editor_set_status_message_with(Severity::Warning, &message);
+        }
+
+        self.editor_read_only()
+    }
+
+    /// Whether rendering wraps links in OSC 8 hyperlink escapes.
+    pub(crate) fn editor_hyperlinks(&self) -> bool {
+        self.config.hyperlinks.unwrap_or(self.terminal.hyperlinks)
+    }
+
+    /// Shows `bytes`, the contents of `filename`, as a freshly opened file.
+    fn editor_load(
+        &mut self,
+        filename: &str,
+        bytes: &[u8],
+        compression: Option<Compression>,
+    ) -> io::Result<()> {
+        let contents = decode_bytes(bytes);
+
+        self.filename = filename.to_string();
+        self.file.compression = compression;
+        self.file.disk_mtime = file_mtime(filename);
+        let name = uncompressed_name(filename);
+        self.highlight.syntax = self.highlight.syntaxes.iter().position(|s| s.matches(name));
+        self.buffer.rows.clear();
+        self.buffer.rows_changed();
+        self.cx = 0;
+        self.cy = 0;
+        self.rowoff = 0;
+        self.coloff = 0;
+        self.jumps.marks.clear();
+        self.jumps.last_position = None;
+        self.jumps.list.clear();
+        self.jumps.index = 0;
+        self.editor_set_diagnostics(Vec::new());
+        self.git.status = None;
+        self.git.query = None;
+        self.git.checked = None;
+        self.git.changes.clear();
+        self.comparison = None;
+
+        let filetype = self.editor_syntax_filetype();
+        self.config = self.base_config.scoped(filetype.as_deref(), filename);
+        if self.config.editorconfig && !filename.is_empty() {
+            self.editor_apply_editorconfig();
+        }
+        self.buffer.tabstop = self.config.tabstop;
+        if self.config.spell_check && self.dictionary.is_none() {
+            self.editor_load_dictionary();
+        }
+
+        let (lines, crlf, final_newline) = split_lines(&contents);
+        self.buffer.crlf = crlf;
+        self.buffer.final_newline = final_newline;
+        if self.config.modelines {
+            self.editor_apply_modelines(&lines);
+        }
+
+        let rows = lines.iter().map(|line| Row { stale: true, ..Row::new(line) });
+        self.buffer.rows = rows.collect();
+        self.buffer.rows_changed();
+        self.editor_highlight_rows(0);
+        self.dirty = false;
+        self.conflicts = find_conflicts(&self.buffer.rows);
+
+        let rows = self.buffer.rows.iter();
+        let style = self.buffer.indent_style();
+        let mixed = rows.filter(|r| mixed_indent(&r.chars, style)).count();
+        if self.config.indent_warnings && mixed > 0 {
+            let message =
+                format!("Mixed tabs and spaces on {} lines (Alt-Shift-M jumps to the next)", mixed);
+            self.editor_set_status_message_with(Severity::Warning, &message);
+        }
+
+        // Tools and the stored positions work with local paths only.
+        if self.file.remote.is_none() && !filename.is_empty() {
+            let result = self.editor_record_recent_file();
+            self.editor_state_error("record the recent file", result);
+            let result = self.editor_restore_position();
+            self.editor_state_error("restore the cursor position", result);
+
+            if self.editor_lint_command().is_some() {
+                self.editor_lint();
+            }
+
+            self.editor_start_lsp();
+        }
+
+        self.editor_run_plugins(|plugin, editor| {
+            plugin.on_open(editor);
+            false
+        });
+        self.editor_run_hook("BufOpen")?;
+
+        Ok(())
+    }
+
+    fn editor_rows_to_string(&self) -> String {
+        self.buffer.to_string()
+    }
+
+    /// The cursor's byte offset in the saved file, and the file's total size.
+    fn editor_byte_offset(&self) -> (usize, usize) {
+        let total = self.buffer.byte_len();
+        let before = self.buffer.row_offset(self.cy);
+
+        match self.cy < self.buffer.rows.len() {
+            true => {
+                let at = self.editor_byte_index(self.cy, self.cx);
+                (before + encoded_len(&self.buffer.rows[self.cy].chars[..at]), total)
+            }
+            false => (before, total),
+        }
+    }
+
+    /// Moves to a prompted byte offset, or a character offset with a `c` suffix, counting
+    /// one byte (and character) per newline.
+    fn editor_goto_offset(&mut self) -> io::Result<()> {
+        let prompt = "Go to offset (bytes, or N c for chars): {}";
+        let input = match self.editor_prompt(prompt, "offset")? {
+            Some(input) => input,
+            None => return Ok(()),
+        };
+        let input = input.trim();
+        let (number, chars) = match input.strip_suffix('c') {
+            Some(number) => (number.trim(), true),
+            None => (input, false),
+        };
+        let mut offset = match number.parse::<usize>() {
+            Ok(offset) => offset,
+            Err(_) => {
+                self.editor_set_status_message_with(
+                    Severity::Warning,
+                    &format!("Invalid offset: {}", input),
+                );
+                return Ok(());
+            }
+        };
+
+        let mut pos = Position { cx: 0, cy: self.buffer.rows.len() };
+        if chars {
+            // Only byte offsets are indexed, so this walks the rows, in time linear in the
+            // offset. Line endings count as one character per byte, like a CRLF's two.
+            let ending = self.buffer.line_ending().len();
+            for (cy, row) in self.buffer.rows.iter().enumerate() {
+                let len = row.chars.chars().count();
+                if offset < len + ending {
+                    pos = Position { cx: cmp::min(offset, len), cy };
+                    break;
+                }
+                offset -= len + ending;
+            }
+        } else {
+            let (cy, rest) = self.buffer.row_at_offset(offset);
+            if let Some(row) = self.buffer.rows.get(cy) {
+                let mut end = 0;
+                let sizes = row.chars.chars().map(encoded_char_len);
+                let cx = sizes
+                    .take_while(|&size| {
+                        end += size;
+                        end <= rest
+                    })
+                    .count();
+                pos = Position { cx, cy };
+            }
+        }
+
+        if pos.cy == self.buffer.rows.len() {
+            self.editor_set_status_message_with(
+                Severity::Warning,
+                "Offset is past the end of the file",
+            );
+        }
+        self.editor_jump_to(pos);
+
+        Ok(())
+    }
+
+    fn editor_goto_line(&mut self) -> io::Result<()> {
+        let input = match self.editor_prompt("Go to line (N or N:COL): {}", "line")? {
+            Some(input) => input,
+            None => return Ok(()),
+        };
+
+        let mut parts = input.trim().splitn(2, ':').map(|part| part.trim().parse::<usize>());
+        let line = parts.next().and_then(|line| line.ok());
+        let col = parts.next().unwrap_or(Ok(1));
+
+        match (line, col) {
+            (Some(line), Ok(col)) if line > 0 && col > 0 => {
+                let cy = (line - 1).min(self.buffer.rows.len().saturating_sub(1));
+                self.editor_jump_to(Position { cx: col - 1, cy });
+            }
+            _ => self.editor_set_status_message_with(
+                Severity::Warning,
+                &format!("Invalid line: {}", input),
+            ),
+        }
+
+        Ok(())
+    }
+
+    /// Runs a shell command, with `%` replaced by the file name, and shows its output.
+    fn editor_shell_command(&mut self) -> io::Result<()> {
+        let command = match self.editor_prompt("Shell command: {}", "command")? {
+            Some(ref command) if !command.trim().is_empty() => command.trim().to_string(),
+            _ => return Ok(()),
+        };
+
+        let output = match run_shell(&command.replace('%', &shell_quote(&self.filename)), None) {
+            Ok(output) => output,
+            Err(e) => {
+                self.editor_set_status_message_with(
+                    Severity::Error,
+                    &format!("{}: {}", command, e),
+                );
+                return Ok(());
+            }
+        };
+
+        let text = match output.status.success() {
+            true => String::from_utf8_lossy(&output.stdout).into_owned(),
+            false => String::from_utf8_lossy(&output.stderr).into_owned(),
+        };
+        let lines = text.lines().filter(|line| !line.trim().is_empty()).collect::<Vec<&str>>();
+
+        let message = match (output.status.success(), lines.first()) {
+            (true, Some(line)) if lines.len() > 1 => {
+                format!("{} (+{} more lines)", line, lines.len() - 1)
+            }
+            (true, Some(line)) => line.to_string(),
+            (true, None) => format!("{}: done", command),
+            (false, Some(line)) => format!("{}: {}", command, line),
+            (false, None) => format!("{}: {}", command, output.status),
+        };
+        let severity = if output.status.success() { Severity::Info } else { Severity::Error };
+        self.editor_set_status_message_with(severity, &message);
+
+        Ok(())
+    }
+
+    /// Reports line, word, character and byte counts for the selection or the buffer.
+    fn editor_stats(&mut self) {
+        let (text, scope) = match (self.editor_selection(), self.editor_block()) {
+            (_, Some((top, bottom, left, right))) => {
+                let lines = self.buffer.rows[top..bottom].iter().map(|row| {
+                    let chars = row.chars.chars().collect::<Vec<char>>();
+                    let (from, to, _) = self.editor_block_span(row, left, right);
+                    chars[from..to].iter().collect::<String>() + "\n"
+                });
+                (lines.collect::<String>(), "block")
+            }
+            (Some((first, last)), None) => (self.editor_text_range(first, last), "selection"),
+            (None, None) => (self.editor_rows_to_string(), "buffer"),
+        };
+
+        let mut lines = text.matches('\n').count();
+        if !text.is_empty() && !text.ends_with('\n') {
+            lines += 1;
+        }
+        let (offset, total) = self.editor_byte_offset();
+
+        self.editor_set_status_message(&format!(
+            "{}: {} lines, {} words, {} chars, {} bytes; cursor at byte {} of {}",
+            scope,
+            lines,
+            text.split_whitespace().count(),
+            text.chars().count(),
+            encoded_len(&text),
+            offset,
+            total
+        ));
+    }
+
+    /// Whether to quit. With unsaved changes, this asks whether to save them first, or waits
+    /// for the number of Ctrl-Q presses `quit_confirm` sets. Unnamed buffers quit without
+    /// asking when `quit_confirm_unnamed` is off.
+    fn editor_confirm_quit(&mut self) -> io::Result<bool> {
+        if !self.dirty || (self.filename.is_empty() && !self.config.quit_confirm_unnamed) {
+            return Ok(true);
+        }
+
+        match self.config.quit_confirm {
+            QuitConfirm::Prompt => {
+                let question = format!("Save changes to {} before quitting?", self.editor_name());
+                match self.editor_confirm(&question, false)? {
+                    Answer::Yes => {
+                        self.editor_save()?;
+                        Ok(!self.dirty)
+                    }
+                    Answer::No => Ok(true),
+                    Answer::All | Answer::Cancel => Ok(false),
+                }
+            }
+            QuitConfirm::Presses(times) => {
+                self.quit_presses += 1;
+                let message = match times.saturating_sub(self.quit_presses) {
+                    0 => return Ok(true),
+                    1 => format!(
+                        "{} has unsaved changes: press Ctrl-Q again to quit",
+                        self.editor_name()
+                    ),
+                    n => format!(
+                        "{} has unsaved changes: press Ctrl-Q {} more times to quit",
+                        self.editor_name(),
+                        n
+                    ),
+                };
+                self.editor_set_status_message_with(Severity::Warning, &message);
+                Ok(false)
+            }
+        }
+    }
+
+    fn editor_save(&mut self) -> io::Result<()> {
+        if self.file.remote_fetch.is_some() {
+            let message = format!("Can't save {} before it has been fetched", self.filename);
+            self.editor_set_status_message_with(Severity::Warning, &message);
+            return Ok(());
+        }
+
+        // A read-only buffer can still be saved as a local copy.
+        if self.filename.is_empty() || self.editor_read_only() {
+            let name = match self.editor_prompt("Save as: {}", "file")? {
+                Some(ref name) if !name.trim().is_empty() => name.trim().to_string(),
+                _ => {
+                    self.editor_set_status_message("Save aborted");
+                    return Ok(());
+                }
+            };
+
+            let question = format!("{} already exists. Overwrite it?", name);
+            if Path::new(&name).exists() && self.editor_confirm(&question, false)? != Answer::Yes {
+                self.editor_set_status_message("Save aborted");
+                return Ok(());
+            }
+            self.editor_set_filename(&name);
+        } else if self.file.disk_mtime.is_some()
+            && file_mtime(&self.filename) != self.file.disk_mtime
+        {
+            let name = &self.filename;
+            let question = format!("{} changed on disk since it was read. Overwrite it?", name);
+            if self.editor_confirm(&question, false)? != Answer::Yes {
+                self.editor_set_status_message("Save aborted");
+                return Ok(());
+            }
+        }
+
+        if !self.editor_run_hook("BufWritePre")? {
+            return Ok(());
+        }
+
+        // Without insert_final_newline a file that lacked a final newline is saved without one.
+        // With it, trailing blank lines are left out of the file but kept in the buffer.
+        let mut note = "";
+        let mut contents = if self.config.insert_final_newline {
+            let len = self.buffer.rows.len();
+            let rows = self.buffer.rows.iter().rev();
+            let blank = rows.take_while(|row| row.chars.is_empty()).count();
+            let blank = blank.min(len.saturating_sub(1));
+            if blank > 0 {
+                note = " (removed trailing blank lines)";
+            } else if !self.buffer.final_newline && len > 0 {
+                note = " (added final newline)";
+            }
+            self.buffer.encode_rows(len - blank, true)
+        } else {
+            self.buffer.to_bytes()
+        };
+        let mut format = String::new();
+        if let Some(compression) = self.file.compression {
+            contents = match compression.compress(&contents) {
+                Ok(compressed) => compressed,
+                Err(e) => {
+                    self.editor_set_status_message_with(
+                        Severity::Error,
+                        &format!("Can't save! {}", e),
+                    );
+                    return Ok(());
+                }
+            };
+            format = format!(" as {}", compression.name());
+        }
+
+        let written = match self.file.remote {
+            Some(ref remote) => remote.write(&contents),
+            None => fs::write(&self.filename, &contents),
+        };
+        let written = match written {
+            Err(ref e) if e.kind() == ErrorKind::PermissionDenied && self.file.remote.is_none() => {
+                let question = format!(
+                    "Permission denied writing {}. Retry with elevated privileges?",
+                    self.filename
+                );
+                match self.editor_confirm(&question, false)? {
+                    Answer::Yes => self.editor_write_privileged(&contents),
+                    _ => written,
+                }
+            }
+            written => written,
+        };
+        match written {
+            Ok(()) => {
+                self.dirty = false;
+                if self.config.insert_final_newline {
+                    self.buffer.final_newline = true;
+                }
+                self.file.disk_mtime = file_mtime(&self.filename);
+                self.git.checked = None;
+                let place = match self.file.remote {
+                    Some(ref remote) => remote.host().to_string(),
+                    None => "disk".to_string(),
+                };
+                let message = format!(
+                    "{} bytes written to {}{}{}",
+                    contents.len(),
+                    place,
+                    format,
+                    note
+                );
+                self.editor_set_status_message(&message);
+                self.editor_sync_lsp();
+                if let Some(Err(e)) = self.lsp.as_mut().map(|client| client.did_save()) {
+                    let message = format!("LSP error: {}", e);
+                    self.editor_set_status_message_with(Severity::Error, &message);
+                }
+                self.editor_run_plugins(|plugin, editor| {
+                    plugin.on_save(editor);
+                    false
+                });
+                self.editor_run_hook("BufWritePost")?;
+            }
+            Err(e) => self.editor_set_status_message_with(
+                Severity::Error,
+                &format!("Can't save! I/O error: {}", e),
+            ),
+        }
+
+        Ok(())
+    }
+
+    /// Runs `command` with `input` on stdin, leaving raw mode while it has the terminal.
+    fn editor_run_suspended(&mut self, command: &str, input: &[u8]) -> io::Result<ExitStatus> {
+        self.disable_raw_mode()?;
+        clear_screen()?;
+        let status = run_with_terminal(command, input);
+        self.enable_raw_mode()?;
+        // Whatever the command printed is gone after the next frame, so redraw all of it.
+        self.frame.lines.clear();
+
+        status
+    }
+
+    /// Opens the URL under the cursor with the desktop's handler for it.
+    fn editor_open_url(&mut self) -> io::Result<()> {
+        let url = self.buffer.rows.get(self.cy).and_then(|row| {
+            let chars = row.chars.chars().collect::<Vec<char>>();
+            let cx = self.cx;
+            let mut urls = find_urls(&chars).into_iter();
+            let found = urls.find(|&(start, len)| start <= cx && cx <= start + len);
+            found.map(|(start, len)| chars[start..start + len].iter().collect::<String>())
+        });
+        let url = match url {
+            Some(url) => url,
+            None => {
+                self.editor_set_status_message("No URL under the cursor");
+                return Ok(());
+            }
+        };
+
+        let opener = if cfg!(target_os = "macos") { "open" } else { "xdg-open" };
+        let command = format!("{} {}", opener, shell_quote(&url));
+        let (severity, message) = match self.editor_run_suspended(&command, &[])? {
+            status if status.success() => (Severity::Info, format!("Opened {}", url)),
+            status => {
+                let message = format!("Can't open {}: {} failed ({})", url, opener, status);
+                (Severity::Error, message)
+            }
+        };
+        self.editor_set_status_message_with(severity, &message);
+
+        Ok(())
+    }
+
+    /// Writes `contents` to the file through `sudo_command`, which gets the terminal in its
+    /// normal mode in case it asks for a password.
+    fn editor_write_privileged(&mut self, contents: &[u8]) -> io::Result<()> {
+        let command = self.config.sudo_command.replace('%', &shell_quote(&self.filename));
+
+        match self.editor_run_suspended(&command, contents)? {
+            status if status.success() => Ok(()),
+            status => Err(io::Error::other(format!("`{}` failed ({})", command, status))),
+        }
+    }
+
+    fn editor_revert(&mut self) -> io::Result<()> {
+        if self.filename.is_empty() {
+            self.editor_set_status_message("No file name");
+            return Ok(());
+        }
+
+        if self.dirty {
+            let question = format!("Discard unsaved changes to {}?", self.filename);
+            if self.editor_confirm(&question, false)? != Answer::Yes {
+                return Ok(());
+            }
+        }
+
+        self.editor_reload()
+    }
+
+    /// Reads the file again, keeping the cursor where it was.
+    fn editor_reload(&mut self) -> io::Result<()> {
+        let pos = self.editor_cursor_position();
+        let filename = self.filename.clone();
+
+        match self.editor_open(&filename) {
+            Ok(()) => {
+                self.editor_move_to(pos);
+                self.editor_set_status_message(&format!("Reverted {}", filename));
+            }
+            Err(e) => self.editor_set_status_message_with(
+                Severity::Error,
+                &format!("Can't revert: {}", e),
+            ),
+        }
+
+        Ok(())
+    }
+
+    /// The filetype that `[filetype.NAME]` sections and language servers go by: the syntax's,
+    /// or the extension for files without one.
+    fn editor_syntax_filetype(&self) -> Option<String> {
+        match self.highlight.syntax {
+            Some(i) => Some(self.highlight.syntaxes[i].filetype.clone()),
+            None => Path::new(uncompressed_name(&self.filename))
+                .extension()
+                .map(|ext| ext.to_string_lossy().into_owned()),
+        }
+    }
+
+    /// The file's extension, looking past a `.gz` or `.xz` suffix, which keys the lint
+    /// commands and hooks for it.
+    fn editor_filetype(&self) -> Option<String> {
+        Path::new(uncompressed_name(&self.filename))
+            .extension()
+            .map(|ext| ext.to_string_lossy().into_owned())
+    }
+
+    fn editor_hook(&self, event: &str) -> Option<String> {
+        let specific = self
+            .editor_filetype()
+            .and_then(|ft| self.config.hooks.get(&format!("{}.{}", event, ft)));
+
+        specific.or_else(|| self.config.hooks.get(event)).cloned()
+    }
+
+    /// Runs the action configured for `event`, returning `false` if it failed.
+    fn editor_run_hook(&mut self, event: &str) -> io::Result<bool> {
+        // A hook's own action can trigger further events; those don't run hooks again.
+        let action = match self.editor_hook(event) {
+            Some(_) if self.plugins.in_hook => return Ok(true),
+            Some(action) => action,
+            None => return Ok(true),
+        };
+
+        self.plugins.in_hook = true;
+        let result = self.editor_run_hook_action(event, &action);
+        self.plugins.in_hook = false;
+
+        result
+    }
+
+    /// Prompts for a named command, such as `hardcopy listing.txt`, and runs it.
+    fn editor_command_line(&mut self) -> io::Result<()> {
+        let input = match self.editor_prompt(":{}", "ex")? {
+            Some(input) => input,
+            None => return Ok(()),
+        };
+
+        let command = input.trim();
+        if !command.is_empty() && !self.editor_run_command(command)? {
+            self.editor_set_status_message_with(
+                Severity::Error,
+                &format!("Unknown command: {}", command),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Runs a named command from the command line or a hook, returning `false` if there is
+    /// no such command.
+    fn editor_run_command(&mut self, command: &str) -> io::Result<bool> {
+        let (name, argument) = match command.split_once(' ') {
+            Some((name, argument)) => (name, argument.trim()),
+            None => (command, ""),
+        };
+
+        match name {
+            "lint" => self.editor_lint(),
+            "save" => self.editor_save()?,
+            "revert" => self.editor_revert()?,
+            "diff" => self.editor_diff_saved()?,
+            "scratch" => self.editor_scratch(argument)?,
+            "hardcopy" => self.editor_print(argument),
+            "replace" => self.editor_replace(true)?,
+            "replaceall" => self.editor_replace(false)?,
+            "grep" => self.editor_grep(argument)?,
+            "make" => self.editor_make(argument)?,
+            "cnext" | "cn" => self.editor_quickfix_step(true)?,
+            "cprev" | "cp" => self.editor_quickfix_step(false)?,
+            "copen" | "clist" => self.editor_quickfix_open()?,
+            _ => match self.plugins.commands.get(name) {
+                Some(&index) => self.editor_run_plugin_command(index, name, argument),
+                None => return Ok(false),
+            },
+        }
+
+        Ok(true)
+    }
+
+    /// Runs a command registered by plugin `index`.
+    fn editor_run_plugin_command(&mut self, index: usize, name: &str, argument: &str) {
+        let mut i = 0;
+        self.editor_run_plugins(|plugin, editor| {
+            if i == index {
+                plugin.run_command(editor, name, argument);
+            }
+            i += 1;
+            i > index
+        });
+    }
+
+    /// Prints the buffer with `print_command`, pipes it to `!command`, or writes it to the
+    /// file `destination`.
+    fn editor_print(&mut self, destination: &str) {
+        let len = self.buffer.rows.len();
+        self.editor_prepare_rows(0, len);
+        let text = self.editor_hardcopy();
+        let pages = text.matches('\x0c').count() + 1;
+
+        let command = match destination.strip_prefix('!') {
+            Some(command) => command.trim().to_string(),
+            None if destination.is_empty() => self.config.print_command.clone(),
+            None => {
+                let (severity, message) = match fs::write(destination, &text) {
+                    Ok(()) => (Severity::Info, format!("Wrote {} pages to {}", pages, destination)),
+                    Err(e) => (Severity::Error, format!("Can't print to {}: {}", destination, e)),
+                };
+                self.editor_set_status_message_with(severity, &message);
+                return;
+            }
+        };
+
+        let failure = match run_shell(&command, Some(text.as_bytes())) {
+            Ok(ref output) if output.status.success() => None,
+            Ok(output) => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                Some(stderr.lines().next().unwrap_or("command failed").to_string())
+            }
+            Err(e) => Some(e.to_string()),
+        };
+        let (severity, message) = match failure {
+            None => (Severity::Info, format!("Sent {} pages to {}", pages, command)),
+            Some(reason) => (Severity::Error, format!("Can't print with {}: {}", command, reason)),
+        };
+        self.editor_set_status_message_with(severity, &message);
+    }
+
+    fn editor_run_hook_action(&mut self, event: &str, action: &str) -> io::Result<bool> {
+        match action {
+            _ if action.starts_with(':') => {
+                if !self.editor_run_command(&action[1..])? {
+                    let message = format!("{}: unknown action {}", event, action);
+                    self.editor_set_status_message_with(Severity::Error, &message);
+                    return Ok(false);
+                }
+            }
+            _ => {
+                let command = action.replace('%', &shell_quote(&self.filename));
+                // Before a write the command filters the buffer, so formatters can rewrite it.
+                let input = match event {
+                    "BufWritePre" => Some(encode_text(&self.editor_rows_to_string())),
+                    _ => None,
+                };
+
+                let output = match run_shell(&command, input.as_deref()) {
+                    Ok(output) => output,
+                    Err(e) => {
+                        self.editor_set_status_message_with(
+                            Severity::Error,
+                            &format!("{}: {}", event, e),
+                        );
+                        return Ok(false);
+                    }
+                };
+
+                if !output.status.success() {
+                    let stderr = String::from_utf8_lossy(&output.stderr);
+                    let reason = stderr.lines().next().unwrap_or("command failed").to_string();
+                    self.editor_set_status_message_with(
+                        Severity::Error,
+                        &format!("{}: {}", event, reason),
+                    );
+                    return Ok(false);
+                }
+
+                if input.is_some() {
+                    self.editor_set_contents(&decode_bytes(&output.stdout));
+                }
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn editor_set_contents(&mut self, contents: &str) {
+        let changed = contents != self.editor_rows_to_string();
+        if !changed {
+            return;
+        }
+
+        let pos = self.editor_cursor_position();
+        // The text was given out with `\n` line endings, so the file's own are kept.
+        let (lines, crlf, _) = split_lines(contents);
+        self.buffer.crlf |= crlf;
+        self.buffer.rows.clear();
+        self.buffer.rows_changed();
+        for line in lines {
+            self.editor_append_row(line);
+        }
+
+        self.dirty = true;
+        self.conflicts = find_conflicts(&self.buffer.rows);
+        self.editor_text_changed();
+        self.editor_move_to(pos);
+    }
+
+    fn editor_lint_command(&self) -> Option<String> {
+        let command = self.config.lint.get(&self.editor_filetype()?)?;
+        let filename = shell_quote(&self.filename);
+
+        if command.contains('%') {
+            Some(command.replace('%', &filename))
+        } else {
+            Some(format!("{} {}", command, filename))
+        }
+    }
+
+    fn editor_lint(&mut self) {
+        let command = match self.editor_lint_command() {
+            Some(command) => command,
+            None => {
+                self.editor_set_status_message("No lint command configured for this file");
+                return;
+            }
+        };
+
+        let output = match Command::new("sh").arg("-c").arg(&command).output() {
+            Ok(output) => output,
+            Err(e) => {
+                self.editor_set_status_message_with(
+                    Severity::Error,
+                    &format!("Can't run lint command: {}", e),
+                );
+                return;
+            }
+        };
+
+        let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+        text.push_str(&String::from_utf8_lossy(&output.stderr));
+
+        let diagnostics = parse_diagnostics(&text, &self.filename);
+        self.editor_set_diagnostics(diagnostics);
+
+        // Linting every file opened from a grep or build list mustn't replace that list.
+        if self.quickfix.as_ref().is_none_or(|q| q.title == "lint") {
+            let locations = self.diagnostics.iter().map(|d| Location {
+                filename: self.filename.clone(),
+                line: d.line,
+                col: d.col,
+                message: d.message.clone(),
+            });
+            self.editor_set_quickfix("lint", locations.collect());
+        }
+
+        let message = match self.diagnostics.len() {
+            0 => "Lint: no problems found".to_string(),
+            1 => "Lint: 1 problem".to_string(),
+            n => format!("Lint: {} problems", n),
+        };
+        self.editor_set_status_message(&message);
+    }
+
+    /// Replaces the diagnostics, underlining the word each one points at.
+    fn editor_set_diagnostics(&mut self, mut diagnostics: Vec<Diagnostic>) {
+        diagnostics.sort_by_key(|d| (d.line, d.col));
+
+        let rows = &self.buffer.rows;
+        let ranges = diagnostics
+            .iter()
+            .filter(|d| d.line < rows.len())
+            .map(|d| {
+                let chars = rows[d.line].chars.chars().skip(d.col).collect::<Vec<char>>();
+                let len = match chars.first() {
+                    Some(&c) if is_word_char(c) => {
+                        chars.iter().take_while(|&&c| is_word_char(c)).count()
+                    }
+                    _ => 1,
+                };
+                let first = Position { cx: d.col, cy: d.line };
+                (first, Position { cx: d.col + len, ..first })
+            })
+            .collect();
+
+        self.diagnostics = diagnostics;
+        self.editor_set_overlay(Overlay::Diagnostics, DIAGNOSTIC_STYLE, ranges);
+    }
+
+    fn editor_show_diagnostic(&mut self) {
+        let messages = self
+            .diagnostics
+            .iter()
+            .filter(|d| d.line == self.cy)
+            .map(|d| format!("{}:{}: {}", d.line + 1, d.col + 1, d.message))
+            .collect::<Vec<String>>();
+
+        if messages.is_empty() {
+            self.editor_set_status_message("No diagnostics on this line");
+        } else {
+            self.editor_set_status_message(&messages.join(" | "));
+        }
+    }
+
+    fn editor_record_recent_file(&self) -> io::Result<()> {
+        let dir = match state_dir() {
+            Some(dir) => dir,
+            None => return Ok(()),
+        };
+        let path = dir.join("recent");
+        let filename = fs::canonicalize(&self.filename)?.display().to_string();
+
+        let mut recent = load_recent_files(&path)?;
+        recent.retain(|f| *f != filename);
+        recent.insert(0, filename);
+        recent.truncate(KILO_RECENT_FILES_SIZE);
+
+        let mut contents = recent.join("\n");
+        contents.push('\n');
+
+        fs::create_dir_all(&dir)?;
+        fs::write(&path, contents)
+    }
+
+    fn editor_open_recent(&mut self) -> io::Result<()> {
+        let recent = match state_dir().map(|dir| load_recent_files(&dir.join("recent"))) {
+            Some(Ok(recent)) => recent,
+            Some(Err(e)) => {
+                self.editor_state_error("read the recent files", Err(e));
+                return Ok(());
+            }
+            None => Vec::new(),
+        };
+
+        if recent.is_empty() {
+            self.editor_set_status_message("No recent files");
+            return Ok(());
+        }
+
+        if self.editor_refuse_if_dirty() {
+            return Ok(());
+        }
+
+        if let Some(i) = self.editor_pick("Recent files", recent.clone())? {
+            let result = self.editor_store_position();
+            self.editor_state_error("store the cursor position", result);
+
+            if let Err(e) = self.editor_open(&recent[i]) {
+                self.editor_set_status_message_with(
+                    Severity::Error,
+                    &format!("Can't open {}: {}", recent[i], e),
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reports a failed read or write of the optional state kept between sessions, such as
+    /// the recent files, as a warning instead of stopping what the editor was doing.
+    fn editor_state_error(&mut self, action: &str, result: io::Result<()>) {
+        if let Err(e) = result {
+            let message = format!("Can't {}: {}", action, e);
+            self.editor_log(format_args!("{}", message));
+            self.editor_set_status_message_with(Severity::Warning, &message);
+        }
+    }
+
+    /// Whether there are unsaved changes that opening another file would discard, saying so
+    /// when there are.
+    fn editor_refuse_if_dirty(&mut self) -> bool {
+        if self.dirty {
+            self.editor_set_status_message_with(
+                Severity::Warning,
+                "Unsaved changes: press Ctrl-S to save first",
+            );
+        }
+        self.dirty
+    }
+
+    fn editor_open_prompt(&mut self) -> io::Result<()> {
+        if self.editor_refuse_if_dirty() {
+            return Ok(());
+        }
+
+        let filename = match self.editor_prompt("Open file: {}", "file")? {
+            Some(ref name) if !name.trim().is_empty() => name.trim().to_string(),
+            _ => return Ok(()),
+        };
+
+        let result = self.editor_store_position();
+        self.editor_state_error("store the cursor position", result);
+        if let Err(e) = self.editor_open(&filename) {
+            self.editor_set_status_message_with(
+                Severity::Error,
+                &format!("Can't open {}: {}", filename, e),
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the buffer with an unnamed scratch buffer, empty or holding the output of
+    /// `!command`. Saving it asks for a file name.
+    fn editor_scratch(&mut self, argument: &str) -> io::Result<()> {
+        if self.editor_refuse_if_dirty() {
+            return Ok(());
+        }
+
+        let text = match argument.strip_prefix('!') {
+            Some(command) => match run_shell(command.trim(), None) {
+                Ok(output) => {
+                    let mut text = output.stdout;
+                    text.extend_from_slice(&output.stderr);
+                    text
+                }
+                Err(e) => {
+                    let message = format!("Can't run {}: {}", command.trim(), e);
+                    self.editor_set_status_message_with(Severity::Error, &message);
+                    return Ok(());
+                }
+            },
+            None => Vec::new(),
+        };
+
+        let result = self.editor_store_position();
+        self.editor_state_error("store the cursor position", result);
+        self.file.remote = None;
+        self.file.remote_fetch = None;
+        self.editor_load("", &text, None)
+    }
+
+    /// The file name, or a placeholder for a buffer that has none.
+    fn editor_name(&self) -> &str {
+        if self.filename.is_empty() { "[No Name]" } else { &self.filename }
+    }
+
+    /// Names the buffer `filename`, picking up the syntax and settings that apply to it.
+    fn editor_set_filename(&mut self, filename: &str) {
+        self.filename = filename.to_string();
+        self.file.compression = Compression::detect(filename, &[]);
+        self.file.remote = Remote::parse(filename);
+        let name = uncompressed_name(filename);
+        self.highlight.syntax = self.highlight.syntaxes.iter().position(|s| s.matches(name));
+
+        let filetype = self.editor_syntax_filetype();
+        self.config = self.base_config.scoped(filetype.as_deref(), filename);
+        self.buffer.tabstop = self.config.tabstop;
+
+        for row in &mut self.buffer.rows {
+            row.stale = true;
+        }
+        self.editor_highlight_rows(0);
+    }
+
+    fn editor_restore_position(&mut self) -> io::Result<()> {
+        let path = match state_dir() {
+            Some(dir) => dir.join("positions"),
+            None => return Ok(()),
+        };
+        let filename = fs::canonicalize(&self.filename)?;
+
+        let positions = load_positions(&path)?;
+
+        if let Some(&(_, pos)) = positions.iter().find(|p| p.0 == filename) {
+            self.editor_move_to(pos);
+        }
+
+        Ok(())
+    }
+
+    fn editor_store_position(&self) -> io::Result<()> {
+        if self.filename.is_empty() || self.file.remote.is_some() {
+            return Ok(());
+        }
+
+        let dir = match state_dir() {
+            Some(dir) => dir,
+            None => return Ok(()),
+        };
+        let path = dir.join("positions");
+        let filename = fs::canonicalize(&self.filename)?;
+
+        let mut positions = load_positions(&path)?;
+        positions.retain(|p| p.0 != filename);
+        positions.push((filename, self.editor_cursor_position()));
+
+        let skip = positions.len().saturating_sub(KILO_POSITIONS_SIZE);
+
+        let mut contents = String::new();
+        for &(ref f, pos) in positions.iter().skip(skip) {
+            contents.push_str(&format!("{}\t{}\t{}\n", pos.cy, pos.cx, f.display()));
+        }
+
+        fs::create_dir_all(&dir)?;
+        fs::write(&path, contents)
+    }
+
+    fn editor_load_session(&mut self, path: &Path) -> io::Result<()> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        let mut cursor = None;
+        let mut offset = None;
+        let mut marks = HashMap::new();
+
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            let mut parts = line.splitn(2, ' ');
+            let key = parts.next().unwrap_or("");
+            let value = parts.next().unwrap_or("");
+
+            match key {
+                "file" => self.editor_open(value)?,
+                "cursor" => cursor = parse_pair(value),
+                "offset" => offset = parse_pair(value),
+                "mark" => {
+                    let mut fields = value.splitn(2, ' ');
+                    let name = fields.next().and_then(|n| n.chars().next());
+                    let pos = fields.next().and_then(parse_pair);
+
+                    if let (Some(name), Some((cy, cx))) = (name, pos) {
+                        marks.insert(name, Position { cx, cy });
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if let Some((cy, cx)) = cursor {
+            self.editor_move_to(Position { cx, cy });
+        }
+        if let Some((rowoff, coloff)) = offset {
+            self.rowoff = rowoff;
+            self.coloff = coloff;
+        }
+        self.jumps.marks = marks;
+
+        Ok(())
+    }
+
+    fn editor_save_session(&self, path: &Path) -> io::Result<()> {
+        let mut contents = String::new();
+
+        if self.file.remote.is_some() {
+            contents.push_str(&format!("file {}\n", self.filename));
+        } else if !self.filename.is_empty() {
+            let filename = fs::canonicalize(&self.filename)?;
+            contents.push_str(&format!("file {}\n", filename.display()));
+        }
+        contents.push_str(&format!("cursor {} {}\n", self.cy, self.cx));
+        contents.push_str(&format!("offset {} {}\n", self.rowoff, self.coloff));
+
+        let mut names = self.jumps.marks.keys().collect::<Vec<&char>>();
+        names.sort();
+        for name in names {
+            let pos = self.jumps.marks[name];
+            contents.push_str(&format!("mark {} {} {}\n", name, pos.cy, pos.cx));
+        }
+
+        fs::write(path, contents)
+    }
+
+    fn editor_word_under_cursor(&self) -> Option<Vec<char>> {
+        let row = self.buffer.rows.get(self.cy)?;
+        let chars = row.chars.chars().collect::<Vec<char>>();
+
+        if !is_word_char(*chars.get(self.cx)?) {
+            return None;
+        }
+
+        let mut start = self.cx;
+        while start > 0 && is_word_char(chars[start - 1]) {
+            start -= 1;
+        }
+
+        let mut end = self.cx;
+        while end < chars.len() && is_word_char(chars[end]) {
+            end += 1;
+        }
+
+        Some(chars[start..end].to_vec())
+    }
+
+    fn editor_idle(&mut self) -> io::Result<()> {
+        if self.word_highlight.is_none() && self.last_key_time.elapsed() >= WORD_HIGHLIGHT_DELAY {
+            self.word_highlight = self.editor_word_under_cursor();
+        }
+
+        self.editor_refresh_git();
+        if self.git.changes_stale && self.last_key_time.elapsed() >= GIT_DIFF_DELAY {
+            self.editor_update_git_changes();
+        }
+        if self.last_key_time.elapsed() >= LSP_CHANGE_DELAY {
+            self.editor_sync_lsp();
+        }
+        self.editor_poll_highlight();
+        self.editor_poll_remote()?;
+        self.editor_check_disk()?;
+        self.editor_check_resize()
+    }
+
+    /// Offers to reload the file if it changed on disk since it was last read or written.
+    /// Checked periodically, and straight away when the window regains focus, since that is
+    /// usually when another program has just changed it.
+    fn editor_check_disk(&mut self) -> io::Result<()> {
+        let refocused = self.terminal.refocused.replace(false);
+        if self.filename.is_empty()
+            || (!refocused && self.file.disk_checked.elapsed() < DISK_CHECK_INTERVAL)
+        {
+            return Ok(());
+        }
+        self.file.disk_checked = Instant::now();
+
+        let mtime = file_mtime(&self.filename);
+        if mtime.is_none() || mtime == self.file.disk_mtime {
+            return Ok(());
+        }
+        // Only ask once per change.
+        self.file.disk_mtime = mtime;
+
+        let name = &self.filename;
+        let question = match self.dirty {
+            true => format!("{} changed on disk. Reload it and discard your changes?", name),
+            false => format!("{} changed on disk. Reload it?", name),
+        };
+        if self.editor_confirm(&question, false)? == Answer::Yes {
+            self.editor_reload()?;
+        } else {
+            let message = format!("{} changed on disk (Alt-R reloads it)", self.filename);
+            self.editor_set_sticky_message(Severity::Warning, &message);
+        }
+
+        Ok(())
+    }
+
+    /// Notes that the text was edited, for the work that is redone once typing pauses.
+    fn editor_text_changed(&mut self) {
+        self.git.changes_stale = true;
+        if let Some(ref mut client) = self.lsp {
+            client.changed = true;
+        }
+    }
+
+    /// The display width of a row's leading whitespace, or `None` for a blank row.
+    fn editor_indent(&self, at: usize) -> Option<usize> {
+        let chars = &self.buffer.rows[at].chars;
+        if chars.trim().is_empty() {
+            return None;
+        }
+
+        let mut width = 0;
+        for c in chars.chars().take_while(|c| c.is_whitespace()) {
+            width += if c == '\t' { self.buffer.tabstop - width % self.buffer.tabstop } else { 1 };
+        }
+
+        Some(width)
+    }
+
+    /// Rewrites the leading whitespace of the selected rows, or the whole buffer, as spaces
+    /// or as tabs (padded with spaces) using the current tab stop. Enter picks what
+    /// `expandtab` says to indent with.
+    fn editor_convert_indent(&mut self) -> io::Result<()> {
+        let default = if self.config.expandtab { "spaces" } else { "tabs" };
+        self.editor_set_status_message(&format!(
+            "Indent with: (s)paces (t)abs, Enter for {}",
+            default
+        ));
+        self.editor_refresh_screen()?;
+        let key = self.editor_wait_key()?;
+        self.editor_set_status_message("");
+
+        let to_tabs = match key {
+            Char(b's') => false,
+            Char(b't') => true,
+            Char(b'\r') => !self.config.expandtab,
+            _ => return Ok(()),
+        };
+
+        let (start, end) = self.editor_selected_rows().unwrap_or((0, self.buffer.rows.len()));
+        let mut changed = 0;
+        let lines = (start..end)
+            .map(|at| {
+                let chars = &self.buffer.rows[at].chars;
+                let indent = chars.len() - chars.trim_start().len();
+                let width = self.editor_indent(at).unwrap_or(0);
+
+                let mut line = if to_tabs {
+                    let tabs = iter::repeat_n('\t', width / self.buffer.tabstop);
+                    tabs.chain(iter::repeat_n(' ', width % self.buffer.tabstop)).collect::<String>()
+                } else {
+                    iter::repeat_n(' ', width).collect::<String>()
+                };
+                if chars.trim().is_empty() {
+                    line = chars.clone();
+                }
+                line.push_str(&chars[indent..]);
+
+                if line != *chars {
+                    changed += 1;
+                }
+                line
+            })
+            .collect::<Vec<String>>();
+
+        if changed > 0 {
+            self.editor_replace_rows(start, end, &lines);
+            let pos = self.editor_cursor_position();
+            self.editor_move_to(pos);
+        }
+        self.anchor = None;
+        self.editor_set_status_message(&format!("Converted indentation of {} lines", changed));
+
+        Ok(())
+    }
+
+    fn editor_next_mixed_indent(&mut self) {
+        let style = self.buffer.indent_style();
+        let count = self.buffer.rows.len();
+        let next = (1..=count)
+            .map(|i| (self.cy + i) % count)
+            .find(|&i| mixed_indent(&self.buffer.rows[i].chars, style));
+
+        match next {
+            Some(cy) => self.editor_jump_to_indent(cy),
+            None => self.editor_set_status_message("No mixed indentation"),
+        }
+    }
+
+    fn editor_jump_to_indent(&mut self, cy: usize) {
+        let cx = self.buffer.rows[cy].chars.chars().take_while(|c| c.is_whitespace()).count();
+        self.editor_jump_to(Position { cx, cy });
+    }
+
+    /// Jumps to the first or last line of the run of lines indented at least as deeply as
+    /// the cursor line, ignoring blank lines.
+    fn editor_indent_block_edge(&mut self, forward: bool) {
+        let indent = match (self.cy..self.buffer.rows.len()).find_map(|i| self.editor_indent(i)) {
+            Some(indent) => indent,
+            None => return,
+        };
+        let inside = |i: usize| self.editor_indent(i).is_none_or(|n| n >= indent);
+
+        let mut edge = self.cy;
+        if forward {
+            while edge + 1 < self.buffer.rows.len() && inside(edge + 1) {
+                edge += 1;
+            }
+            while edge > self.cy && self.editor_indent(edge).is_none() {
+                edge -= 1;
+            }
+        } else {
+            while edge > 0 && inside(edge - 1) {
+                edge -= 1;
+            }
+            while edge < self.cy && self.editor_indent(edge).is_none() {
+                edge += 1;
+            }
+        }
+
+        self.editor_jump_to_indent(edge);
+    }
+
+    /// Jumps to the next or previous line at the cursor line's indent, without leaving the
+    /// enclosing block.
+    fn editor_next_sibling(&mut self, forward: bool) {
+        let indent = match self.buffer.rows.get(self.cy).and_then(|_| self.editor_indent(self.cy)) {
+            Some(indent) => indent,
+            None => return,
+        };
+
+        let mut lines: Box<dyn Iterator<Item = usize>> = if forward {
+            Box::new((self.cy + 1)..self.buffer.rows.len())
+        } else {
+            Box::new((0..self.cy).rev())
+        };
+        let target = lines
+            .find(|&i| self.editor_indent(i).is_some_and(|n| n <= indent))
+            .filter(|&i| self.editor_indent(i) == Some(indent));
+
+        match target {
+            Some(cy) => self.editor_jump_to_indent(cy),
+            None => self.editor_set_status_message("No other line at this indent"),
+        }
+    }
+
+    /// Moves to the blank line after (or before) the paragraph at the cursor.
+    fn editor_next_paragraph(&mut self, forward: bool) {
+        let blank = |i: usize| self.buffer.rows[i].chars.trim().is_empty();
+        let mut cy = self.cy.min(self.buffer.rows.len());
+
+        if forward {
+            while cy < self.buffer.rows.len() && blank(cy) {
+                cy += 1;
+            }
+            while cy < self.buffer.rows.len() && !blank(cy) {
+                cy += 1;
+            }
+        } else {
+            while cy > 0 && blank(cy - 1) {
+                cy -= 1;
+            }
+            while cy > 0 && !blank(cy - 1) {
+                cy -= 1;
+            }
+            cy = cy.saturating_sub(1);
+        }
+
+        self.editor_move_to(Position { cx: 0, cy });
+    }
+
+    /// Whether a row starts a top-level block: an unindented line that isn't a closing
+    /// bracket, following a blank or indented line.
+    fn editor_is_block_start(&self, at: usize) -> bool {
+        let starts = self.editor_indent(at) == Some(0)
+            && !self.buffer.rows[at].chars.starts_with(['}', ')', ']']);
+
+        starts && (at == 0 || self.editor_indent(at - 1).is_none_or(|n| n > 0))
+    }
+
+    fn editor_next_block(&mut self, forward: bool) {
+        let target = if forward {
+            ((self.cy + 1)..self.buffer.rows.len()).find(|&i| self.editor_is_block_start(i))
+        } else {
+            (0..self.cy.min(self.buffer.rows.len())).rev().find(|&i| self.editor_is_block_start(i))
+        };
+
+        match target {
+            Some(cy) => self.editor_jump_to(Position { cx: 0, cy }),
+            None => self.editor_set_status_message("No more blocks"),
+        }
+    }
+
+    /// The number of characters in row `cy`, which is zero for the line after the last.
+    pub(crate) fn editor_row_len(&self, cy: usize) -> usize {
+        self.buffer.rows.get(cy).map_or(0, |row| row.chars.chars().count())
+    }
+
+    fn editor_move_cursor(&mut self, key: EditorKey) {
+        match key {
+            ArrowLeft => {
+                if self.cx != 0 {
+                    self.cx -= 1;
+                } else if self.cy > 0 {
+                    self.cy -= 1;
+                    self.cx = self.editor_row_len(self.cy);
+                }
+            }
+            ArrowRight if self.cy < self.buffer.rows.len() => {
+                let rowlen = self.editor_row_len(self.cy);
+                if self.cx < rowlen {
+                    self.cx += 1;
+                } else if self.cx == rowlen {
+                    self.cy += 1;
+                    self.cx = 0;
+                }
+            }
+            ArrowUp if self.cy != 0 => self.cy -= 1,
+            ArrowDown if self.cy < self.buffer.rows.len() => self.cy += 1,
+            _ => {}
+        }
+
+        self.cx = self.cx.min(self.editor_row_len(self.cy));
+    }
+
+    fn editor_load_registers(&mut self) -> io::Result<()> {
+        if let Some(dir) = state_dir() {
+            self.clipboard.registers = load_registers(&dir.join("registers"))?;
+        }
+
+        Ok(())
+    }
+
+    fn editor_store_registers(&self) -> io::Result<()> {
+        let dir = match state_dir() {
+            Some(dir) => dir,
+            None => return Ok(()),
+        };
+
+        let mut names = self.clipboard.registers.keys().collect::<Vec<&char>>();
+        names.sort();
+
+        let mut contents = String::new();
+        for name in names {
+            let text = escape_register(&self.clipboard.registers[name]);
+            contents.push_str(&format!("{}\t{}\n", name, text));
+        }
+
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join("registers"), contents)
+    }
+
+    fn editor_cursor_position(&self) -> Position {
+        Position {
+            cx: self.cx,
+            cy: self.cy,
+        }
+    }
+
+    fn editor_record_jump(&mut self) {
+        let pos = self.editor_cursor_position();
+
+        self.jumps.last_position = Some(pos);
+
+        self.jumps.list.truncate(self.jumps.index);
+        if self.jumps.list.last() != Some(&pos) {
+            self.jumps.list.push(pos);
+        }
+        if self.jumps.list.len() > KILO_JUMPLIST_SIZE {
+            self.jumps.list.remove(0);
+        }
+        self.jumps.index = self.jumps.list.len();
+    }
+
+    /// Moves the marks and jumps below `at` along after `removed` rows there were replaced
+    /// by `added` ones. Marks on removed rows are dropped, as in vi, while jumps there go to
+    /// the start of row `at` so that the jump list keeps its length.
+    fn editor_shift_marks(&mut self, at: usize, removed: usize, added: usize) {
+        let shift = |pos: Position| match pos.cy {
+            cy if cy < at => Some(pos),
+            cy if cy < at + removed => None,
+            cy => Some(Position { cx: pos.cx, cy: cy + added - removed }),
+        };
+        let clamp = |pos: Position| shift(pos).unwrap_or(Position { cx: 0, cy: at });
+
+        self.jumps.marks.retain(|_, pos| match shift(*pos) {
+            Some(moved) => {
+                *pos = moved;
+                true
+            }
+            None => false,
+        });
+        for pos in &mut self.jumps.list {
+            *pos = clamp(*pos);
+        }
+        self.jumps.last_position = self.jumps.last_position.map(clamp);
+    }
+
+    fn editor_jump_to(&mut self, pos: Position) {
+        self.editor_record_jump();
+        self.editor_move_to(pos);
+    }
+
+    fn editor_move_to(&mut self, pos: Position) {
+        self.cy = pos.cy.min(self.buffer.rows.len());
+
+        self.cx = pos.cx.min(self.editor_row_len(self.cy));
+    }
+
+    fn editor_jump_back(&mut self) {
+        if self.jumps.index == 0 {
+            self.editor_set_status_message("Already at oldest jump");
+            return;
+        }
+
+        if self.jumps.index == self.jumps.list.len() {
+            let pos = self.editor_cursor_position();
+            if self.jumps.list.last() != Some(&pos) {
+                self.jumps.list.push(pos);
+            }
+        }
+
+        self.jumps.index -= 1;
+        let current = self.editor_cursor_position();
+        if self.jumps.list[self.jumps.index] == current && self.jumps.index > 0 {
+            self.jumps.index -= 1;
+        }
+
+        let pos = self.jumps.list[self.jumps.index];
+        self.editor_move_to(pos);
+    }
+
+    fn editor_jump_forward(&mut self) {
+        if self.jumps.index + 1 >= self.jumps.list.len() {
+            self.editor_set_status_message("Already at newest jump");
+            return;
+        }
+
+        self.jumps.index += 1;
+
+        let pos = self.jumps.list[self.jumps.index];
+        self.editor_move_to(pos);
+    }
+
+    fn editor_set_mark(&mut self) -> io::Result<()> {
+        match self.editor_read_mark_name("Set mark: (a-z, A-Z)")? {
+            Some(name) if name != '\'' => {
+                let pos = self.editor_cursor_position();
+                self.jumps.marks.insert(name, pos);
+                self.editor_set_status_message(&format!("Mark '{}' set", name));
+            }
+            _ => self.editor_set_status_message_with(Severity::Warning, "Invalid mark name"),
+        }
+
+        Ok(())
+    }
+
+    fn editor_goto_mark(&mut self) -> io::Result<()> {
+        let name = match self.editor_read_mark_name("Go to mark: (a-z, A-Z, ' = last position)")? {
+            Some(name) => name,
+            None => {
+                self.editor_set_status_message_with(Severity::Warning, "Invalid mark name");
+                return Ok(());
+            }
+        };
+
+        let pos = if name == '\'' {
+            self.jumps.last_position
+        } else {
+            self.jumps.marks.get(&name).cloned()
+        };
+
+        match pos {
+            Some(pos) => self.editor_jump_to(pos),
+            None => self.editor_set_status_message(&format!("Mark '{}' not set", name)),
+        }
+
+        Ok(())
+    }
+
+    fn init_editor(&mut self) -> io::Result<()> {
+        let (screenrows, screencols) = self.get_window_size()?;
+
+        match Config::load() {
+            Ok((config, errors)) => {
+                self.base_config = config;
+                if !errors.is_empty() {
+                    self.editor_set_status_message_with(Severity::Error, &errors.join("; "));
+                }
+            }
+            Err(e) => self.editor_state_error("read kilorc", Err(e)),
+        }
+        self.config = self.base_config.clone();
+
+        if let Some(dir) = config_dir() {
+            match EditorSyntax::load_dir(&dir.join("syntax")) {
+                Ok((mut syntaxes, errors)) => {
+                    syntaxes.append(&mut self.highlight.syntaxes);
+                    self.highlight.syntaxes = syntaxes;
+
+                    if !errors.is_empty() {
+                        self.editor_set_status_message_with(
+                            Severity::Error,
+                            &format!("Syntax files: {}", errors.join("; ")),
+                        );
+                    }
+                }
+                Err(e) => self.editor_state_error("read the syntax files", Err(e)),
+            }
+        }
+
+        if self.config.spell_check {
+            self.editor_load_dictionary();
+        }
+
+        if self.base_config.persist_registers {
+            let result = self.editor_load_registers();
+            self.editor_state_error("read the registers", result);
+        }
+
+        if let Some(dir) = state_dir() {
+            match load_recent_files(&dir.join("recent")) {
+                Ok(recent) => self.recent_files = recent,
+                Err(e) => self.editor_state_error("read the recent files", Err(e)),
+            }
+        }
+
+        self.screenrows = screenrows.saturating_sub(2);
+        self.screencols = screencols;
+
+        Ok(())
+    }
+
+    fn editor_loop(&mut self) -> io::Result<()> {
+        loop {
+            // Coalesce bursts of input such as pastes, redrawing at most once per frame.
+            let due = self.frame.refreshed.elapsed() >= KILO_FRAME_INTERVAL;
+            if !self.editor_input_pending() || due {
+                self.editor_refresh_screen()?;
+                self.frame.refreshed = Instant::now();
+            }
+            if !self.editor_process_keypress()? {
+                return Ok(());
+            }
+        }
+    }
+
+    pub fn run(mut self) -> io::Result<()> {
+        let args = Args::parse(env::args().skip(1))?;
+        self.log = Logger::open(args.debug)?;
+
+        self.enable_raw_mode()?;
+        self.init_editor()?;
+
+        if args.vi || self.base_config.vi {
+            self.vi_mode = Some(ViMode::Normal);
+        }
+        if self.base_config.terminal_title {
+            // Save the current title on the terminal's title stack so it can be restored.
+            write_terminal(b"\x1b[22;0t")?;
+        }
+
+        if let Some(ref session) = args.session {
+            self.editor_load_session(session)?;
+        }
+
+        if let Some((ref left, ref right)) = args.diff {
+            self.editor_compare(left, right)?;
+        } else if let Some(ref filename) = args.filename {
+            self.editor_open(filename)?;
+        }
+
+        if self.message.text.is_empty() {
+            self.editor_set_status_message(
+                "HELP: Ctrl-S = save | Ctrl-Q = quit | Ctrl-R = recent | Ctrl-Space/Ctrl-B = marks",
+            );
+        }
+
+        let result = if args.show_keys { self.editor_show_keys() } else { self.editor_loop() };
+        if let Err(e) = result {
+            self.editor_log(format_args!("error: {}", e));
+            return Err(e);
+        }
+
+        let result = self.editor_store_position();
+        self.editor_state_error("store the cursor position", result);
+
+        if self.base_config.persist_registers {
+            let result = self.editor_store_registers();
+            self.editor_state_error("store the registers", result);
+        }
+
+        if let Some(ref session) = args.session {
+            self.editor_save_session(session)?;
+        }
+
+        if self.base_config.terminal_title {
+            write_terminal(b"\x1b]2;\x07\x1b[23;0t")?;
+        }
+
+        clear_screen()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A headless editor on the lines of `text`, with the cursor at the start.
+    fn editor(text: &str) -> Kilo {
+        let mut kilo = Kilo::headless(24, 80);
+        kilo.buffer = Buffer::from_text(text);
+        kilo
+    }
+
+    #[test]
+    fn marks_follow_their_rows() {
+        let mut kilo = editor("one\ntwo\nthree\nfour\n");
+        kilo.jumps.marks.insert('a', Position { cx: 1, cy: 2 });
+        kilo.jumps.marks.insert('b', Position { cx: 2, cy: 3 });
+        kilo.set_cursor(1, 0);
+        kilo.editor_record_jump();
+
+        // A line inserted above the marks moves them down.
+        kilo.set_cursor(0, 0);
+        kilo.feed(b"\r").unwrap();
+        assert_eq!(kilo.jumps.marks[&'a'], Position { cx: 1, cy: 3 });
+        assert_eq!(kilo.jumps.list, [Position { cx: 0, cy: 2 }]);
+
+        // Joining a line moves its marks up with its text.
+        kilo.set_cursor(4, 0);
+        kilo.feed(b"\x7f").unwrap();
+        assert_eq!(kilo.line(3), Some("threefour"));
+        assert_eq!(kilo.jumps.marks[&'b'], Position { cx: 7, cy: 3 });
+
+        // Deleting the line a mark is on drops the mark; jumps there go to the line after.
+        kilo.editor_del_row(3);
+        kilo.editor_del_row(2);
+        assert!(kilo.jumps.marks.is_empty());
+        assert_eq!(kilo.jumps.list, [Position { cx: 0, cy: 2 }]);
+    }
+
+    const RIGHT: &[u8] = b"\x1b[C";
+    const UP: &[u8] = b"\x1b[A";
+    const DELETE: &[u8] = b"\x1b[3~";
+
+    #[test]
+    fn cursor_moves_by_characters() {
+        let mut kilo = editor("éé\nx\n");
+        kilo.feed(&RIGHT.repeat(2)).unwrap();
+        assert_eq!(kilo.cursor(), (0, 2));
+        kilo.feed(RIGHT).unwrap();
+        assert_eq!(kilo.cursor(), (1, 0));
+
+        let mut kilo = editor("ééé\nabcdefg\n");
+        kilo.set_cursor(1, 6);
+        kilo.feed(UP).unwrap();
+        assert_eq!(kilo.cursor(), (0, 3));
+        kilo.feed(b"\x7f").unwrap();
+        assert_eq!(kilo.line(0), Some("éé"));
+    }
+
+    #[test]
+    fn typing_utf8() {
+        let mut kilo = editor("ab\n");
+        kilo.set_cursor(0, 1);
+        kilo.feed("é漢".as_bytes()).unwrap();
+        assert_eq!(kilo.line(0), Some("aé漢b"));
+        assert_eq!(kilo.cursor(), (0, 3));
+
+        // A truncated sequence is dropped, and the key after it still counts.
+        kilo.feed(b"\xc3x").unwrap();
+        assert_eq!(kilo.line(0), Some("aé漢xb"));
+    }
+
+    #[test]
+    fn delete_forward() {
+        let mut kilo = editor("é\nx\n");
+        kilo.set_cursor(0, 1);
+        kilo.feed(DELETE).unwrap();
+        assert_eq!(kilo.line(0), Some("éx"));
+        assert_eq!(kilo.cursor(), (0, 1));
+
+        kilo.feed(DELETE).unwrap();
+        assert_eq!(kilo.line(0), Some("é"));
+        kilo.feed(DELETE).unwrap();
+        assert_eq!((kilo.line_count(), kilo.cursor()), (1, (0, 1)));
+    }
+
+    #[test]
+    fn blocks_span_display_columns() {
+        let mut kilo = editor("\tab\n12345678cd\nxy\n");
+        kilo.anchor = Some(Position { cx: 1, cy: 0 });
+        kilo.block = true;
+        kilo.set_cursor(2, 2);
+        assert_eq!(kilo.editor_block(), Some((0, 3, 2, 8)));
+
+        kilo.set_cursor(1, 9);
+        kilo.editor_edit_block(Some(""));
+        assert_eq!(kilo.clipboard.rectangle, ["a", "c"]);
+        assert_eq!((kilo.line(0), kilo.line(1)), (Some("\tb"), Some("12345678d")));
+
+        kilo.set_cursor(2, 1);
+        kilo.editor_yank_rectangle();
+        assert_eq!((kilo.line(2), kilo.line(3)), (Some("xay"), Some(" c")));
+        kilo.set_cursor(0, 1);
+        kilo.editor_yank_rectangle();
+        assert_eq!((kilo.line(0), kilo.line(1)), (Some("\tab"), Some("12345678cd")));
+    }
+
+    #[test]
+    fn hooks_of_compressed_files() {
+        let mut kilo = editor("");
+        kilo.config.hooks.insert("BufWrite.rs".to_string(), "!true".to_string());
+        kilo.filename = "main.rs.gz".to_string();
+        assert_eq!(kilo.editor_filetype().as_deref(), Some("rs"));
+        assert_eq!(kilo.editor_hook("BufWrite").as_deref(), Some("!true"));
+    }
+
+    #[test]
+    fn scratch_buffers() {
+        let mut kilo = editor("one\n");
+        kilo.editor_scratch("!printf 'a\\nb\\n'").unwrap();
+        assert_eq!((kilo.filename.as_str(), kilo.line_count()), ("", 2));
+        assert_eq!((kilo.line(1), kilo.dirty), (Some("b"), false));
+
+        kilo.feed(b"x").unwrap();
+        kilo.config.quit_confirm_unnamed = false;
+        assert!(kilo.editor_confirm_quit().unwrap());
+    }
 }
@@ -0,0 +1,53 @@
+//! Diagnostic logging to a file, since nothing can be printed while the terminal is in raw mode.
+
+use super::*;
+
+pub(crate) struct Logger {
+    file: File,
+    start: Instant,
+}
+
+impl Logger {
+    /// Opens the file named by `KILO_LOG`, or `debug.log` in the state directory when `debug`
+    /// is set. Returns `None` when logging is off.
+    pub(crate) fn open(debug: bool) -> io::Result<Option<Logger>> {
+        let path = match env::var_os("KILO_LOG") {
+            Some(ref path) if !path.is_empty() => PathBuf::from(path),
+            _ if debug => match state_dir() {
+                Some(dir) => {
+                    fs::create_dir_all(&dir)?;
+                    dir.join("debug.log")
+                }
+                None => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+
+        let file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+        let logger = Logger { file, start: Instant::now() };
+        logger.write(format_args!("kilo {} started", KILO_VERSION.unwrap_or("")));
+
+        Ok(Some(logger))
+    }
+
+    /// Appends a line stamped with the time since the log was opened. Write errors are
+    /// ignored so that a full disk never interrupts editing.
+    pub(crate) fn write(&self, args: fmt::Arguments) {
+        let elapsed = self.start.elapsed();
+        let _ = writeln!(
+            &self.file,
+            "[{:4}.{:03}] {}",
+            elapsed.as_secs(),
+            elapsed.subsec_millis(),
+            args
+        );
+    }
+}
+
+impl Kilo {
+    pub(crate) fn editor_log(&self, args: fmt::Arguments) {
+        if let Some(ref log) = self.log {
+            log.write(args);
+        }
+    }
+}
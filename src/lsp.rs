@@ -0,0 +1,506 @@
+//! The language server client: starting the server configured for the buffer's filetype,
+//! keeping it in sync with edits, and going to definitions and showing hover information.
+
+use super::*;
+
+/// The path a `file://` URI names, on whichever host.
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let rest = uri.strip_prefix("file://")?;
+    let bytes = url_decode(&rest[rest.find('/')?..])?;
+
+    Some(PathBuf::from(OsString::from_vec(bytes)))
+}
+
+/// The number of UTF-16 code units in the first `cx` characters of `text`, which is how
+/// language servers count columns.
+fn utf16_col(text: &str, cx: usize) -> usize {
+    text.chars().take(cx).map(char::len_utf16).sum()
+}
+
+/// The character index in `text` of UTF-16 column `col`.
+fn utf16_to_cx(text: &str, col: usize) -> usize {
+    let mut units = 0;
+    let before = text.chars().take_while(|c| {
+        units += c.len_utf16();
+        units <= col
+    });
+
+    before.count()
+}
+
+fn read_lsp_message<R: BufRead>(reader: &mut R) -> io::Result<Option<Json>> {
+    let mut length = None;
+
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+
+        let lower = header.to_lowercase();
+        if lower.starts_with("content-length:") {
+            length = header["content-length:".len()..].trim().parse::<usize>().ok();
+        }
+    }
+
+    let length = match length {
+        Some(length) => length,
+        None => return Err(invalid_input("LSP message without Content-Length")),
+    };
+
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+
+    Ok(Json::parse(&String::from_utf8_lossy(&body)))
+}
+
+pub(crate) struct LspClient {
+    child: Child,
+    stdin: ChildStdin,
+    messages: Receiver<Json>,
+    next_id: usize,
+    pending: HashMap<usize, &'static str>,
+    initialized: bool,
+    uri: String,
+    /// The version of the text the server was last sent.
+    version: usize,
+    /// Whether the buffer was edited since the server was last sent its text.
+    pub(crate) changed: bool,
+}
+
+impl LspClient {
+    fn start(command: &str, path: &Path) -> io::Result<Self> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        let stdin = child.stdin.take().ok_or_else(|| invalid_input("LSP server has no stdin"))?;
+        let stdout = child.stdout.take().ok_or_else(|| invalid_input("LSP server has no stdout"))?;
+
+        let (sender, messages) = mpsc::channel();
+
+        thread::spawn(move || {
+            let mut reader = BufReader::new(stdout);
+            while let Ok(Some(message)) = read_lsp_message(&mut reader) {
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut client = LspClient {
+            child,
+            stdin,
+            messages,
+            next_id: 1,
+            pending: HashMap::new(),
+            initialized: false,
+            uri: path_to_uri(path, ""),
+            version: 1,
+            changed: false,
+        };
+
+        let root = env::current_dir()?;
+        client.request(
+            "initialize",
+            Json::object(vec![
+                ("processId", Json::Number(f64::from(std::process::id()))),
+                ("rootUri", Json::Str(path_to_uri(&root, ""))),
+                ("capabilities", Json::object(vec![])),
+            ]),
+        )?;
+
+        Ok(client)
+    }
+
+    fn send(&mut self, message: Json) -> io::Result<()> {
+        let body = message.to_string();
+
+        write!(self.stdin, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+        self.stdin.flush()
+    }
+
+    fn request(&mut self, method: &'static str, params: Json) -> io::Result<()> {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.pending.insert(id, method);
+
+        self.send(Json::object(vec![
+            ("jsonrpc", Json::string("2.0")),
+            ("id", Json::Number(id as f64)),
+            ("method", Json::string(method)),
+            ("params", params),
+        ]))
+    }
+
+    fn notify(&mut self, method: &str, params: Json) -> io::Result<()> {
+        self.send(Json::object(vec![
+            ("jsonrpc", Json::string("2.0")),
+            ("method", Json::string(method)),
+            ("params", params),
+        ]))
+    }
+
+    fn did_open(&mut self, language: &str, text: &str) -> io::Result<()> {
+        let uri = self.uri.clone();
+
+        self.initialized = true;
+        self.changed = false;
+        self.notify("initialized", Json::object(vec![]))?;
+        self.notify(
+            "textDocument/didOpen",
+            Json::object(vec![(
+                "textDocument",
+                Json::object(vec![
+                    ("uri", Json::Str(uri)),
+                    ("languageId", Json::string(language)),
+                    ("version", Json::Number(1.0)),
+                    ("text", Json::string(text)),
+                ]),
+            )]),
+        )
+    }
+
+    /// Sends the whole of the edited `text`.
+    fn did_change(&mut self, text: &str) -> io::Result<()> {
+        let uri = self.uri.clone();
+
+        self.version += 1;
+        self.changed = false;
+        let change = Json::object(vec![("text", Json::string(text))]);
+        self.notify(
+            "textDocument/didChange",
+            Json::object(vec![
+                (
+                    "textDocument",
+                    Json::object(vec![
+                        ("uri", Json::Str(uri)),
+                        ("version", Json::Number(self.version as f64)),
+                    ]),
+                ),
+                ("contentChanges", Json::Array(vec![change])),
+            ]),
+        )
+    }
+
+    pub(crate) fn did_save(&mut self) -> io::Result<()> {
+        let uri = self.uri.clone();
+
+        self.notify(
+            "textDocument/didSave",
+            Json::object(vec![("textDocument", Json::object(vec![("uri", Json::Str(uri))]))]),
+        )
+    }
+
+    /// Whether `uri` names the file this client is for, however the server encoded it.
+    fn is_ours(&self, uri: &str) -> bool {
+        uri == self.uri || uri_to_path(uri).is_some_and(|path| uri_to_path(&self.uri) == Some(path))
+    }
+
+    /// Asks about position `pos`, whose column is `character` UTF-16 code units in.
+    fn position_request(
+        &mut self,
+        method: &'static str,
+        pos: Position,
+        character: usize,
+    ) -> io::Result<()> {
+        let uri = self.uri.clone();
+
+        self.request(
+            method,
+            Json::object(vec![
+                ("textDocument", Json::object(vec![("uri", Json::Str(uri))])),
+                (
+                    "position",
+                    Json::object(vec![
+                        ("line", Json::Number(pos.cy as f64)),
+                        ("character", Json::Number(character as f64)),
+                    ]),
+                ),
+            ]),
+        )
+    }
+}
+
+impl Drop for LspClient {
+    fn drop(&mut self) {
+        let _ = self.request("shutdown", Json::Null);
+        let _ = self.notify("exit", Json::Null);
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// The URI and start of the first location in `result`, with the column still counted in
+/// UTF-16 code units.
+fn lsp_location(result: &Json) -> Option<(String, Position)> {
+    let location = match *result {
+        Json::Array(ref items) => items.first()?,
+        _ => result,
+    };
+
+    let uri = location.get("uri").or_else(|| location.get("targetUri"))?.as_str()?;
+    let range = location.get("range").or_else(|| location.get("targetSelectionRange"))?;
+    let start = range.get("start")?;
+
+    Some((
+        uri.to_string(),
+        Position {
+            cy: start.get("line")?.as_usize()?,
+            cx: start.get("character")?.as_usize()?,
+        },
+    ))
+}
+
+fn lsp_hover_text(contents: &Json) -> String {
+    match *contents {
+        Json::Str(ref s) => s.clone(),
+        Json::Array(ref items) => items
+            .iter()
+            .map(lsp_hover_text)
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<String>>()
+            .join(" "),
+        Json::Object(_) => contents
+            .get("value")
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .to_string(),
+        _ => String::new(),
+    }
+}
+
+impl Kilo {
+    pub(crate) fn editor_start_lsp(&mut self) {
+        self.lsp = None;
+
+        let filetype = self.editor_syntax_filetype();
+        let command = self.config.lsp.iter().find(|&(name, _)| {
+            filetype.as_ref().is_some_and(|ft| ft.eq_ignore_ascii_case(name))
+        });
+        let command = match command {
+            Some((_, command)) => command.clone(),
+            None => return,
+        };
+
+        let result = fs::canonicalize(&self.filename)
+            .and_then(|path| LspClient::start(&command, &path));
+
+        match result {
+            Ok(client) => self.lsp = Some(client),
+            Err(e) => self.editor_set_status_message_with(
+                Severity::Error,
+                &format!("Can't start LSP server: {}", e),
+            ),
+        }
+    }
+
+    pub(crate) fn editor_poll_lsp(&mut self) {
+        let messages = match self.lsp {
+            Some(ref client) => client.messages.try_iter().collect::<Vec<Json>>(),
+            None => return,
+        };
+
+        for message in messages {
+            if let Err(e) = self.editor_handle_lsp_message(&message) {
+                self.editor_set_status_message_with(Severity::Error, &format!("LSP error: {}", e));
+            }
+        }
+    }
+
+    fn editor_handle_lsp_message(&mut self, message: &Json) -> io::Result<()> {
+        let id = message.get("id").and_then(|id| id.as_usize());
+        let method = message.get("method").and_then(|m| m.as_str());
+
+        match (id, method) {
+            (Some(id), Some(_)) => {
+                // Server-to-client request: acknowledge it so the server doesn't stall.
+                if let Some(ref mut client) = self.lsp {
+                    client.send(Json::object(vec![
+                        ("jsonrpc", Json::string("2.0")),
+                        ("id", Json::Number(id as f64)),
+                        ("result", Json::Null),
+                    ]))?;
+                }
+            }
+            (Some(id), None) => {
+                let request = match self.lsp {
+                    Some(ref mut client) => client.pending.remove(&id),
+                    None => None,
+                };
+
+                if let Some(error) = message.get("error") {
+                    let text = error
+                        .get("message")
+                        .and_then(|m| m.as_str())
+                        .unwrap_or("unknown error");
+                    self.editor_set_status_message(&format!("LSP: {}", text));
+                    return Ok(());
+                }
+
+                let result = message.get("result").unwrap_or(&Json::Null);
+
+                match request {
+                    Some("initialize") => {
+                        let language = self.editor_syntax_filetype().unwrap_or_default();
+                        let language = language.to_lowercase();
+                        let text = self.editor_rows_to_string();
+
+                        if let Some(ref mut client) = self.lsp {
+                            client.did_open(&language, &text)?;
+                        }
+                    }
+                    Some("textDocument/definition") => self.editor_goto_lsp_location(result)?,
+                    Some("textDocument/hover") => {
+                        let text = result.get("contents").map(lsp_hover_text).unwrap_or_default();
+                        let text = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+
+                        if text.is_empty() {
+                            self.editor_set_status_message("No hover information");
+                        } else {
+                            self.editor_set_status_message(&text);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            (None, Some("textDocument/publishDiagnostics")) => {
+                let params = match message.get("params") {
+                    Some(params) => params,
+                    None => return Ok(()),
+                };
+
+                let uri = params.get("uri").and_then(|u| u.as_str());
+                let ours = match (&self.lsp, uri) {
+                    (Some(client), Some(uri)) => client.is_ours(uri),
+                    _ => false,
+                };
+
+                if let (true, Some(Json::Array(items))) = (ours, params.get("diagnostics")) {
+                    let diagnostics = items
+                        .iter()
+                        .filter_map(|item| {
+                            let start = item.get("range")?.get("start")?;
+                            let line = start.get("line")?.as_usize()?;
+                            let col = start.get("character")?.as_usize()?;
+                            let row = self.buffer.rows.get(line).map_or("", |r| r.chars.as_str());
+                            Some(Diagnostic {
+                                line,
+                                col: utf16_to_cx(row, col),
+                                message: item.get("message")?.as_str()?.to_string(),
+                            })
+                        })
+                        .collect();
+                    self.editor_set_diagnostics(diagnostics);
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn editor_goto_lsp_location(&mut self, result: &Json) -> io::Result<()> {
+        let (uri, pos) = match lsp_location(result) {
+            Some(location) => location,
+            None => {
+                self.editor_set_status_message("No definition found");
+                return Ok(());
+            }
+        };
+
+        let ours = match self.lsp {
+            Some(ref client) => client.is_ours(&uri),
+            None => false,
+        };
+
+        if ours {
+            let pos = self.editor_lsp_position(pos);
+            self.editor_jump_to(pos);
+            return Ok(());
+        }
+
+        let path = match uri_to_path(&uri) {
+            Some(path) => path,
+            None => {
+                self.editor_set_status_message_with(
+                    Severity::Error,
+                    &format!("Can't open {}", uri),
+                );
+                return Ok(());
+            }
+        };
+
+        if self.editor_refuse_if_dirty() {
+            return Ok(());
+        }
+
+        let result = self.editor_store_position();
+        self.editor_state_error("store the cursor position", result);
+        match self.editor_open(&path.to_string_lossy()) {
+            Ok(()) => {
+                let pos = self.editor_lsp_position(pos);
+                self.editor_move_to(pos);
+            }
+            Err(e) => {
+                let message = format!("Can't open {}: {}", path.display(), e);
+                self.editor_set_status_message(&message);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// `pos` from a language server, with its column turned from UTF-16 code units into
+    /// characters of the row it is on.
+    fn editor_lsp_position(&self, pos: Position) -> Position {
+        match self.buffer.rows.get(pos.cy) {
+            Some(row) => Position { cx: utf16_to_cx(&row.chars, pos.cx), ..pos },
+            None => pos,
+        }
+    }
+
+    /// Sends the server the buffer's text if it was edited since it was last sent.
+    pub(crate) fn editor_sync_lsp(&mut self) {
+        if !self.lsp.as_ref().is_some_and(|client| client.initialized && client.changed) {
+            return;
+        }
+
+        let text = self.editor_rows_to_string();
+        if let Some(Err(e)) = self.lsp.as_mut().map(|client| client.did_change(&text)) {
+            self.editor_set_status_message_with(Severity::Error, &format!("LSP error: {}", e));
+        }
+    }
+
+    pub(crate) fn editor_lsp_request(&mut self, method: &'static str) -> io::Result<()> {
+        let pos = self.editor_cursor_position();
+        let character = match self.buffer.rows.get(pos.cy) {
+            Some(row) => utf16_col(&row.chars, pos.cx),
+            None => 0,
+        };
+        self.editor_sync_lsp();
+
+        match self.lsp {
+            Some(ref mut client) if client.initialized => {
+                client.position_request(method, pos, character)
+            }
+            Some(_) => {
+                self.editor_set_status_message("LSP server is still starting");
+                Ok(())
+            }
+            None => {
+                self.editor_set_status_message("No LSP server configured for this file");
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,213 @@
+//! Highlights that features lay over the syntax colouring of the text, such as URLs, the
+//! current search match, diagnostics and the selection. Highlights that follow from a row's
+//! text are worked out as it is drawn; the others are kept until their owner replaces them.
+
+use super::*;
+
+/// The features that own highlights, in the order they are drawn: where highlights overlap,
+/// the later one shows.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Overlay {
+    Urls,
+    Misspellings,
+    Plugins,
+    ControlChars,
+    WordHighlight,
+    MixedIndent,
+    Diagnostics,
+    Selection,
+    SearchMatch,
+    Ruler,
+}
+
+const OVERLAYS: [Overlay; 10] = [
+    Overlay::Urls,
+    Overlay::Misspellings,
+    Overlay::Plugins,
+    Overlay::ControlChars,
+    Overlay::WordHighlight,
+    Overlay::MixedIndent,
+    Overlay::Diagnostics,
+    Overlay::Selection,
+    Overlay::SearchMatch,
+    Overlay::Ruler,
+];
+
+/// Ranges of text drawn in one style, each from a start position up to an end position.
+pub(crate) struct HighlightGroup {
+    pub(crate) style: &'static str,
+    pub(crate) ranges: Vec<(Position, Position)>,
+}
+
+impl Kilo {
+    /// Replaces the ranges `owner` highlights; no ranges removes its group.
+    pub(crate) fn editor_set_overlay(
+        &mut self,
+        owner: Overlay,
+        style: &'static str,
+        ranges: Vec<(Position, Position)>,
+    ) {
+        if ranges.is_empty() {
+            self.overlays.remove(&owner);
+        } else {
+            self.overlays.insert(owner, HighlightGroup { style, ranges });
+        }
+    }
+
+    /// Moves the kept highlights along with the rows below `at` after `removed` rows there
+    /// were replaced by `added` ones, dropping those on removed rows.
+    pub(crate) fn editor_shift_overlays(&mut self, at: usize, removed: usize, added: usize) {
+        let shift = |cy: usize| match cy {
+            cy if cy < at => Some(cy),
+            cy if cy < at + removed => None,
+            cy => Some(cy + added - removed),
+        };
+
+        for group in self.overlays.values_mut() {
+            group.ranges.retain_mut(|(first, last)| match (shift(first.cy), shift(last.cy)) {
+                (Some(top), Some(bottom)) => {
+                    first.cy = top;
+                    last.cy = bottom;
+                    true
+                }
+                _ => false,
+            });
+        }
+        self.overlays.retain(|_, group| !group.ranges.is_empty());
+
+        self.diagnostics.retain_mut(|d| match shift(d.line) {
+            Some(line) => {
+                d.line = line;
+                true
+            }
+            None => false,
+        });
+    }
+
+    /// The highlights covering row `filerow`, as render columns `from..to` and a style, in
+    /// the order they are drawn.
+    pub(crate) fn editor_overlay_spans(
+        &self,
+        filerow: usize,
+    ) -> Vec<(usize, usize, Cow<'static, str>)> {
+        let row = &self.buffer.rows[filerow];
+        let render = row.render.chars().collect::<Vec<char>>();
+        let found = |found: Vec<(usize, usize)>, style: &'static str| {
+            found.into_iter().map(move |(start, len)| (start, start + len, Cow::from(style)))
+        };
+        let mut spans = Vec::new();
+
+        for owner in OVERLAYS {
+            match owner {
+                Overlay::Urls => spans.extend(found(find_urls(&render), URL_STYLE)),
+                Overlay::Misspellings => {
+                    if let (true, Some(dictionary)) =
+                        (self.config.spell_check, self.dictionary.as_ref())
+                    {
+                        spans.extend(found(dictionary.misspellings(&render), MISSPELLING_STYLE));
+                    }
+                }
+                Overlay::Plugins => {
+                    for plugin in &self.plugins.loaded {
+                        let styled = plugin.on_render_row(filerow, &row.render);
+                        let styled = styled.into_iter();
+                        spans.extend(styled.map(|(start, len, style)| {
+                            (start, start + len, Cow::from(style))
+                        }));
+                    }
+                }
+                // Placeholders for control characters and bytes that aren't UTF-8 are drawn
+                // in inverse video so they can't be mistaken for the same text typed literally.
+                Overlay::ControlChars => {
+                    let mut rx = 0;
+                    for c in row.chars.chars() {
+                        let width = placeholder_width(c);
+                        if c == '\t' {
+                            rx += self.buffer.tabstop - rx % self.buffer.tabstop;
+                        } else if width > 0 {
+                            spans.push((rx, rx + width, Cow::from(CONTROL_CHAR_STYLE)));
+                            rx += width;
+                        } else {
+                            rx += 1;
+                        }
+                    }
+                }
+                Overlay::WordHighlight => {
+                    if let Some(ref word) = self.word_highlight {
+                        let occurrences = find_word_occurrences(&render, word).into_iter();
+                        let occurrences = occurrences.map(|start| (start, word.len()));
+                        spans.extend(found(occurrences.collect(), WORD_HIGHLIGHT_STYLE));
+                    }
+                }
+                Overlay::MixedIndent => {
+                    let chars = &row.chars;
+                    if self.config.indent_warnings
+                        && mixed_indent(chars, self.buffer.indent_style())
+                    {
+                        let indent = chars.chars().take_while(|c| c.is_whitespace()).count();
+                        let width = self.editor_row_cx_to_rx(row, indent);
+                        spans.push((0, width, Cow::from(MIXED_INDENT_STYLE)));
+                    }
+                }
+                Overlay::Diagnostics | Overlay::Selection | Overlay::SearchMatch => {
+                    if let Some(group) = self.overlays.get(&owner) {
+                        spans.extend(self.editor_group_spans(group, filerow));
+                    }
+                }
+                Overlay::Ruler => {
+                    if let Some(col) = self.config.ruler {
+                        spans.push((col - 1, col, Cow::from(RULER_STYLE)));
+                    }
+                }
+            }
+        }
+
+        spans
+    }
+
+    /// The parts of the ranges in `group` on row `filerow`, as in `editor_overlay_spans`.
+    fn editor_group_spans(
+        &self,
+        group: &HighlightGroup,
+        filerow: usize,
+    ) -> Vec<(usize, usize, Cow<'static, str>)> {
+        let row = &self.buffer.rows[filerow];
+        let mut spans = Vec::new();
+
+        for &(first, last) in &group.ranges {
+            if filerow < first.cy || filerow > last.cy {
+                continue;
+            }
+            let from = match filerow == first.cy {
+                true => self.editor_row_cx_to_rx(row, first.cx),
+                false => 0,
+            };
+            let to = match filerow == last.cy {
+                true => self.editor_row_cx_to_rx(row, last.cx),
+                false => row.render.chars().count(),
+            };
+            spans.push((from, to, Cow::from(group.style)));
+        }
+
+        spans
+    }
+
+    /// Follows the selection, which changes with every cursor movement, so that it is drawn
+    /// as it currently is.
+    pub(crate) fn editor_update_selection_overlay(&mut self) {
+        let ranges = if let Some((top, bottom, left, right)) = self.editor_block() {
+            (top..bottom)
+                .map(|cy| {
+                    let (from, to, _) = self.editor_block_span(&self.buffer.rows[cy], left, right);
+                    (Position { cx: from, cy }, Position { cx: to, cy })
+                })
+                .collect()
+        } else if let Some(range) = self.editor_vi_selection() {
+            vec![range]
+        } else {
+            self.editor_selection().into_iter().collect()
+        };
+
+        self.editor_set_overlay(Overlay::Selection, SELECTION_STYLE, ranges);
+    }
+}
@@ -0,0 +1,188 @@
+//! The quickfix list: locations collected from grep, build and lint output, which `:cnext`
+//! and `:cprev` step through and `:copen` lists.
+
+use super::*;
+
+/// A place in a file, with the message that pointed at it.
+#[derive(Clone)]
+pub(crate) struct Location {
+    pub(crate) filename: String,
+    pub(crate) line: usize,
+    pub(crate) col: usize,
+    pub(crate) message: String,
+}
+
+/// Every `file:line: message` or `file:line:column: message` line in `output`, as grep -n,
+/// compilers and linters print them. Rust's `--> file:line:column` lines count too.
+pub(crate) fn parse_locations(output: &str) -> Vec<Location> {
+    let mut locations = Vec::new();
+
+    for line in output.lines() {
+        let mut parts = line.splitn(4, ':');
+        let file = parts.next().unwrap_or("").trim();
+        let file = file.strip_prefix("--> ").unwrap_or(file);
+        let lnum = match parts.next().and_then(|l| l.trim().parse::<usize>().ok()) {
+            Some(lnum) if lnum > 0 && !file.is_empty() => lnum,
+            _ => continue,
+        };
+
+        let rest = parts.next().unwrap_or("");
+        let tail = parts.next();
+        let (col, message) = match (rest.trim().parse::<usize>(), tail) {
+            (Ok(col), _) => (col.saturating_sub(1), tail.unwrap_or("").to_string()),
+            (Err(_), Some(tail)) => (0, format!("{}:{}", rest, tail)),
+            (Err(_), None) => (0, rest.to_string()),
+        };
+
+        locations.push(Location {
+            filename: file.to_string(),
+            line: lnum - 1,
+            col,
+            message: message.trim().to_string(),
+        });
+    }
+
+    locations
+}
+
+pub(crate) struct Quickfix {
+    /// What produced the list, e.g. `grep` or `make`.
+    pub(crate) title: String,
+    pub(crate) locations: Vec<Location>,
+    /// The location last jumped to.
+    pub(crate) current: Option<usize>,
+}
+
+impl Kilo {
+    pub(crate) fn editor_set_quickfix(&mut self, title: &str, locations: Vec<Location>) {
+        self.quickfix = Some(Quickfix { title: title.to_string(), locations, current: None });
+    }
+
+    /// Runs `grep_command` for `pattern` in the current directory and collects the matches.
+    pub(crate) fn editor_grep(&mut self, pattern: &str) -> io::Result<()> {
+        if pattern.is_empty() {
+            self.editor_set_status_message_with(Severity::Warning, "Usage: :grep PATTERN");
+            return Ok(());
+        }
+
+        let command = format!("{} {} .", self.config.grep_command, shell_quote(pattern));
+        self.editor_collect_locations("grep", &command)
+    }
+
+    /// Runs `make_command` with `arguments` and collects the errors and warnings it reports.
+    pub(crate) fn editor_make(&mut self, arguments: &str) -> io::Result<()> {
+        let command = format!("{} {}", self.config.make_command, arguments);
+        self.editor_collect_locations("make", command.trim())
+    }
+
+    /// Runs `command` and makes the locations in its output the quickfix list.
+    fn editor_collect_locations(&mut self, title: &str, command: &str) -> io::Result<()> {
+        let output = match run_shell(command, None) {
+            Ok(output) => output,
+            Err(e) => {
+                let message = format!("Can't run {}: {}", command, e);
+                self.editor_set_status_message_with(Severity::Error, &message);
+                return Ok(());
+            }
+        };
+
+        let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+        text.push_str(&String::from_utf8_lossy(&output.stderr));
+        let locations = parse_locations(&text);
+
+        let (severity, message) = match locations.len() {
+            0 if output.status.success() => (Severity::Info, format!("{}: done", title)),
+            0 => {
+                let reason = text.lines().find(|l| !l.trim().is_empty());
+                let reason = reason.map_or_else(|| output.status.to_string(), str::to_string);
+                (Severity::Error, format!("{}: {}", title, reason))
+            }
+            1 => (Severity::Info, format!("{}: 1 location (:cnext to go there)", title)),
+            n => (Severity::Info, format!("{}: {} locations (:cnext to step through)", title, n)),
+        };
+
+        if !locations.is_empty() {
+            self.editor_set_quickfix(title, locations);
+        }
+        self.editor_set_status_message_with(severity, &message);
+
+        Ok(())
+    }
+
+    /// Jumps to the next (or previous) location in the quickfix list.
+    pub(crate) fn editor_quickfix_step(&mut self, forward: bool) -> io::Result<()> {
+        let (len, current) = match self.quickfix {
+            Some(ref quickfix) => (quickfix.locations.len(), quickfix.current),
+            None => {
+                self.editor_set_status_message("No quickfix list");
+                return Ok(());
+            }
+        };
+
+        let next = match (current, forward) {
+            (None, true) => Some(0),
+            (None, false) => len.checked_sub(1),
+            (Some(i), true) => Some(i + 1).filter(|&next| next < len),
+            (Some(i), false) => i.checked_sub(1),
+        };
+
+        match next {
+            Some(i) => self.editor_quickfix_jump(i),
+            None => {
+                self.editor_set_status_message("No more locations");
+                Ok(())
+            }
+        }
+    }
+
+    /// Lists the quickfix locations in a picker and jumps to the chosen one.
+    pub(crate) fn editor_quickfix_open(&mut self) -> io::Result<()> {
+        let (title, items) = match self.quickfix {
+            Some(ref quickfix) => {
+                let items = quickfix.locations.iter().map(|l| {
+                    format!("{}:{}:{}: {}", l.filename, l.line + 1, l.col + 1, l.message)
+                });
+                (quickfix.title.clone(), items.collect::<Vec<String>>())
+            }
+            None => {
+                self.editor_set_status_message("No quickfix list");
+                return Ok(());
+            }
+        };
+
+        match self.editor_pick(&title, items)? {
+            Some(i) => self.editor_quickfix_jump(i),
+            None => Ok(()),
+        }
+    }
+
+    /// Goes to location `i` of the quickfix list, opening its file if it isn't this one.
+    fn editor_quickfix_jump(&mut self, i: usize) -> io::Result<()> {
+        let (location, len) = match self.quickfix {
+            Some(ref quickfix) => (quickfix.locations[i].clone(), quickfix.locations.len()),
+            None => return Ok(()),
+        };
+
+        if !same_file(Path::new(&location.filename), Path::new(&self.filename)) {
+            if self.editor_refuse_if_dirty() {
+                return Ok(());
+            }
+
+            let result = self.editor_store_position();
+            self.editor_state_error("store the cursor position", result);
+            if let Err(e) = self.editor_open(&location.filename) {
+                let message = format!("Can't open {}: {}", location.filename, e);
+                self.editor_set_status_message_with(Severity::Error, &message);
+                return Ok(());
+            }
+        }
+
+        if let Some(ref mut quickfix) = self.quickfix {
+            quickfix.current = Some(i);
+        }
+        self.editor_jump_to(Position { cx: location.col, cy: location.line });
+        self.editor_set_status_message(&format!("({} of {}) {}", i + 1, len, location.message));
+
+        Ok(())
+    }
+}
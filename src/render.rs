@@ -0,0 +1,1042 @@
+//! Drawing the text area, gutter, status and message bars to the screen.
+
+use super::*;
+
+/// How serious a status message is, which decides its color.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    fn style(self) -> &'static str {
+        match self {
+            Severity::Info => "",
+            Severity::Warning => WARNING_STYLE,
+            Severity::Error => ERROR_STYLE,
+        }
+    }
+}
+
+/// Terminal columns taken by `c`: zero for combining marks, two for wide East Asian
+/// characters and emoji.
+pub(crate) fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x0300..=0x036f | 0x0483..=0x0489 | 0x0591..=0x05bd | 0x200b..=0x200f | 0x20d0..=0x20ff
+        | 0xfe00..=0xfe0f | 0xfe20..=0xfe2f => 0,
+        0x1100..=0x115f
+        | 0x2e80..=0x303e
+        | 0x3041..=0x33ff
+        | 0x3400..=0x4dbf
+        | 0x4e00..=0x9fff
+        | 0xa000..=0xa4cf
+        | 0xac00..=0xd7a3
+        | 0xf900..=0xfaff
+        | 0xfe30..=0xfe4f
+        | 0xff00..=0xff60
+        | 0xffe0..=0xffe6
+        | 0x1f300..=0x1f64f
+        | 0x1f900..=0x1f9ff
+        | 0x20000..=0x3fffd => 2,
+        _ => 1,
+    }
+}
+
+pub(crate) fn str_width(s: &str) -> usize {
+    s.chars().map(char_width).sum()
+}
+
+/// The longest prefix of `s` that fits in `width` columns.
+pub(crate) fn truncate_to_width(s: &str, width: usize) -> &str {
+    let mut used = 0;
+
+    for (i, c) in s.char_indices() {
+        used += char_width(c);
+        if used > width {
+            return &s[..i];
+        }
+    }
+
+    s
+}
+
+/// The `(start, length)` of every URL in `line`. A URL runs from a known scheme to the next
+/// space or quote, leaving out trailing punctuation and closing brackets it didn't open.
+pub(crate) fn find_urls(line: &[char]) -> Vec<(usize, usize)> {
+    let schemes = ["https://", "http://", "ftp://", "file://", "mailto:"];
+    let mut urls = Vec::new();
+    let mut start = 0;
+
+    while start < line.len() {
+        let scheme = schemes.iter().find(|scheme| {
+            let scheme = scheme.chars().collect::<Vec<char>>();
+            line[start..].starts_with(&scheme)
+        });
+        let boundary = start == 0 || !line[start - 1].is_alphanumeric();
+        let scheme_len = match scheme {
+            Some(scheme) if boundary => scheme.len(),
+            _ => {
+                start += 1;
+                continue;
+            }
+        };
+
+        let mut end = start;
+        while end < line.len() && !line[end].is_whitespace() && !"<>\"'`".contains(line[end]) {
+            end += 1;
+        }
+        loop {
+            let url = &line[start..end];
+            let opened = |open: char| url.iter().filter(|&&c| c == open).count();
+            let unmatched = match url.last() {
+                Some(')') => opened(')') > opened('('),
+                Some(']') => opened(']') > opened('['),
+                _ => false,
+            };
+            if unmatched || url.last().is_some_and(|c| ".,;:!?".contains(*c)) {
+                end -= 1;
+            } else {
+                break;
+            }
+        }
+
+        if end > start + scheme_len {
+            urls.push((start, end - start));
+            start = end;
+        } else {
+            start += scheme_len;
+        }
+    }
+
+    urls
+}
+
+/// The `(start, length, path length)` of every `path:line` or `path:line:column` reference
+/// in `line`, as grep and compilers print them.
+pub(crate) fn find_file_references(line: &[char]) -> Vec<(usize, usize, usize)> {
+    let mut references = Vec::new();
+    let mut start = 0;
+
+    while start < line.len() {
+        if start > 0 && !line[start - 1].is_whitespace() {
+            start += 1;
+            continue;
+        }
+
+        let mut end = start;
+        while end < line.len() && !line[end].is_whitespace() && line[end] != ':' {
+            end += 1;
+        }
+        let path_len = end - start;
+
+        // One or two `:digits` groups after the path.
+        for _ in 0..2 {
+            let digits = line[end..].iter().skip(1).take_while(|c| c.is_ascii_digit()).count();
+            if line.get(end) != Some(&':') || digits == 0 {
+                break;
+            }
+            end += 1 + digits;
+        }
+
+        if path_len > 0 && end > start + path_len {
+            references.push((start, end - start, path_len));
+        }
+        start = end.max(start + 1);
+    }
+
+    references
+}
+
+/// A screen cell of a drawn line: the escape sequences in effect there and what it shows,
+/// which is nothing in the second column of a wide character.
+#[derive(Clone, PartialEq)]
+struct Cell {
+    style: String,
+    link: String,
+    text: String,
+}
+
+impl Cell {
+    fn blank(style: &str) -> Self {
+        Cell { style: style.to_string(), link: String::new(), text: " ".to_string() }
+    }
+}
+
+/// The cells a drawn `line` covers, in a screen `cols` wide. An erase to the end of the line
+/// fills the rest of it with blanks in the style it was made in.
+fn line_cells(line: &str, cols: usize) -> Vec<Cell> {
+    let mut cells = Vec::new();
+    let (mut style, mut link) = (String::new(), String::new());
+    let mut col = 0;
+    let mut rest = line;
+
+    while let Some(c) = rest.chars().next() {
+        if let Some(seq) = rest.strip_prefix("\x1b[") {
+            let len = seq.find(|c: char| ('@'..='~').contains(&c)).map_or(seq.len(), |i| i + 1);
+            match &seq[..len] {
+                "m" | "0m" => style.clear(),
+                "K" => {
+                    cells.truncate(col);
+                    cells.resize(cmp::max(col, cols), Cell::blank(&style));
+                }
+                params if params.ends_with('m') => style.push_str(&rest[..(len + 2)]),
+                _ => {}
+            }
+            rest = &seq[len..];
+            continue;
+        }
+        if let Some(seq) = rest.strip_prefix("\x1b]") {
+            let len = seq.find("\x1b\\").map_or(seq.len(), |i| i + 2);
+            link = match &seq[..len] {
+                "8;;\x1b\\" => String::new(),
+                _ => rest[..(len + 2)].to_string(),
+            };
+            rest = &seq[len..];
+            continue;
+        }
+        rest = &rest[c.len_utf8()..];
+
+        let width = char_width(c);
+        if width == 0 {
+            let drawn = cmp::min(col, cells.len());
+            let drawn = &mut cells[..drawn];
+            if let Some(cell) = drawn.iter_mut().rfind(|cell| !cell.text.is_empty()) {
+                cell.text.push(c);
+            }
+            continue;
+        }
+        for i in 0..width {
+            let text = if i == 0 { c.to_string() } else { String::new() };
+            let cell = Cell { style: style.clone(), link: link.clone(), text };
+            match cells.get_mut(col) {
+                Some(old) => *old = cell,
+                None => cells.push(cell),
+            }
+            col += 1;
+        }
+    }
+
+    cells
+}
+
+/// The escape sequences and text that draw `cells`.
+fn cells_text(cells: &[Cell]) -> String {
+    let mut text = String::new();
+    let (mut style, mut link) = (None, "");
+
+    for cell in cells {
+        if cell.link != link {
+            link = &cell.link;
+            match link.is_empty() {
+                true => text.push_str(&hyperlink("")),
+                false => text.push_str(link),
+            }
+        }
+        if style != Some(&cell.style) {
+            style = Some(&cell.style);
+            text.push_str("\x1b[m");
+            text.push_str(&cell.style);
+        }
+        text.push_str(&cell.text);
+    }
+    if !link.is_empty() {
+        text.push_str(&hyperlink(""));
+    }
+
+    text
+}
+
+/// What turns screen line `y` from `old` into `new`: each run of changed cells, after a move
+/// to where it starts. Runs only a few cells apart are sent as one, since the move in between
+/// would cost about as much, and blanks up to the end of the line are erased.
+fn line_update(y: usize, old: &[Cell], new: &[Cell]) -> String {
+    let len = cmp::max(old.len(), new.len());
+    let changed = |x: usize| old.get(x) != new.get(x);
+    // A wide character is always redrawn whole.
+    let continues = |x: usize| [old.get(x), new.get(x)].iter().flatten().any(|c| c.text.is_empty());
+    let tail = match new.last() {
+        Some(last) => {
+            let blank = Cell::blank(&last.style);
+            new.len() - new.iter().rev().take_while(|&c| *c == blank).count()
+        }
+        None => 0,
+    };
+
+    let mut update = String::new();
+    let mut x = 0;
+    while x < len {
+        if !changed(x) {
+            x += 1;
+            continue;
+        }
+        let mut start = x;
+        while start > 0 && continues(start) {
+            start -= 1;
+        }
+        let mut end = x + 1;
+        loop {
+            while end < len && continues(end) {
+                end += 1;
+            }
+            match (end..len).find(|&x| changed(x)) {
+                Some(next) if next - end < CELL_RUN_GAP => end = next + 1,
+                _ => break,
+            }
+        }
+
+        update.push_str(&format!("\x1b[{};{}H", y + 1, start + 1));
+        update.push_str(&cells_text(new.get(start..cmp::min(end, tail)).unwrap_or(&[])));
+        if end > tail {
+            update.push_str("\x1b[m");
+            update.push_str(new.get(tail).map_or("", |c| c.style.as_str()));
+            update.push_str("\x1b[K");
+        }
+        update.push_str("\x1b[m");
+        x = end;
+    }
+
+    update
+}
+
+/// What was last drawn to the terminal, so the next refresh only sends what changed.
+pub(crate) struct Frame {
+    pub(crate) refreshed: Instant,
+    /// The lines of the last frame, and the width they were drawn at.
+    pub(crate) lines: Vec<String>,
+    pub(crate) cols: usize,
+    pub(crate) title: String,
+}
+
+/// The message shown in the message bar.
+pub(crate) struct StatusMessage {
+    pub(crate) text: String,
+    pub(crate) time: Instant,
+    pub(crate) severity: Severity,
+    /// Whether the message stays up until dismissed.
+    pub(crate) sticky: bool,
+}
+
+impl Kilo {
+    pub(crate) fn editor_row_cx_to_rx(&self, row: &Row, cx: usize) -> usize {
+        let mut rx = 0;
+
+        for c in row.chars.chars().take(cx) {
+            if c == '\t' {
+                rx += (self.buffer.tabstop - 1) - (rx % self.buffer.tabstop);
+            } else {
+                rx += placeholder_width(c).saturating_sub(1);
+            }
+            rx += 1;
+        }
+
+        rx
+    }
+
+    /// The index of the character drawn at display column `rx` of `row`, or the row's length
+    /// when it is narrower than that.
+    pub(crate) fn editor_row_rx_to_cx(&self, row: &Row, rx: usize) -> usize {
+        let mut cur_rx = 0;
+
+        for (cx, c) in row.chars.chars().enumerate() {
+            if c == '\t' {
+                cur_rx += (self.buffer.tabstop - 1) - (cur_rx % self.buffer.tabstop);
+            } else {
+                cur_rx += placeholder_width(c).saturating_sub(1);
+            }
+            cur_rx += 1;
+
+            if cur_rx > rx {
+                return cx;
+            }
+        }
+
+        row.chars.chars().count()
+    }
+
+    /// Renders the rows in `start..end` that haven't been displayed before.
+    pub(crate) fn editor_prepare_rows(&mut self, start: usize, end: usize) {
+        for at in start..cmp::min(end, self.buffer.rows.len()) {
+            if self.buffer.rows[at].stale {
+                self.buffer.update_render(at);
+            }
+        }
+    }
+
+    pub(crate) fn editor_gutter_width(&self) -> usize {
+        if self.diagnostics.is_empty() && self.git.changes.iter().all(|c| c.is_none()) {
+            0
+        } else {
+            GUTTER_WIDTH
+        }
+    }
+
+    pub(crate) fn editor_text_cols(&self) -> usize {
+        let cols = self.screencols.saturating_sub(self.editor_gutter_width());
+
+        match self.comparison {
+            // Each pane gets half of the width, minus one column for the separator.
+            Some(_) => cols.saturating_sub(1) / 2,
+            None => cols,
+        }
+    }
+
+    pub(crate) fn editor_scroll(&mut self) {
+        self.rx = 0;
+
+        if self.cy < self.buffer.rows.len() {
+            self.rx = self.editor_row_cx_to_rx(&self.buffer.rows[self.cy], self.cx);
+        }
+
+        if self.cy < self.rowoff {
+            self.rowoff = self.cy;
+        }
+
+        if self.cy >= self.rowoff + self.screenrows {
+            self.rowoff = self.cy - self.screenrows + 1;
+        }
+
+        while self.rowoff < self.cy && self.editor_screen_row(self.cy) >= self.screenrows {
+            self.rowoff += 1;
+        }
+
+        if self.rx < self.coloff {
+            self.coloff = self.rx;
+        }
+
+        let textcols = self.editor_text_cols();
+        if self.rx >= self.coloff + textcols {
+            self.coloff = self.rx - textcols + 1;
+        }
+    }
+
+    pub(crate) fn editor_draw_picker(&self, buffer: &mut String, picker: &Picker) {
+        let matches = picker.matches();
+        let visible = self.screenrows.saturating_sub(1);
+        let offset = (picker.selected + 1).saturating_sub(visible);
+
+        let header = format!("{}: {}", picker.title, picker.query);
+        buffer.extend(header.chars().take(self.screencols));
+        buffer.push_str("\x1b[K\r\n");
+
+        for y in 0..visible {
+            if let Some(&i) = matches.get(y + offset) {
+                if y + offset == picker.selected {
+                    buffer.push_str("\x1b[7m");
+                }
+                buffer.extend(picker.items[i].chars().take(self.screencols));
+                buffer.push_str("\x1b[m");
+            }
+
+            buffer.push_str("\x1b[K");
+            buffer.push_str("\r\n");
+        }
+    }
+
+    /// Draws the lines of a diff from the view's top line on, coloured by what they do.
+    pub(crate) fn editor_draw_diff(&self, buffer: &mut String, view: &DiffView) {
+        for y in 0..self.screenrows {
+            if let Some(line) = view.lines.get(view.top + y) {
+                let style = match line.chars().next() {
+                    Some('+') => DIFF_ADDED_STYLE,
+                    Some('-') => DIFF_REMOVED_STYLE,
+                    Some('@') => DIFF_HUNK_STYLE,
+                    _ => "",
+                };
+                let text = render_text(line, self.buffer.tabstop);
+                buffer.push_str(style);
+                buffer.push_str(truncate_to_width(&text, self.screencols));
+                buffer.push_str("\x1b[m");
+            }
+
+            buffer.push_str("\x1b[K");
+            buffer.push_str("\r\n");
+        }
+    }
+
+    /// Draws the completion popup over the screen `lines`, below the word being completed
+    /// or above it if there isn't room.
+    pub(crate) fn editor_draw_completion(&self, lines: &mut [String], completion: &Completion) {
+        let height = cmp::min(completion.items.len(), KILO_COMPLETION_ITEMS);
+        let offset = (completion.selected + 1).saturating_sub(height);
+        let width = completion.items.iter().map(|item| str_width(item)).max().unwrap_or(0) + 2;
+        let width = cmp::min(width, self.screencols);
+
+        let row = self.editor_screen_row(completion.start.cy);
+        let top = match row + 1 + height <= self.screenrows {
+            true => row + 1,
+            false => row.saturating_sub(height),
+        };
+        let rx = match self.buffer.rows.get(completion.start.cy) {
+            Some(row) => self.editor_row_cx_to_rx(row, completion.start.cx),
+            None => 0,
+        };
+        let col = (rx + self.editor_gutter_width()).saturating_sub(self.coloff + 1);
+        let col = cmp::min(col, self.screencols - width);
+
+        for (y, i) in (top..cmp::min(top + height, self.screenrows)).zip(offset..) {
+            let style = match i == completion.selected {
+                true => COMPLETION_SELECTED_STYLE,
+                false => COMPLETION_STYLE,
+            };
+            let cell = |text: String| Cell { style: style.to_string(), link: String::new(), text };
+            let mut item = vec![Cell::blank(style)];
+            for c in completion.items[i].chars() {
+                item.push(cell(c.to_string()));
+                for _ in 1..char_width(c) {
+                    item.push(cell(String::new()));
+                }
+            }
+            item.truncate(width);
+            item.resize(width, Cell::blank(style));
+
+            let mut cells = line_cells(&lines[y], self.screencols);
+            cells.resize(cmp::max(cells.len(), self.screencols), Cell::blank(""));
+            // Halves of wide characters the popup covers the other half of are left blank.
+            if cells[col].text.is_empty() && col > 0 {
+                cells[col - 1] = Cell::blank(&cells[col - 1].style);
+            }
+            if cells.get(col + width).is_some_and(|cell| cell.text.is_empty()) {
+                cells[col + width] = Cell::blank(&cells[col + width].style);
+            }
+            cells.splice(col..(col + width), item);
+
+            lines[y] = cells_text(&cells);
+            lines[y].push_str("\x1b[m");
+        }
+    }
+
+    /// The startup screen: version, the most recently opened files and the main key bindings.
+    pub(crate) fn editor_welcome_lines(&self) -> Vec<String> {
+        let mut lines = vec![match KILO_VERSION {
+            Some(version) => format!("Kilo editor -- version {}", version),
+            None => "Kilo editor".to_string(),
+        }];
+
+        if !self.recent_files.is_empty() {
+            lines.push(String::new());
+            lines.push("Recent files (Ctrl-R):".to_string());
+
+            let home = env::var("HOME").unwrap_or_default();
+            for file in self.recent_files.iter().take(KILO_WELCOME_RECENT) {
+                let file = match file.strip_prefix(&home) {
+                    Some(rest) if !home.is_empty() && rest.starts_with('/') => format!("~{}", rest),
+                    _ => file.clone(),
+                };
+                lines.push(format!("  {}", file));
+            }
+        }
+
+        lines.push(String::new());
+        lines.extend(
+            [
+                "Ctrl-E  open file      Ctrl-R  recent files",
+                "Ctrl-F  find           Ctrl-L  go to line",
+                "Ctrl-S  save           Ctrl-Q  quit",
+            ]
+            .iter()
+            .map(|hint| hint.to_string()),
+        );
+
+        lines
+    }
+
+    pub(crate) fn editor_draw_rows(&self, buffer: &mut String) {
+        let welcome = match self.buffer.rows.is_empty() {
+            true => self.editor_welcome_lines(),
+            false => Vec::new(),
+        };
+        // The welcome screen is centred as a block, and lines that don't fit are left out.
+        let welcome_top = self.screenrows.saturating_sub(welcome.len()) / 3;
+        let width = welcome.iter().map(|line| str_width(line)).max().unwrap_or(0);
+        let welcome_left = self.screencols.saturating_sub(width) / 2;
+
+        for y in 0..self.screenrows {
+            let filerow = y + self.rowoff;
+            if filerow >= self.buffer.rows.len() {
+                match y.checked_sub(welcome_top).and_then(|i| welcome.get(i)) {
+                    Some(line) => {
+                        let line = truncate_to_width(line, self.screencols - welcome_left);
+                        if welcome_left > 0 {
+                            buffer.push('~');
+                        }
+                        buffer.extend(iter::repeat_n(' ', welcome_left.saturating_sub(1)));
+                        buffer.push_str(line);
+                    }
+                    _ => buffer.push('~'),
+                }
+            } else {
+                self.editor_draw_row(buffer, filerow);
+            }
+
+            buffer.push_str("\x1b[K");
+            buffer.push_str("\r\n");
+        }
+    }
+
+    pub(crate) fn editor_draw_comparison(&self, buffer: &mut String, comparison: &Comparison) {
+        let top = comparison.left_lines[self.rowoff.min(self.buffer.rows.len())];
+        let textcols = self.editor_text_cols();
+        let separator = self.editor_gutter_width() + textcols + 1;
+
+        for y in 0..self.screenrows {
+            let line = comparison.lines.get(top + y);
+
+            match line {
+                Some(&(Some(filerow), _)) => self.editor_draw_row(buffer, filerow),
+                Some(&(None, _)) => {}
+                None => buffer.push('~'),
+            }
+
+            buffer.push_str("\x1b[K\x1b[m");
+            buffer.push_str(&format!("\x1b[{}G\x1b[7m \x1b[m", separator));
+
+            match line {
+                Some(&(_, Some(filerow))) => {
+                    let row = &comparison.rows[filerow];
+                    if comparison.changed_right[filerow] {
+                        buffer.push_str(DIFF_CHANGED_STYLE);
+                    }
+
+                    let mut current = Highlight::Normal;
+                    let chars = row.render.chars().enumerate().skip(self.coloff).take(textcols);
+                    for (i, c) in chars {
+                        let highlight = row.hl.get(i).cloned().unwrap_or(Highlight::Normal);
+                        if highlight != current {
+                            buffer.push_str("\x1b[39;22;24m");
+                            buffer.push_str(highlight.style());
+                            current = highlight;
+                        }
+                        buffer.push(c);
+                    }
+                }
+                Some(&(_, None)) => {}
+                None => buffer.push('~'),
+            }
+
+            buffer.push_str("\x1b[K\x1b[m");
+            buffer.push_str("\r\n");
+        }
+    }
+
+    /// Screen row of file row `at`, accounting for blank lines in a comparison.
+    pub(crate) fn editor_screen_row(&self, at: usize) -> usize {
+        match self.comparison {
+            Some(ref c) => {
+                let line = |row: usize| c.left_lines[row.min(self.buffer.rows.len())];
+                line(at).saturating_sub(line(self.rowoff))
+            }
+            None => at - self.rowoff,
+        }
+    }
+
+    pub(crate) fn editor_draw_gutter(&self, buffer: &mut String, filerow: usize) {
+        let width = self.editor_gutter_width();
+
+        if width == 0 {
+            return;
+        }
+
+        if self.diagnostics.iter().any(|d| d.line == filerow) {
+            buffer.push_str("\x1b[33m>\x1b[m");
+        } else {
+            buffer.push(' ');
+        }
+
+        match self.git.changes.get(filerow) {
+            Some(&Some(change)) => buffer.push_str(change.marker()),
+            _ => buffer.push(' '),
+        }
+
+        for _ in 2..width {
+            buffer.push(' ');
+        }
+    }
+
+    pub(crate) fn editor_draw_row(&self, buffer: &mut String, filerow: usize) {
+        self.editor_draw_gutter(buffer, filerow);
+
+        let render = self.buffer.rows[filerow].render.chars().collect::<Vec<char>>();
+        let start = self.coloff.min(render.len());
+        let end = (self.coloff + self.editor_text_cols()).min(render.len());
+        let visible = &render[start..end];
+
+        let conflict = self.conflicts.iter().find(|c| c.start <= filerow && filerow <= c.end);
+        let changed = self.comparison.as_ref().is_some_and(|c| c.changed_left[filerow]);
+        let base = if self.config.highlight_current_line && filerow == self.cy {
+            Some(CURRENT_LINE_STYLE)
+        } else if changed {
+            Some(DIFF_CHANGED_STYLE)
+        } else {
+            conflict.map(|c| {
+                if [c.start, c.middle, c.end].contains(&filerow) || c.base == Some(filerow) {
+                    CONFLICT_MARKER_STYLE
+                } else if filerow > c.middle {
+                    CONFLICT_THEIRS_STYLE
+                } else if c.base.is_some_and(|b| filerow > b) {
+                    CONFLICT_BASE_STYLE
+                } else {
+                    CONFLICT_OURS_STYLE
+                }
+            })
+        };
+        if let Some(style) = base {
+            buffer.push_str(style);
+        }
+        let spans = self.editor_overlay_spans(filerow);
+        let mut styles = vec![base; visible.len()];
+
+        // Only the ruler reaches past the end of the text, over blank cells.
+        let cols = self.editor_text_cols();
+        for (from, to, style) in &spans {
+            while styles.len() < cols && styles.len() + self.coloff < *to {
+                styles.push(base);
+            }
+            for i in *from..*to {
+                if i >= self.coloff && i - self.coloff < styles.len() {
+                    styles[i - self.coloff] = Some(style);
+                }
+            }
+        }
+
+        // Mark rows clipped by horizontal scrolling or the right edge.
+        let clipped_left = start > 0 && !visible.is_empty();
+        let clipped_right = render.len() > end && !visible.is_empty();
+        if clipped_left {
+            styles[0] = Some(TRUNCATION_STYLE);
+        }
+        if clipped_right {
+            styles[visible.len() - 1] = Some(TRUNCATION_STYLE);
+        }
+
+        // References to files only mean something in output from grep or a compiler, which
+        // has no syntax of its own.
+        let mut links = Vec::new();
+        if self.editor_hyperlinks() {
+            for (from, len) in find_urls(&render) {
+                links.push((from, len, render[from..(from + len)].iter().collect::<String>()));
+            }
+            if self.highlight.syntax.is_none() {
+                for (from, len, path_len) in find_file_references(&render) {
+                    let path = render[from..(from + path_len)].iter().collect::<String>();
+                    if let Some(uri) = file_uri(&path).filter(|_| Path::new(&path).is_file()) {
+                        links.push((from, len, uri));
+                    }
+                }
+            }
+        }
+
+        let hl = &self.buffer.rows[filerow].hl;
+        let mut current = (base, Highlight::Normal);
+        let mut current_link = None;
+
+        for (i, &style) in styles.iter().enumerate() {
+            let rx = start + i;
+            let link = links.iter().find(|l| l.0 <= rx && rx < l.0 + l.1).map(|l| &l.2);
+            if link != current_link {
+                buffer.push_str(&hyperlink(link.map_or("", |uri| uri.as_str())));
+                current_link = link;
+            }
+
+            let highlight = hl.get(start + i).cloned().unwrap_or(Highlight::Normal);
+            let c = match i {
+                0 if clipped_left => '<',
+                _ if clipped_right && i + 1 == visible.len() => '>',
+                _ => *visible.get(i).unwrap_or(&' '),
+            };
+
+            if (style, highlight) != current {
+                buffer.push_str("\x1b[m");
+                buffer.push_str(base.unwrap_or(""));
+                if style != base {
+                    buffer.push_str(style.unwrap_or(""));
+                }
+                buffer.push_str(highlight.style());
+                current = (style, highlight);
+            }
+            buffer.push(c);
+        }
+
+        if current_link.is_some() {
+            buffer.push_str(&hyperlink(""));
+        }
+
+        if current != (base, Highlight::Normal) {
+            buffer.push_str("\x1b[m");
+            buffer.push_str(base.unwrap_or(""));
+        }
+
+        if base.is_some() {
+            buffer.push_str("\x1b[K");
+            buffer.push_str("\x1b[m");
+        }
+    }
+
+    pub(crate) fn editor_draw_status_bar(&self, buffer: &mut String) {
+        // Dimmed while the terminal window doesn't have focus.
+        buffer.push_str(if self.terminal.focused.get() { "\x1b[7m" } else { "\x1b[2;7m" });
+        let name = match self.comparison {
+            Some(ref c) => format!("{:.20} <> {:.20}", self.filename, c.filename),
+            None => format!("{:.20}", self.filename),
+        };
+        let status = format!(
+            "{} - {} lines {}",
+            name,
+            self.buffer.rows.len(),
+            match (self.dirty, self.editor_read_only()) {
+                (true, _) => "(modified)",
+                (false, true) => "(read-only)",
+                (false, false) => "",
+            }
+        );
+        let status = truncate_to_width(&status, self.screencols);
+        let filetype = match self.highlight.syntax {
+            Some(i) => &self.highlight.syntaxes[i].filetype,
+            None => "no ft",
+        };
+        let git = match self.git.status {
+            Some(ref git) => format!("{}{} | ", git.branch, if git.dirty { "*" } else { "" }),
+            None => String::new(),
+        };
+        let segments = self
+            .plugins.loaded
+            .iter()
+            .filter_map(|plugin| plugin.status(self))
+            .map(|segment| format!("{} | ", segment))
+            .collect::<String>();
+        let offset = match self.config.show_offset {
+            true => {
+                let (offset, total) = self.editor_byte_offset();
+                format!("byte {} of {} | ", offset, total)
+            }
+            false => String::new(),
+        };
+        let mode = match (self.vi_mode, self.overwrite) {
+            (Some(mode), _) => format!("{} | ", mode.name()),
+            (None, true) => "OVR | ".to_string(),
+            (None, false) => String::new(),
+        };
+        let compression = match self.file.compression {
+            Some(compression) => format!("{} | ", compression.name()),
+            None => String::new(),
+        };
+        let rstatus = format!(
+            "{}{}{}{}{}{} | {}/{}",
+            mode,
+            offset,
+            segments,
+            git,
+            compression,
+            filetype,
+            self.cy + 1,
+            self.buffer.rows.len()
+        );
+        // The right-hand part is only shown if it fits; either way the bar fills the line.
+        let len = str_width(status);
+        let rlen = str_width(&rstatus);
+        buffer.push_str(status);
+        if len + rlen <= self.screencols {
+            buffer.extend(iter::repeat_n(' ', self.screencols - len - rlen));
+            buffer.push_str(&rstatus);
+        } else {
+            buffer.extend(iter::repeat_n(' ', self.screencols - len));
+        }
+        buffer.push_str("\x1b[m");
+        buffer.push_str("\r\n");
+    }
+
+    pub(crate) fn editor_draw_message_bar(&self, buffer: &mut String) {
+        buffer.push_str("\x1b[K");
+        let timeout = Duration::from_secs(self.config.message_timeout);
+        if self.message.sticky || timeout.is_zero() || self.message.time.elapsed() < timeout {
+            buffer.push_str(self.message.severity.style());
+            buffer.push_str(truncate_to_width(&self.message.text, self.screencols));
+            buffer.push_str("\x1b[m");
+        }
+    }
+
+    pub(crate) fn editor_refresh_screen(&mut self) -> io::Result<()> {
+        let start = Instant::now();
+        let (lines, cursor) = self.editor_draw_screen();
+        let mut buffer = self.editor_title_update();
+        buffer.push_str("\x1b[?25l");
+
+        // Only the cells that changed since the last frame are sent, so slow terminals don't
+        // visibly repaint the whole screen on every keystroke.
+        let full = self.frame.lines.len() != lines.len() || self.frame.cols != self.screencols;
+        let mut changed = 0;
+        for (y, line) in lines.iter().enumerate() {
+            if full {
+                buffer.push_str(&format!("\x1b[{};1H\x1b[m", y + 1));
+                buffer.push_str(line);
+            } else if self.frame.lines[y] != *line {
+                let old = line_cells(&self.frame.lines[y], self.screencols);
+                buffer.push_str(&line_update(y, &old, &line_cells(line, self.screencols)));
+            } else {
+                continue;
+            }
+            changed += 1;
+        }
+
+        buffer.push_str(&cursor);
+        buffer.push_str("\x1b[?25h");
+
+        self.frame.lines = lines;
+        self.frame.cols = self.screencols;
+
+        write_terminal(buffer.as_bytes())?;
+        if changed > 0 {
+            self.editor_log(format_args!(
+                "render: {} of {} lines, {} bytes in {:?}",
+                changed,
+                self.frame.lines.len(),
+                buffer.len(),
+                start.elapsed()
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// The buffer laid out for printing, as plain text: pages of `KILO_PAGE_LINES` lines
+    /// separated by form feeds, each headed by the file name, the date and the page number,
+    /// with every row rendered as on screen and preceded by its line number.
+    pub(crate) fn editor_hardcopy(&self) -> String {
+        let rows = &self.buffer.rows;
+        let per_page = KILO_PAGE_LINES - 2;
+        let pages = cmp::max(1, rows.len().div_ceil(per_page));
+        let number_width = rows.len().to_string().len();
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH);
+        let date = format_date(now.map_or(0, |d| d.as_secs() as i64));
+
+        let mut text = String::new();
+        for page in 0..pages {
+            if page > 0 {
+                text.push('\x0c');
+            }
+            let name = self.editor_name();
+            text.push_str(&format!("{}  {}  Page {} of {}\n\n", name, date, page + 1, pages));
+
+            for (i, row) in rows.iter().enumerate().skip(page * per_page).take(per_page) {
+                text.push_str(&format!("{:>2$} {}\n", i + 1, row.render, number_width));
+            }
+        }
+
+        text
+    }
+
+    /// A complete frame, redrawing every line.
+    pub(crate) fn editor_render_frame(&mut self) -> String {
+        let (lines, cursor) = self.editor_draw_screen();
+        let mut buffer = self.editor_title_update();
+
+        buffer.push_str("\x1b[?25l");
+        buffer.push_str("\x1b[H");
+        buffer.push_str(&lines.join("\r\n"));
+        buffer.push_str(&cursor);
+        buffer.push_str("\x1b[?25h");
+
+        buffer
+    }
+
+    /// The sequence setting the terminal title, if it changed since it was last set.
+    pub(crate) fn editor_title_update(&mut self) -> String {
+        if !self.base_config.terminal_title {
+            return String::new();
+        }
+
+        let title = format!(
+            "{}{} \u{2014} kilo",
+            self.editor_name(),
+            if self.dirty { " [+]" } else { "" }
+        );
+        if title == self.frame.title {
+            return String::new();
+        }
+
+        let update = format!("\x1b]2;{}\x07", title);
+        self.frame.title = title;
+        update
+    }
+
+    /// Draws the screen, returning its lines and the sequence that places the cursor.
+    pub(crate) fn editor_draw_screen(&mut self) -> (Vec<String>, String) {
+        self.editor_scroll();
+        self.editor_prepare_rows(self.rowoff, self.rowoff + self.screenrows);
+        self.editor_highlight_visible();
+        self.editor_update_selection_overlay();
+
+        let mut buffer = String::new();
+
+        match (&self.picker, &self.diff_view, &self.comparison) {
+            (Some(picker), _, _) => self.editor_draw_picker(&mut buffer, picker),
+            (None, Some(view), _) => self.editor_draw_diff(&mut buffer, view),
+            (None, None, Some(comparison)) => self.editor_draw_comparison(&mut buffer, comparison),
+            (None, None, None) => self.editor_draw_rows(&mut buffer),
+        }
+        self.editor_draw_status_bar(&mut buffer);
+        self.editor_draw_message_bar(&mut buffer);
+
+        let mut lines = buffer.split("\r\n").map(|line| line.to_string()).collect::<Vec<_>>();
+        if let Some(ref completion) = self.completion {
+            self.editor_draw_completion(&mut lines, completion);
+        }
+
+        let cursor = match self.picker {
+            Some(ref picker) => {
+                let col = picker.title.len() + picker.query.len() + 2;
+                let col = col.min(self.screencols.saturating_sub(1));
+                format!("\x1b[1;{}H", col + 1)
+            }
+            None if self.diff_view.is_some() => "\x1b[1;1H".to_string(),
+            None => match self.prompt.cursor {
+                Some(col) => format!(
+                    "\x1b[{};{}H",
+                    self.screenrows + 2,
+                    col.min(self.screencols.saturating_sub(1)) + 1
+                ),
+                None => format!(
+                    "\x1b[{};{}H",
+                    self.editor_screen_row(self.cy) + 1,
+                    (self.rx - self.coloff) + self.editor_gutter_width() + 1
+                ),
+            },
+        };
+
+        (lines, cursor)
+    }
+
+    pub(crate) fn editor_set_status_message(&mut self, message: &str) {
+        self.editor_set_status_message_with(Severity::Info, message);
+    }
+
+    /// Like `editor_set_status_message`, colored by `severity`. Errors also ring the bell.
+    pub(crate) fn editor_set_status_message_with(&mut self, severity: Severity, message: &str) {
+        self.editor_log(format_args!("message ({:?}): {}", severity, message));
+        self.message.text = message.to_string();
+        self.message.severity = severity;
+        self.message.time = Instant::now();
+        self.message.sticky = false;
+
+        if severity == Severity::Error {
+            self.editor_ring_bell();
+        }
+    }
+
+    /// Like `editor_set_status_message_with`, but the message stays up, without timing out or
+    /// being cleared by the next key, until Escape dismisses it or another message replaces it.
+    pub(crate) fn editor_set_sticky_message(&mut self, severity: Severity, message: &str) {
+        self.editor_set_status_message_with(severity, message);
+        self.message.sticky = true;
+    }
+
+    /// Clears the message left by the previous action, unless it is sticky or `force` is set.
+    pub(crate) fn editor_clear_status_message(&mut self, force: bool) {
+        if force || !self.message.sticky {
+            self.message.text.clear();
+            self.message.severity = Severity::Info;
+            self.message.sticky = false;
+        }
+    }
+}
@@ -0,0 +1,357 @@
+//! Incremental search through the buffer, and replacing what it finds.
+
+use super::*;
+
+/// The lines a search query has to match, split where it has `\n`. `\\` stands for a
+/// backslash.
+pub(crate) fn query_lines(query: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut chars = query.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some('n')) => {
+                chars.next();
+                lines.push(mem::take(&mut line));
+            }
+            ('\\', Some('\\')) => {
+                chars.next();
+                line.push('\\');
+            }
+            _ => line.push(c),
+        }
+    }
+    lines.push(line);
+
+    lines
+}
+
+/// Where a search or replacement looks for matches.
+#[derive(Clone, Copy)]
+pub(crate) enum SearchScope {
+    Buffer,
+    /// From the first position up to the second.
+    Selection(Position, Position),
+}
+
+impl Kilo {
+    /// Asks whether to search only the selection, if there is one, and deselects it so that
+    /// moving to the matches doesn't change it. `None` if the question is cancelled.
+    fn editor_search_scope(&mut self) -> io::Result<Option<SearchScope>> {
+        let (first, last) = match self.editor_selection() {
+            Some((first, last)) if first != last => (first, last),
+            _ => return Ok(Some(SearchScope::Buffer)),
+        };
+
+        match self.editor_confirm("Only in the selection?", false)? {
+            Answer::Yes => {
+                self.anchor = None;
+                Ok(Some(SearchScope::Selection(first, last)))
+            }
+            Answer::No => Ok(Some(SearchScope::Buffer)),
+            Answer::All | Answer::Cancel => Ok(None),
+        }
+    }
+
+    /// Like `editor_find_from`, only finding matches inside `scope` and wrapping around its
+    /// ends.
+    fn editor_find_in(
+        &self,
+        query: &str,
+        start: Position,
+        forward: bool,
+        scope: SearchScope,
+    ) -> Option<(Position, Position)> {
+        let (first, last) = match scope {
+            SearchScope::Buffer => return self.editor_find_from(query, start, forward),
+            SearchScope::Selection(first, last) => (first, last),
+        };
+
+        let order = |pos: Position| (pos.cy, pos.cx);
+        let matches = self.editor_matches_between(query, first, last);
+        let found = match forward {
+            true => matches.iter().find(|m| order(m.0) >= order(start)).or(matches.first()),
+            false => matches.iter().rev().find(|m| order(m.0) < order(start)).or(matches.last()),
+        };
+
+        found.cloned()
+    }
+
+    /// The start and end of the first match of `query` at or after `start` (before it, if
+    /// not `forward`), wrapping around the ends of the buffer. A query of several lines
+    /// matches the end of one row, any rows in between whole and the start of the last.
+    pub(crate) fn editor_find_from(
+        &self,
+        query: &str,
+        start: Position,
+        forward: bool,
+    ) -> Option<(Position, Position)> {
+        let n = self.buffer.rows.len();
+        if query.is_empty() || n == 0 {
+            return None;
+        }
+        let start = match start.cy < n {
+            true => start,
+            false => Position { cx: 0, cy: 0 },
+        };
+        let lines = query_lines(query);
+        let last = lines.len() - 1;
+
+        for step in 0..=n {
+            let cy = match forward {
+                true => (start.cy + step) % n,
+                false => (start.cy + n - step % n) % n,
+            };
+            let chars = self.buffer.rows[cy].chars.chars().collect::<Vec<char>>();
+            let (from, to) = match (step, forward) {
+                (0, true) => (start.cx, chars.len() + 1),
+                (0, false) => (0, start.cx.min(chars.len() + 1)),
+                _ => (0, chars.len() + 1),
+            };
+
+            let hit = if last == 0 {
+                let mut candidates = from..to.min(chars.len());
+                match forward {
+                    true => candidates.find(|&j| starts_with_at(&chars, j, &lines[0])),
+                    false => candidates.rev().find(|&j| starts_with_at(&chars, j, &lines[0])),
+                }
+            } else {
+                // Only one place in a row can be followed by the rest of the query.
+                let first = lines[0].chars().collect::<Vec<char>>();
+                let j = chars.len().checked_sub(first.len()).filter(|&j| from <= j && j < to);
+                j.filter(|&j| chars[j..] == first[..] && self.editor_rows_follow(cy, &lines))
+            };
+
+            if let Some(cx) = hit {
+                let end = match last {
+                    0 => Position { cx: cx + lines[0].chars().count(), cy },
+                    _ => Position { cx: lines[last].chars().count(), cy: cy + last },
+                };
+                return Some((Position { cx, cy }, end));
+            }
+        }
+
+        None
+    }
+
+    /// Whether the rows after `cy` match the lines of a query after its first: whole rows,
+    /// then the start of the row the last line is on.
+    fn editor_rows_follow(&self, cy: usize, lines: &[String]) -> bool {
+        let last = lines.len() - 1;
+        let rows = match self.buffer.rows.get((cy + 1)..(cy + 1 + last)) {
+            Some(rows) => rows,
+            None => return false,
+        };
+
+        rows.iter().zip(&lines[1..]).enumerate().all(|(i, (row, line))| match i + 1 == last {
+            true => row.chars.starts_with(line.as_str()),
+            false => row.chars == *line,
+        })
+    }
+
+    /// Highlights the match `found`, or nothing, as the one the search is on.
+    fn editor_set_search_match(&mut self, found: Option<(Position, Position)>) {
+        self.search_match = found;
+        let ranges = found.into_iter().collect();
+        self.editor_set_overlay(Overlay::SearchMatch, SEARCH_MATCH_STYLE, ranges);
+    }
+
+    /// Incremental search: the cursor follows the first match as the query is typed, Ctrl-N
+    /// and Ctrl-P step through the matches, and Escape returns to where the search started,
+    /// scrolled as it was. The prompt says when a match was found by wrapping around.
+    pub(crate) fn editor_find(&mut self) -> io::Result<()> {
+        let origin = self.editor_cursor_position();
+        let (rowoff, coloff, anchor) = (self.rowoff, self.coloff, self.anchor);
+        let scope = match self.editor_search_scope()? {
+            Some(scope) => scope,
+            None => return Ok(()),
+        };
+        // Matches are looked for from the cursor, or from the start of the selection.
+        let (start, bottom, top) = match scope {
+            SearchScope::Buffer => (origin, "BOTTOM", "TOP"),
+            SearchScope::Selection(first, _) => (first, "END OF SELECTION", "START"),
+        };
+        self.editor_set_search_match(None);
+
+        let prompt = "Search: {} (Ctrl-N/Ctrl-P = next/previous, Esc = cancel)";
+        let query = self.editor_prompt_with(prompt, "search", |editor, query, key| {
+            let last = editor.search_match.map(|m| m.0);
+            let (from, forward) = match key {
+                Char(b'\r') | Char(b'\x1b') => return,
+                Char(c) if c == ctrl_key('n') || c == ctrl_key('f') => {
+                    (last.map_or(start, |pos| Position { cx: pos.cx + 1, ..pos }), true)
+                }
+                Char(c) if c == ctrl_key('p') => (last.unwrap_or(start), false),
+                _ => (start, true),
+            };
+            let found = editor.editor_find_in(query, from, forward, scope);
+
+            let order = |pos: Position| (pos.cy, pos.cx);
+            editor.prompt.note = match found.map(|m| m.0) {
+                Some(pos) if forward && order(pos) < order(from) => {
+                    Some(format!("search hit {}, continuing at {}", bottom, top))
+                }
+                Some(pos) if !forward && order(pos) >= order(from) => {
+                    Some(format!("search hit {}, continuing at {}", top, bottom))
+                }
+                _ => None,
+            };
+            editor.editor_set_search_match(found);
+            editor.editor_move_to(found.map_or(origin, |m| m.0));
+        })?;
+
+        let found = self.search_match;
+        self.editor_set_search_match(None);
+        self.editor_move_to(origin);
+        if let (Some(_), Some((pos, _))) = (&query, found) {
+            self.editor_jump_to(pos);
+            return Ok(());
+        }
+
+        // Back where the search started, as if it never happened.
+        self.rowoff = rowoff;
+        self.coloff = coloff;
+        self.anchor = anchor;
+        if let Some(ref query) = query.filter(|query| !query.is_empty()) {
+            self.editor_set_status_message_with(Severity::Error, &format!("Not found: {}", query));
+        }
+
+        Ok(())
+    }
+
+    /// Every match of `query` from `start` to `end`, in order and without overlaps.
+    fn editor_matches_between(
+        &self,
+        query: &str,
+        start: Position,
+        end: Position,
+    ) -> Vec<(Position, Position)> {
+        let order = |pos: Position| (pos.cy, pos.cx);
+        let mut matches = Vec::new();
+        let mut from = start;
+
+        while let Some((first, last)) = self.editor_find_from(query, from, true) {
+            // Past the end, or wrapped around to the start of the buffer.
+            if order(first) < order(from) || order(last) > order(end) {
+                break;
+            }
+            matches.push((first, last));
+            from = last;
+        }
+
+        matches
+    }
+
+    /// Replaces `matches`, which are in order and don't overlap, with `replacement` in one
+    /// edit of the rows they span. Returns where the last replacement ends.
+    fn editor_replace_matches(
+        &mut self,
+        matches: &[(Position, Position)],
+        replacement: &str,
+    ) -> Position {
+        let top = matches[0].0.cy;
+        let bottom = matches[matches.len() - 1].1.cy;
+
+        let mut text = String::new();
+        let mut pos = Position { cx: 0, cy: top };
+        for &(first, last) in matches {
+            text.push_str(&self.buffer.text_range(pos, first));
+            text.push_str(replacement);
+            pos = last;
+        }
+        let end = Position {
+            cx: text.rsplit('\n').next().map_or(0, |line| line.chars().count()),
+            cy: top + text.matches('\n').count(),
+        };
+        let row_end = Position { cx: self.buffer.rows[bottom].chars.chars().count(), cy: bottom };
+        text.push_str(&self.buffer.text_range(pos, row_end));
+
+        let lines = text.split('\n').map(String::from).collect::<Vec<String>>();
+        self.editor_replace_rows(top, bottom + 1, &lines);
+
+        end
+    }
+
+    /// Prompts for text to replace and its replacement, then replaces every match in the
+    /// buffer or, if asked to, the selection, asking about each one first if `confirm` is
+    /// set. All the replacements are made together once the questions are answered.
+    pub(crate) fn editor_replace(&mut self, confirm: bool) -> io::Result<()> {
+        if self.editor_read_only() {
+            let message = format!("{} is read-only", self.filename);
+            self.editor_set_status_message_with(Severity::Warning, &message);
+            return Ok(());
+        }
+
+        let n = self.buffer.rows.len();
+        let (start, end) = match self.editor_search_scope()? {
+            Some(SearchScope::Buffer) => (Position { cx: 0, cy: 0 }, Position { cx: 0, cy: n }),
+            Some(SearchScope::Selection(first, last)) => (first, last),
+            None => return Ok(()),
+        };
+
+        let query = match self.editor_prompt("Replace: {}", "search")? {
+            Some(query) if !query.is_empty() => query,
+            _ => return Ok(()),
+        };
+        let replacement = match self.editor_prompt("Replace with: {}", "replace")? {
+            Some(replacement) => query_lines(&replacement).join("\n"),
+            None => return Ok(()),
+        };
+
+        let mut matches = self.editor_matches_between(&query, start, end);
+        if matches.is_empty() {
+            self.editor_set_status_message_with(Severity::Error, &format!("Not found: {}", query));
+            return Ok(());
+        }
+
+        if confirm {
+            let origin = self.editor_cursor_position();
+            let (rowoff, coloff) = (self.rowoff, self.coloff);
+            let mut accepted = Vec::new();
+
+            for (i, &found) in matches.iter().enumerate() {
+                self.editor_set_search_match(Some(found));
+                self.editor_move_to(found.0);
+                match self.editor_confirm("Replace this match?", true)? {
+                    Answer::Yes => accepted.push(found),
+                    Answer::No => {}
+                    Answer::All => {
+                        accepted.extend_from_slice(&matches[i..]);
+                        break;
+                    }
+                    Answer::Cancel => break,
+                }
+            }
+
+            self.editor_set_search_match(None);
+            self.editor_move_to(origin);
+            self.rowoff = rowoff;
+            self.coloff = coloff;
+            matches = accepted;
+        }
+
+        if matches.is_empty() {
+            self.editor_set_status_message("Replaced nothing");
+            return Ok(());
+        }
+
+        let mut lines = matches.iter().map(|m| m.0.cy).collect::<Vec<usize>>();
+        lines.dedup();
+        self.anchor = None;
+        let pos = self.editor_replace_matches(&matches, &replacement);
+        self.editor_jump_to(pos);
+
+        let occurrences = match matches.len() {
+            1 => "1 occurrence".to_string(),
+            n => format!("{} occurrences", n),
+        };
+        let rows = match lines.len() {
+            1 => "1 line".to_string(),
+            n => format!("{} lines", n),
+        };
+        self.editor_set_status_message(&format!("Replaced {} on {}", occurrences, rows));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,203 @@
+//! Snippets: a trigger word followed by Tab expands to the template configured for it with
+//! `snippet.<trigger>`, and Tab then steps through the template's fields.
+
+use super::*;
+
+/// A field of an expanded snippet: `$1`, or `${1:text}` with text to start from.
+#[derive(Clone, Copy)]
+pub(crate) struct SnippetField {
+    pub(crate) number: usize,
+    pub(crate) pos: Position,
+    /// The field's current length in characters.
+    pub(crate) len: usize,
+}
+
+/// The text `template` expands to and its fields, positioned relative to where the text
+/// starts. `\n` and `\t` in the template are a newline and a tab, every new line starts with
+/// `indent`, and a backslash takes the next character literally.
+pub(crate) fn parse_snippet(template: &str, indent: &str) -> (String, Vec<SnippetField>) {
+    let mut text = String::new();
+    let mut fields = Vec::new();
+    let mut pos = Position { cx: 0, cy: 0 };
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => {
+                    text.push('\n');
+                    text.push_str(indent);
+                    pos = Position { cx: indent.chars().count(), cy: pos.cy + 1 };
+                }
+                Some(c) => {
+                    text.push(if c == 't' { '\t' } else { c });
+                    pos.cx += 1;
+                }
+                None => {}
+            },
+            '$' if chars.peek().is_some_and(|&c| c.is_ascii_digit() || c == '{') => {
+                let braced = chars.next_if_eq(&'{').is_some();
+                let mut number = String::new();
+                while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                    number.push(digit);
+                }
+
+                let mut default = String::new();
+                if braced {
+                    if chars.next_if_eq(&':').is_some() {
+                        while let Some(c) = chars.next_if(|&c| c != '}') {
+                            default.push(c);
+                        }
+                    }
+                    chars.next();
+                }
+
+                let len = default.chars().count();
+                let number = number.parse().unwrap_or(0);
+                fields.push(SnippetField { number, pos, len });
+                text.push_str(&default);
+                pos.cx += len;
+            }
+            c => {
+                text.push(c);
+                pos.cx += 1;
+            }
+        }
+    }
+
+    // Without a `$0`, the cursor ends up after the snippet.
+    if !fields.iter().any(|field| field.number == 0) {
+        fields.push(SnippetField { number: 0, pos, len: 0 });
+    }
+
+    (text, fields)
+}
+
+impl Kilo {
+    /// Expands the snippet whose trigger is the word before the cursor and has its fields
+    /// filled in, returning false if there is no such snippet.
+    pub(crate) fn editor_expand_snippet(&mut self) -> io::Result<bool> {
+        let row = match self.buffer.rows.get(self.cy) {
+            Some(row) => row,
+            None => return Ok(false),
+        };
+        let before = row.chars.chars().take(self.cx).collect::<Vec<char>>();
+        let start = before.iter().rposition(|&c| !is_word_char(c)).map_or(0, |i| i + 1);
+
+        let trigger = before[start..].iter().collect::<String>();
+        let template = match self.config.snippets.get(&trigger) {
+            Some(template) if !trigger.is_empty() => template.clone(),
+            _ => return Ok(false),
+        };
+
+        let indent = row.chars.chars().take_while(|&c| c == ' ' || c == '\t');
+        let indent = indent.collect::<String>();
+        let (text, mut fields) = parse_snippet(&template, &indent);
+        for field in &mut fields {
+            if field.pos.cy == 0 {
+                field.pos.cx += start;
+            }
+            field.pos.cy += self.cy;
+        }
+
+        let pos = self.editor_cursor_position();
+        self.editor_delete_range(Position { cx: start, cy: self.cy }, pos);
+        self.editor_insert_str(&text);
+        self.editor_fill_snippet(fields)?;
+
+        Ok(true)
+    }
+
+    /// Steps through the fields in number order, with `$0` last. Each field's text is
+    /// selected until a key edits it, typing replaces it, and every field with the same
+    /// number follows along; Tab or Enter goes to the next field and Escape stops.
+    fn editor_fill_snippet(&mut self, mut fields: Vec<SnippetField>) -> io::Result<()> {
+        let mut numbers = fields.iter().map(|field| field.number).collect::<Vec<usize>>();
+        numbers.sort_unstable_by_key(|&number| (number == 0, number));
+        numbers.dedup();
+
+        'fields: for number in numbers {
+            let field = match fields.iter().find(|field| field.number == number) {
+                Some(&field) if number != 0 => field,
+                Some(&field) => {
+                    self.editor_move_to(field.pos);
+                    break;
+                }
+                None => continue,
+            };
+
+            let end = Position { cx: field.pos.cx + field.len, ..field.pos };
+            let mut input = Prompt::new();
+            input.set_input(self.editor_text_range(field.pos, end).chars().collect());
+            let mut fresh = true;
+
+            loop {
+                let field = fields.iter().find(|field| field.number == number).copied();
+                let pos = field.map_or(self.editor_cursor_position(), |field| field.pos);
+                self.anchor = match fresh && !input.input.is_empty() {
+                    true => Some(pos),
+                    false => None,
+                };
+                self.editor_move_to(Position { cx: pos.cx + input.cursor, ..pos });
+                self.editor_set_status_message("Snippet: Tab for the next field, Escape to stop");
+                self.editor_update_selection_overlay();
+                self.editor_refresh_screen()?;
+
+                let key = self.editor_wait_key()?;
+                let previous = input.text();
+                match key {
+                    Char(b'\t') | Char(b'\r') => break,
+                    Char(b'\x1b') => break 'fields,
+                    Char(c) if fresh && (c == 127 || c == ctrl_key('h') || c >= b' ') => {
+                        input.set_input(Vec::new());
+                    }
+                    Text(_) if fresh => input.set_input(Vec::new()),
+                    _ => {}
+                }
+                fresh = false;
+
+                input.handle_key(key, &[]);
+                let text = input.text();
+                if text != previous {
+                    self.editor_set_snippet_field(&mut fields, number, &text);
+                }
+            }
+        }
+
+        self.anchor = None;
+        self.editor_update_selection_overlay();
+        self.editor_set_status_message("");
+        Ok(())
+    }
+
+    /// Replaces the text of every field numbered `number` with `text`, moving the fields
+    /// after them on the same row along.
+    fn editor_set_snippet_field(
+        &mut self,
+        fields: &mut [SnippetField],
+        number: usize,
+        text: &str,
+    ) {
+        let len = text.chars().count();
+
+        for i in 0..fields.len() {
+            let field = fields[i];
+            if field.number != number {
+                continue;
+            }
+
+            let end = Position { cx: field.pos.cx + field.len, ..field.pos };
+            self.editor_delete_range(field.pos, end);
+            self.editor_move_to(field.pos);
+            self.editor_insert_str(text);
+
+            for (j, other) in fields.iter_mut().enumerate() {
+                let after = other.pos.cx > field.pos.cx || (other.pos.cx == field.pos.cx && j > i);
+                if other.pos.cy == field.pos.cy && after {
+                    other.pos.cx = other.pos.cx - field.len + len;
+                }
+            }
+            fields[i].len = len;
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! Spell checking against a word list, with suggestions for the misspellings Alt-S visits.
+
+use super::*;
+
+pub(crate) struct Dictionary {
+    words: HashSet<String>,
+}
+
+impl Dictionary {
+    fn load(path: &Path) -> io::Result<Self> {
+        let mut words = HashSet::new();
+
+        for (i, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+            let line = line?;
+            // Hunspell .dic files start with a word count and tag words with /FLAGS.
+            if i == 0 && line.trim().parse::<usize>().is_ok() {
+                continue;
+            }
+            let word = line.split('/').next().unwrap_or("").trim();
+            if !word.is_empty() {
+                words.insert(word.to_lowercase());
+            }
+        }
+
+        Ok(Dictionary { words })
+    }
+
+    fn contains(&self, word: &str) -> bool {
+        self.words.contains(&word.to_lowercase())
+    }
+
+    pub(crate) fn misspellings(&self, line: &[char]) -> Vec<(usize, usize)> {
+        let mut misspellings = Vec::new();
+        let mut i = 0;
+
+        while i < line.len() {
+            if !is_word_char(line[i]) {
+                i += 1;
+                continue;
+            }
+
+            let start = i;
+            while i < line.len() && is_word_char(line[i]) {
+                i += 1;
+            }
+
+            let word = &line[start..i];
+            if word.len() > 1 && word.iter().all(|c| c.is_alphabetic()) {
+                let word = word.iter().collect::<String>();
+                if !self.contains(&word) {
+                    misspellings.push((start, i - start));
+                }
+            }
+        }
+
+        misspellings
+    }
+
+    fn suggestions(&self, word: &str) -> Vec<String> {
+        let word = word.to_lowercase().chars().collect::<Vec<char>>();
+        let mut candidates = Vec::new();
+
+        for i in 0..(word.len() + 1) {
+            if i < word.len() {
+                let mut deletion = word.clone();
+                deletion.remove(i);
+                candidates.push(deletion);
+            }
+            if i + 1 < word.len() {
+                let mut transposition = word.clone();
+                transposition.swap(i, i + 1);
+                candidates.push(transposition);
+            }
+            for c in b'a'..(b'z' + 1) {
+                if i < word.len() {
+                    let mut replacement = word.clone();
+                    replacement[i] = c as char;
+                    candidates.push(replacement);
+                }
+                let mut insertion = word.clone();
+                insertion.insert(i, c as char);
+                candidates.push(insertion);
+            }
+        }
+
+        let mut suggestions = Vec::new();
+        for candidate in candidates {
+            let candidate = candidate.into_iter().collect::<String>();
+            if self.words.contains(&candidate) && !suggestions.contains(&candidate) {
+                suggestions.push(candidate);
+                if suggestions.len() == KILO_SUGGESTIONS {
+                    break;
+                }
+            }
+        }
+
+        suggestions
+    }
+}
+
+impl Kilo {
+    pub(crate) fn editor_next_misspelling(&mut self) {
+        let dictionary = match self.dictionary {
+            Some(ref dictionary) if self.config.spell_check => dictionary,
+            _ => {
+                self.editor_set_status_message("Spell checking is disabled");
+                return;
+            }
+        };
+
+        let mut found = None;
+
+        for offset in 0..(self.buffer.rows.len() + 1) {
+            let cy = (self.cy + offset) % self.buffer.rows.len().max(1);
+            let row = match self.buffer.rows.get(cy) {
+                Some(row) => row,
+                None => break,
+            };
+            let chars = row.chars.chars().collect::<Vec<char>>();
+
+            let next = dictionary
+                .misspellings(&chars)
+                .into_iter()
+                .find(|&(start, _)| offset > 0 || start > self.cx);
+
+            if let Some((start, len)) = next {
+                let word = chars[start..(start + len)].iter().collect::<String>();
+                found = Some((Position { cx: start, cy }, word));
+                break;
+            }
+        }
+
+        match found {
+            Some((pos, word)) => {
+                let suggestions = dictionary.suggestions(&word);
+                let message = if suggestions.is_empty() {
+                    format!("'{}': no suggestions", word)
+                } else {
+                    format!("'{}': {}", word, suggestions.join(", "))
+                };
+
+                self.editor_jump_to(pos);
+                self.editor_set_status_message(&message);
+            }
+            None => self.editor_set_status_message("No misspellings found"),
+        }
+    }
+
+    pub(crate) fn editor_load_dictionary(&mut self) {
+        let path = self
+            .config
+            .spell_dictionary
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(KILO_DICTIONARY));
+
+        match Dictionary::load(&path) {
+            Ok(dictionary) => self.dictionary = Some(dictionary),
+            Err(e) => {
+                let message = format!("Can't load dictionary {}: {}", path.display(), e);
+                self.editor_set_status_message_with(Severity::Error, &message);
+            }
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! Where file contents come from and go to: local or remote files, decompressing gzip and
+//! xz files on the way.
+
+use super::*;
+
+mod remote;
+
+pub(crate) use self::remote::*;
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Compression {
+    Gzip,
+    Xz,
+}
+
+impl Compression {
+    /// The compression of a file from its magic bytes, or from its extension if it is empty
+    /// or new, so that a new `.gz` file is saved compressed too.
+    pub(crate) fn detect(filename: &str, bytes: &[u8]) -> Option<Compression> {
+        if bytes.starts_with(b"\x1f\x8b") {
+            Some(Compression::Gzip)
+        } else if bytes.starts_with(b"\xfd7zXZ\0") {
+            Some(Compression::Xz)
+        } else if !bytes.is_empty() {
+            None
+        } else if filename.ends_with(".gz") {
+            Some(Compression::Gzip)
+        } else if filename.ends_with(".xz") {
+            Some(Compression::Xz)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gzip",
+            Compression::Xz => "xz",
+        }
+    }
+
+    pub(crate) fn compress(self, bytes: &[u8]) -> io::Result<Vec<u8>> {
+        self.filter("-c", bytes)
+    }
+
+    pub(crate) fn decompress(self, bytes: &[u8]) -> io::Result<Vec<u8>> {
+        self.filter("-dc", bytes)
+    }
+
+    fn filter(self, flags: &str, bytes: &[u8]) -> io::Result<Vec<u8>> {
+        let output = run_shell(&format!("{} {}", self.name(), flags), Some(bytes))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let reason = stderr.lines().next().unwrap_or("command failed");
+            return Err(io::Error::other(format!("{}: {}", self.name(), reason)));
+        }
+
+        Ok(output.stdout)
+    }
+}
+
+/// `filename` without a `.gz` or `.xz` extension, for choosing the syntax and settings of
+/// a compressed file by the name it has uncompressed.
+pub(crate) fn uncompressed_name(filename: &str) -> &str {
+    filename.strip_suffix(".gz").or_else(|| filename.strip_suffix(".xz")).unwrap_or(filename)
+}
+
+/// Decompressed file contents, and the compression they were stored with.
+pub(crate) type Contents = (Vec<u8>, Option<Compression>);
+
+/// The contents of `filename`, decompressed, and the compression it used.
+pub(crate) fn read_file(filename: &str) -> io::Result<Contents> {
+    decompress(filename, fs::read(filename)?)
+}
+
+/// `bytes` read from `filename`, decompressed, and the compression they used.
+pub(crate) fn decompress(filename: &str, bytes: Vec<u8>) -> io::Result<Contents> {
+    match Compression::detect(filename, &bytes) {
+        Some(compression) if !bytes.is_empty() => {
+            Ok((compression.decompress(&bytes)?, Some(compression)))
+        }
+        compression => Ok((bytes, compression)),
+    }
+}
+
+pub(crate) fn file_mtime(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+pub(crate) fn load_recent_files(path: &Path) -> io::Result<Vec<String>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(contents.lines().map(|l| l.to_string()).collect()),
+        Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+pub(crate) fn load_registers(path: &Path) -> io::Result<HashMap<char, String>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut registers = HashMap::new();
+
+    for line in contents.lines() {
+        let mut parts = line.splitn(2, '\t');
+
+        if let (Some(name), Some(text)) = (parts.next(), parts.next()) {
+            if let Some(name) = name.chars().next() {
+                registers.insert(name, unescape_register(text));
+            }
+        }
+    }
+
+    Ok(registers)
+}
+
+pub(crate) fn escape_register(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+pub(crate) fn unescape_register(text: &str) -> String {
+    let mut result = String::new();
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                result.push('\n');
+                chars.next();
+            }
+            ('\\', Some('\\')) => {
+                result.push('\\');
+                chars.next();
+            }
+            _ => result.push(c),
+        }
+    }
+
+    result
+}
+
+pub(crate) fn load_positions(path: &Path) -> io::Result<Vec<(PathBuf, Position)>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut positions = Vec::new();
+
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        let mut parts = line.splitn(3, '\t');
+
+        let cy = parts.next().and_then(|p| p.parse().ok());
+        let cx = parts.next().and_then(|p| p.parse().ok());
+
+        if let (Some(cy), Some(cx), Some(filename)) = (cy, cx, parts.next()) {
+            positions.push((PathBuf::from(filename), Position { cx, cy }));
+        }
+    }
+
+    Ok(positions)
+}
+
+/// Where the open file is stored and how it was last seen there.
+pub(crate) struct FileState {
+    pub(crate) compression: Option<Compression>,
+    pub(crate) remote: Option<Remote>,
+    pub(crate) remote_fetch: Option<Receiver<io::Result<Contents>>>,
+    /// Modification time of the file when it was last read or written.
+    pub(crate) disk_mtime: Option<SystemTime>,
+    pub(crate) disk_checked: Instant,
+}
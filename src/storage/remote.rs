@@ -0,0 +1,202 @@
+//! Files on other machines, read and written over ssh or fetched over http.
+
+use super::*;
+
+/// A file that isn't on the local disk.
+#[derive(Clone)]
+pub(crate) enum Remote {
+    /// A file on another machine, read and written over ssh.
+    Ssh {
+        host: String,
+        port: Option<String>,
+        path: String,
+    },
+    /// A file served over http or https, which can only be read.
+    Http(String),
+}
+
+impl Remote {
+    /// The remote file named by an http(s) URL, by `scp://[user@]host[:port]/path` (or
+    /// `sftp://`), where the path is relative to the home directory unless it starts with a
+    /// second slash, or by `user@host:path` as scp takes it. A local file of the same name
+    /// takes precedence over the last form.
+    pub(crate) fn parse(name: &str) -> Option<Remote> {
+        if name.starts_with("http://") || name.starts_with("https://") {
+            return Some(Remote::Http(name.to_string()));
+        }
+
+        let url = name.strip_prefix("scp://").or_else(|| name.strip_prefix("sftp://"));
+        let (host, port, path) = match url {
+            Some(url) => {
+                let (authority, path) = url.split_once('/')?;
+                match authority.rsplit_once(':') {
+                    Some((host, port)) => (host, Some(port), path),
+                    None => (authority, None, path),
+                }
+            }
+            None => {
+                let (host, path) = name.split_once(':')?;
+                if !host.contains('@') || host.contains('/') || Path::new(name).exists() {
+                    return None;
+                }
+                (host, None, path)
+            }
+        };
+
+        if host.is_empty() || path.is_empty() || port.is_some_and(|p| p.parse::<u16>().is_err()) {
+            return None;
+        }
+
+        Some(Remote::Ssh {
+            host: host.to_string(),
+            port: port.map(String::from),
+            path: path.to_string(),
+        })
+    }
+
+    pub(crate) fn host(&self) -> &str {
+        match *self {
+            Remote::Ssh { ref host, .. } => host,
+            Remote::Http(ref url) => url.split('/').nth(2).unwrap_or(url),
+        }
+    }
+
+    pub(crate) fn read_only(&self) -> bool {
+        match *self {
+            Remote::Ssh { .. } => false,
+            Remote::Http(_) => true,
+        }
+    }
+
+    pub(crate) fn read(&self) -> io::Result<Vec<u8>> {
+        match *self {
+            Remote::Ssh { ref path, .. } => {
+                self.ssh(&format!("cat -- {}", shell_quote(path)), None)
+            }
+            Remote::Http(ref url) => {
+                let output = run_shell(&format!("curl -fsSL -- {}", shell_quote(url)), None)?;
+                if !output.status.success() {
+                    let stderr = String::from_utf8_lossy(&output.stderr);
+                    let reason = stderr.lines().next().unwrap_or("curl failed");
+                    return Err(io::Error::other(reason.to_string()));
+                }
+                Ok(output.stdout)
+            }
+        }
+    }
+
+    pub(crate) fn write(&self, bytes: &[u8]) -> io::Result<()> {
+        match *self {
+            Remote::Ssh { ref path, .. } => {
+                self.ssh(&format!("cat > {}", shell_quote(path)), Some(bytes)).map(|_| ())
+            }
+            Remote::Http(_) => Err(io::Error::other("files served over http are read-only")),
+        }
+    }
+
+    /// Runs `command` on the host. Batch mode makes ssh fail instead of prompting for a
+    /// password, which it couldn't do while the terminal is in raw mode.
+    fn ssh(&self, command: &str, input: Option<&[u8]>) -> io::Result<Vec<u8>> {
+        let port = match *self {
+            Remote::Ssh { port: Some(ref port), .. } => format!("-p {} ", port),
+            _ => String::new(),
+        };
+        let ssh = format!(
+            "ssh -o BatchMode=yes {}{} {}",
+            port,
+            shell_quote(self.host()),
+            shell_quote(command)
+        );
+
+        let output = run_shell(&ssh, input)?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let reason = stderr.lines().next().unwrap_or("ssh failed");
+            return Err(io::Error::other(format!("{}: {}", self.host(), reason)));
+        }
+
+        Ok(output.stdout)
+    }
+}
+
+impl Kilo {
+    pub(crate) fn editor_read_only(&self) -> bool {
+        self.file.remote.as_ref().is_some_and(|remote| remote.read_only())
+    }
+
+    /// Starts fetching a remote file in the background; `editor_poll_remote` loads it into
+    /// the buffer once it arrives.
+    pub(crate) fn editor_fetch_remote(&mut self, filename: &str, remote: Remote) {
+        self.filename = filename.to_string();
+        self.buffer.rows.clear();
+        self.buffer.rows_changed();
+        self.cx = 0;
+        self.cy = 0;
+        self.rowoff = 0;
+        self.coloff = 0;
+        self.dirty = false;
+        self.editor_set_status_message(&format!("Fetching {}...", filename));
+
+        let (sender, receiver) = mpsc::channel();
+        let (name, fetch) = (filename.to_string(), remote.clone());
+        thread::spawn(move || {
+            let _ = sender.send(fetch.read().and_then(|bytes| decompress(&name, bytes)));
+        });
+        self.file.remote = Some(remote);
+        self.file.remote_fetch = Some(receiver);
+    }
+
+    pub(crate) fn editor_poll_remote(&mut self) -> io::Result<()> {
+        let result = match self.file.remote_fetch {
+            Some(ref fetch) => match fetch.try_recv() {
+                Ok(result) => result,
+                Err(mpsc::TryRecvError::Empty) => return Ok(()),
+                Err(mpsc::TryRecvError::Disconnected) => Err(io::Error::other("fetch failed")),
+            },
+            None => return Ok(()),
+        };
+        self.file.remote_fetch = None;
+
+        let filename = self.filename.clone();
+        match result {
+            Ok((bytes, compression)) => self.editor_load(&filename, &bytes, compression),
+            Err(e) => {
+                self.editor_set_status_message_with(
+                    Severity::Error,
+                    &format!("Can't fetch {}: {}", filename, e),
+                );
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(name: &str) -> Option<String> {
+        Remote::parse(name).map(|remote| match remote {
+            Remote::Ssh { host, port, path } => {
+                format!("ssh {} {} {}", host, port.unwrap_or_default(), path)
+            }
+            Remote::Http(url) => format!("http {}", url),
+        })
+    }
+
+    #[test]
+    fn parse_names() {
+        let url = "https://example.com/a.txt";
+        assert_eq!(parse(url), Some(format!("http {}", url)));
+        assert_eq!(parse("scp://me@host/notes.txt"), Some("ssh me@host  notes.txt".into()));
+        assert_eq!(parse("sftp://host:2222//etc/x"), Some("ssh host 2222 /etc/x".into()));
+        assert_eq!(parse("me@host:notes.txt"), Some("ssh me@host  notes.txt".into()));
+
+        assert_eq!(parse("scp://host:port/x"), None);
+        assert_eq!(parse("scp://host/"), None);
+        assert_eq!(parse("scp:///x"), None);
+        assert_eq!(parse("host:notes.txt"), None);
+        assert_eq!(parse("dir/me@host:x"), None);
+        assert_eq!(parse("notes.txt"), None);
+    }
+}
@@ -0,0 +1,743 @@
+//! Syntax definitions and per-row highlighting.
+
+use super::*;
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Highlight {
+    Normal,
+    Comment,
+    MlComment,
+    Keyword1,
+    Keyword2,
+    Str,
+    Number,
+    Heading,
+    Bullet,
+    Link,
+    Code,
+}
+
+impl Highlight {
+    pub(crate) fn style(self) -> &'static str {
+        match self {
+            Highlight::Comment | Highlight::MlComment => "\x1b[36m",
+            Highlight::Keyword1 => "\x1b[33m",
+            Highlight::Keyword2 => "\x1b[32m",
+            Highlight::Str => "\x1b[35m",
+            Highlight::Number => "\x1b[31m",
+            Highlight::Heading => "\x1b[1;34m",
+            Highlight::Bullet => "\x1b[1;33m",
+            Highlight::Link => "\x1b[4;34m",
+            Highlight::Code => "\x1b[32m",
+            Highlight::Normal => "",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct EditorSyntax {
+    pub(crate) filetype: String,
+    pub(crate) filematch: Vec<String>,
+    pub(crate) keywords: Vec<String>,
+    pub(crate) singleline_comment_start: String,
+    pub(crate) multiline_comment_start: String,
+    pub(crate) multiline_comment_end: String,
+    pub(crate) string_quotes: Vec<char>,
+    pub(crate) highlight_numbers: bool,
+    pub(crate) markdown: bool,
+    pub(crate) embedded: Vec<EmbeddedRule>,
+}
+
+/// Delimiters of a region highlighted by another syntax, e.g. `<script>` in HTML.
+#[derive(Clone)]
+pub(crate) struct EmbeddedRule {
+    pub(crate) start: String,
+    pub(crate) end: String,
+    pub(crate) filetype: String,
+}
+
+pub(crate) fn to_strings(items: &[&str]) -> Vec<String> {
+    items.iter().map(|s| s.to_string()).collect()
+}
+
+impl EditorSyntax {
+    pub(crate) fn builtin() -> Vec<EditorSyntax> {
+        vec![
+            EditorSyntax {
+                filetype: "c".to_string(),
+                filematch: to_strings(&[".c", ".h", ".cpp"]),
+                keywords: to_strings(&[
+                    "switch", "if", "while", "for", "break", "continue", "return", "else", "struct",
+                    "union", "typedef", "static", "enum", "class", "case", "int|", "long|",
+                    "double|", "float|", "char|", "unsigned|", "signed|", "void|",
+                ]),
+                singleline_comment_start: "//".to_string(),
+                multiline_comment_start: "/*".to_string(),
+                multiline_comment_end: "*/".to_string(),
+                string_quotes: vec!['"', '\''],
+                highlight_numbers: true,
+                markdown: false,
+                embedded: Vec::new(),
+            },
+            EditorSyntax {
+                filetype: "rust".to_string(),
+                filematch: to_strings(&[".rs"]),
+                keywords: to_strings(&[
+                    "as", "break", "const", "continue", "crate", "else", "enum", "extern", "fn",
+                    "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+                    "ref", "return", "self", "Self", "static", "struct", "trait", "type", "unsafe",
+                    "use", "where", "while", "bool|", "char|", "str|", "u8|", "u16|", "u32|",
+                    "u64|", "usize|", "i8|", "i16|", "i32|", "i64|", "isize|", "f32|", "f64|",
+                    "String|", "Vec|", "Option|", "Result|",
+                ]),
+                singleline_comment_start: "//".to_string(),
+                multiline_comment_start: "/*".to_string(),
+                multiline_comment_end: "*/".to_string(),
+                string_quotes: vec!['"'],
+                highlight_numbers: true,
+                markdown: false,
+                embedded: Vec::new(),
+            },
+            EditorSyntax {
+                filetype: "markdown".to_string(),
+                filematch: to_strings(&[".md", ".markdown"]),
+                keywords: Vec::new(),
+                singleline_comment_start: String::new(),
+                multiline_comment_start: String::new(),
+                multiline_comment_end: String::new(),
+                string_quotes: Vec::new(),
+                highlight_numbers: false,
+                markdown: true,
+                embedded: Vec::new(),
+            },
+        ]
+    }
+
+    pub(crate) fn from_json(json: &Json) -> Option<EditorSyntax> {
+        let strings = |key: &str| -> Vec<String> {
+            json.get(key)
+                .and_then(|v| v.as_array())
+                .map(|items| items.iter().filter_map(|i| i.as_str().map(String::from)).collect())
+                .unwrap_or_default()
+        };
+
+        let mut keywords = strings("keywords");
+        keywords.extend(strings("types").into_iter().map(|t| format!("{}|", t)));
+
+        let multiline = strings("multiline_comment");
+        let (ml_start, ml_end) = match multiline.len() {
+            2 => (multiline[0].clone(), multiline[1].clone()),
+            _ => (String::new(), String::new()),
+        };
+
+        Some(EditorSyntax {
+            filetype: json.get("filetype")?.as_str()?.to_string(),
+            filematch: strings("filematch"),
+            keywords,
+            singleline_comment_start: json
+                .get("singleline_comment")
+                .and_then(|v| v.as_str())
+                .unwrap_or("")
+                .to_string(),
+            multiline_comment_start: ml_start,
+            multiline_comment_end: ml_end,
+            string_quotes: json
+                .get("strings")
+                .and_then(|v| v.as_str())
+                .unwrap_or("\"'")
+                .chars()
+                .collect(),
+            highlight_numbers: json.get("numbers").and_then(|v| v.as_bool()).unwrap_or(true),
+            markdown: false,
+            embedded: json
+                .get("embedded")
+                .and_then(|v| v.as_array())
+                .map(|rules| rules.iter().filter_map(EmbeddedRule::from_json).collect())
+                .unwrap_or_default(),
+        })
+    }
+
+    pub(crate) fn load_dir(dir: &Path) -> io::Result<(Vec<EditorSyntax>, Vec<String>)> {
+        let mut syntaxes = Vec::new();
+        let mut errors = Vec::new();
+
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok((syntaxes, errors)),
+            Err(e) => return Err(e),
+        };
+
+        let mut paths = entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+            .collect::<Vec<PathBuf>>();
+        paths.sort();
+
+        for path in paths {
+            let name = path.display().to_string();
+            let json = match Json::parse(&fs::read_to_string(&path)?) {
+                Some(json) => json,
+                None => {
+                    errors.push(format!("{}: invalid JSON", name));
+                    continue;
+                }
+            };
+
+            let definitions = match json {
+                Json::Array(items) => items,
+                json => vec![json],
+            };
+
+            for definition in &definitions {
+                match EditorSyntax::from_json(definition) {
+                    Some(syntax) => syntaxes.push(syntax),
+                    None => errors.push(format!("{}: missing filetype", name)),
+                }
+            }
+        }
+
+        Ok((syntaxes, errors))
+    }
+
+    pub(crate) fn matches(&self, filename: &str) -> bool {
+        let extension = Path::new(filename)
+            .extension()
+            .map(|ext| format!(".{}", ext.to_string_lossy()));
+
+        self.filematch.iter().any(|pattern| {
+            if pattern.starts_with('.') {
+                extension.as_ref() == Some(pattern)
+            } else {
+                filename.contains(pattern.as_str())
+            }
+        })
+    }
+}
+
+impl EmbeddedRule {
+    pub(crate) fn from_json(json: &Json) -> Option<EmbeddedRule> {
+        Some(EmbeddedRule {
+            start: json.get("start")?.as_str()?.to_string(),
+            end: json.get("end")?.as_str()?.to_string(),
+            filetype: json.get("filetype")?.as_str()?.to_string(),
+        })
+    }
+}
+
+#[inline]
+pub(crate) fn is_separator(c: char) -> bool {
+    c.is_whitespace() || c == '\0' || ",.()+-/*=~%<>[];{}:&|!".contains(c)
+}
+
+#[inline]
+pub(crate) fn starts_with_at(chars: &[char], i: usize, pattern: &str) -> bool {
+    let mut len = 0;
+
+    for (j, p) in pattern.chars().enumerate() {
+        if chars.get(i + j) != Some(&p) {
+            return false;
+        }
+        len += 1;
+    }
+
+    len > 0
+}
+
+pub(crate) fn highlight_row(
+    chars: &[char],
+    syntax: &EditorSyntax,
+    open_comment: bool,
+) -> (Vec<Highlight>, bool) {
+    let mut hl = vec![Highlight::Normal; chars.len()];
+
+    let scs = &syntax.singleline_comment_start;
+    let mcs = &syntax.multiline_comment_start;
+    let mce = &syntax.multiline_comment_end;
+
+    let mut prev_sep = true;
+    let mut in_string = None;
+    let mut in_comment = open_comment;
+
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let prev_hl = if i > 0 { hl[i - 1] } else { Highlight::Normal };
+
+        if in_string.is_none() && !in_comment && starts_with_at(chars, i, scs) {
+            hl[i..].fill(Highlight::Comment);
+            break;
+        }
+
+        if in_string.is_none() && !mcs.is_empty() && !mce.is_empty() {
+            if in_comment {
+                hl[i] = Highlight::MlComment;
+                if starts_with_at(chars, i, mce) {
+                    let len = mce.chars().count();
+                    hl[i..(i + len)].fill(Highlight::MlComment);
+                    i += len;
+                    in_comment = false;
+                    prev_sep = true;
+                } else {
+                    i += 1;
+                }
+                continue;
+            } else if starts_with_at(chars, i, mcs) {
+                let len = mcs.chars().count();
+                hl[i..(i + len)].fill(Highlight::MlComment);
+                i += len;
+                in_comment = true;
+                continue;
+            }
+        }
+
+        if let Some(quote) = in_string {
+            hl[i] = Highlight::Str;
+            if c == '\\' && i + 1 < chars.len() {
+                hl[i + 1] = Highlight::Str;
+                i += 2;
+                continue;
+            }
+            if c == quote {
+                in_string = None;
+            }
+            i += 1;
+            prev_sep = true;
+            continue;
+        } else if syntax.string_quotes.contains(&c) {
+            in_string = Some(c);
+            hl[i] = Highlight::Str;
+            i += 1;
+            continue;
+        }
+
+        if syntax.highlight_numbers
+            && ((c.is_ascii_digit() && (prev_sep || prev_hl == Highlight::Number))
+                || (c == '.' && prev_hl == Highlight::Number))
+        {
+            hl[i] = Highlight::Number;
+            i += 1;
+            prev_sep = false;
+            continue;
+        }
+
+        if prev_sep {
+            let mut matched = false;
+
+            for keyword in &syntax.keywords {
+                let kw2 = keyword.ends_with('|');
+                let keyword = keyword.trim_end_matches('|');
+                let len = keyword.chars().count();
+
+                let boundary = match chars.get(i + len) {
+                    Some(&c) => is_separator(c),
+                    None => true,
+                };
+
+                if boundary && starts_with_at(chars, i, keyword) {
+                    let h = if kw2 { Highlight::Keyword2 } else { Highlight::Keyword1 };
+                    for slot in &mut hl[i..(i + len)] {
+                        *slot = h;
+                    }
+                    i += len;
+                    matched = true;
+                    break;
+                }
+            }
+
+            if matched {
+                prev_sep = false;
+                continue;
+            }
+        }
+
+        prev_sep = is_separator(c);
+        i += 1;
+    }
+
+    (hl, in_comment)
+}
+
+pub(crate) fn markdown_fence(chars: &[char]) -> bool {
+    let start = chars.iter().position(|c| !c.is_whitespace()).unwrap_or(chars.len());
+    starts_with_at(chars, start, "```") || starts_with_at(chars, start, "~~~")
+}
+
+pub(crate) fn highlight_markdown_inline(chars: &[char], hl: &mut [Highlight], from: usize) {
+    let mut i = from;
+
+    while i < chars.len() {
+        match chars[i] {
+            '`' => {
+                let end = chars[(i + 1)..].iter().position(|&c| c == '`').map(|p| i + 1 + p);
+                if let Some(end) = end {
+                    hl[i..(end + 1)].fill(Highlight::Code);
+                    i = end + 1;
+                    continue;
+                }
+            }
+            '[' => {
+                let close = chars[i..].iter().position(|&c| c == ']').map(|p| i + p);
+                if let Some(close) = close {
+                    if chars.get(close + 1) == Some(&'(') {
+                        let end = chars[close..].iter().position(|&c| c == ')').map(|p| close + p);
+                        if let Some(end) = end {
+                            hl[i..(close + 1)].fill(Highlight::Link);
+                            hl[(close + 1)..(end + 1)].fill(Highlight::Str);
+                            i = end + 1;
+                            continue;
+                        }
+                    }
+                }
+            }
+            '<' if starts_with_at(chars, i + 1, "http") => {
+                let end = chars[i..].iter().position(|&c| c == '>').map(|p| i + p);
+                if let Some(end) = end {
+                    hl[i..(end + 1)].fill(Highlight::Link);
+                    i = end + 1;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+}
+
+pub(crate) fn highlight_markdown_row(chars: &[char]) -> Vec<Highlight> {
+    let mut hl = vec![Highlight::Normal; chars.len()];
+
+    let indent = chars.iter().position(|c| !c.is_whitespace()).unwrap_or(chars.len());
+    let rest = &chars[indent..];
+
+    let hashes = rest.iter().take_while(|&&c| c == '#').count();
+    if indent == 0 && hashes > 0 && hashes <= 6 && rest.get(hashes).is_none_or(|c| *c == ' ') {
+        hl.fill(Highlight::Heading);
+        return hl;
+    }
+
+    if rest.first() == Some(&'>') {
+        hl.fill(Highlight::Comment);
+        return hl;
+    }
+
+    let digits = rest.iter().take_while(|c| c.is_ascii_digit()).count();
+    let marker = match rest.first() {
+        Some(&'-') | Some(&'*') | Some(&'+') => 1,
+        _ if digits > 0 && (rest.get(digits) == Some(&'.') || rest.get(digits) == Some(&')')) => {
+            digits + 1
+        }
+        _ => 0,
+    };
+
+    let mut from = indent;
+    if marker > 0 && rest.get(marker) == Some(&' ') {
+        hl[indent..(indent + marker)].fill(Highlight::Bullet);
+        from = indent + marker;
+    }
+
+    highlight_markdown_inline(chars, &mut hl, from);
+
+    hl
+}
+/// Highlighting state carried from the end of one row to the start of the next.
+#[derive(Clone, Copy, Default, PartialEq)]
+pub(crate) struct HighlightState {
+    pub(crate) open_comment: bool,
+    pub(crate) region: Option<Region>,
+}
+
+/// A region of rows highlighted by another syntax, such as a fenced code block in Markdown.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) struct Region {
+    /// Index into the outer syntax's `embedded` rules, or `None` for a Markdown fence.
+    pub(crate) rule: Option<usize>,
+    pub(crate) syntax: Option<usize>,
+}
+
+pub(crate) fn find_syntax(syntaxes: &[EditorSyntax], name: &str) -> Option<usize> {
+    let extension = format!(".{}", name);
+
+    syntaxes.iter().position(|s| {
+        !name.is_empty()
+            && (s.filetype.eq_ignore_ascii_case(name) || s.filematch.contains(&extension))
+    })
+}
+
+pub(crate) fn highlight_region(
+    chars: &[char],
+    syntax: &EditorSyntax,
+    open_comment: bool,
+) -> (Vec<Highlight>, bool) {
+    if syntax.markdown {
+        (highlight_markdown_row(chars), false)
+    } else {
+        highlight_row(chars, syntax, open_comment)
+    }
+}
+
+pub(crate) fn highlight_line(
+    chars: &[char],
+    syntaxes: &[EditorSyntax],
+    outer: usize,
+    state: HighlightState,
+) -> (Vec<Highlight>, HighlightState) {
+    let syntax = &syntaxes[outer];
+    let mut hl = Vec::with_capacity(chars.len());
+    let mut state = state;
+
+    if syntax.markdown && state.region.is_none() && markdown_fence(chars) {
+        let info = chars.iter().collect::<String>();
+        let language = info.trim().trim_start_matches(['`', '~']).split_whitespace().next();
+        let region = Region { rule: None, syntax: find_syntax(syntaxes, language.unwrap_or("")) };
+        state = HighlightState { open_comment: false, region: Some(region) };
+        return (vec![Highlight::Code; chars.len()], state);
+    }
+
+    while hl.len() < chars.len() {
+        let i = hl.len();
+
+        match state.region {
+            Some(Region { rule: None, .. }) if markdown_fence(chars) => {
+                hl = vec![Highlight::Code; chars.len()];
+                state = HighlightState::default();
+            }
+            Some(region) => {
+                let end = region.rule.and_then(|r| {
+                    let pattern = &syntax.embedded[r].end;
+                    (i..chars.len()).find(|&j| starts_with_at(chars, j, pattern))
+                });
+                let stop = end.unwrap_or(chars.len());
+
+                match region.syntax {
+                    Some(s) => {
+                        let (region_hl, open_comment) =
+                            highlight_region(&chars[i..stop], &syntaxes[s], state.open_comment);
+                        hl.extend(region_hl);
+                        state.open_comment = open_comment;
+                    }
+                    None if region.rule.is_none() => hl.extend(vec![Highlight::Code; stop - i]),
+                    None => hl.extend(vec![Highlight::Normal; stop - i]),
+                }
+
+                if end.is_some() {
+                    state = HighlightState::default();
+                }
+            }
+            None => {
+                let start = syntax.embedded.iter().enumerate().filter_map(|(r, rule)| {
+                    (i..chars.len())
+                        .find(|&j| starts_with_at(chars, j, &rule.start))
+                        .map(|j| (j + rule.start.chars().count(), r))
+                });
+                let start = start.min();
+                let stop = start.map_or(chars.len(), |(j, _)| j);
+
+                let (outer_hl, open_comment) =
+                    highlight_region(&chars[i..stop], syntax, state.open_comment);
+                hl.extend(outer_hl);
+                state.open_comment = open_comment;
+
+                if let Some((_, r)) = start {
+                    let filetype = &syntax.embedded[r].filetype;
+                    let region = Region { rule: Some(r), syntax: find_syntax(syntaxes, filetype) };
+                    state = HighlightState { open_comment: false, region: Some(region) };
+                }
+            }
+        }
+    }
+
+    (hl, state)
+}
+
+/// The highlighting of consecutive rows, starting from the first row's index.
+pub(crate) type HighlightBatch = (usize, Vec<(Vec<Highlight>, HighlightState)>);
+
+/// Highlighting of the rows from some row to the end of the buffer, running on a worker
+/// thread that sends back batches of `KILO_HIGHLIGHT_BATCH` rows.
+pub(crate) struct HighlightJob {
+    receiver: Receiver<HighlightBatch>,
+    /// The first row the worker hasn't sent back yet.
+    next: usize,
+    started: Instant,
+}
+
+impl HighlightJob {
+    /// Highlights `lines`, the rows from `start` on, continuing from `state`.
+    fn spawn(
+        lines: Vec<String>,
+        tabstop: usize,
+        syntaxes: Vec<EditorSyntax>,
+        outer: usize,
+        start: usize,
+        state: HighlightState,
+    ) -> HighlightJob {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut state = state;
+            for (i, batch) in lines.chunks(KILO_HIGHLIGHT_BATCH).enumerate() {
+                let results = batch
+                    .iter()
+                    .map(|line| {
+                        let chars = render_text(line, tabstop).chars().collect::<Vec<char>>();
+                        let (hl, next) = highlight_line(&chars, &syntaxes, outer, state);
+                        state = next;
+                        (hl, next)
+                    })
+                    .collect();
+
+                // The editor drops the receiver once the text has changed under the job.
+                if sender.send((start + i * KILO_HIGHLIGHT_BATCH, results)).is_err() {
+                    return;
+                }
+            }
+        });
+
+        HighlightJob { receiver, next: start, started: Instant::now() }
+    }
+}
+
+/// The syntaxes known to the editor, the open file's, and its background highlighting.
+pub(crate) struct Highlighting {
+    pub(crate) syntaxes: Vec<EditorSyntax>,
+    pub(crate) syntax: Option<usize>,
+    pub(crate) job: Option<HighlightJob>,
+    /// The row to restart background highlighting from, once edits have settled.
+    pub(crate) restart: Option<usize>,
+}
+
+impl Kilo {
+    /// Highlights the rows from `at` to the end: straight away for short stretches, or on a
+    /// worker thread, with the visible rows done first, so that opening a large file
+    /// doesn't keep the editor waiting.
+    pub(crate) fn editor_highlight_rows(&mut self, at: usize) {
+        self.highlight.job = None;
+        self.highlight.restart = None;
+
+        let outer = match self.highlight.syntax {
+            Some(outer) if self.buffer.rows.len().saturating_sub(at) > KILO_HIGHLIGHT_BATCH => {
+                outer
+            }
+            // Without a syntax everything is drawn plainly, and nothing needs rendering yet.
+            None => {
+                for row in &mut self.buffer.rows[at..] {
+                    row.hl.clear();
+                    row.hl_state = HighlightState::default();
+                }
+                return;
+            }
+            Some(_) => {
+                for at in at..self.buffer.rows.len() {
+                    self.editor_highlight_row(at);
+                }
+                return;
+            }
+        };
+
+        let state = match at {
+            0 => HighlightState::default(),
+            _ => self.buffer.rows[at - 1].hl_state,
+        };
+        let lines = self.buffer.rows[at..].iter().map(|row| row.chars.clone()).collect();
+        let (tabstop, syntaxes) = (self.buffer.tabstop, self.highlight.syntaxes.clone());
+        let job = HighlightJob::spawn(lines, tabstop, syntaxes, outer, at, state);
+        self.highlight.job = Some(job);
+        self.editor_highlight_visible();
+    }
+
+    /// The first row whose highlighting is a guess, while rows are being highlighted in the
+    /// background.
+    fn editor_highlight_frontier(&self) -> Option<usize> {
+        match (self.highlight.job.as_ref().map(|job| job.next), self.highlight.restart) {
+            (Some(next), Some(restart)) => Some(cmp::min(next, restart)),
+            (next, restart) => next.or(restart),
+        }
+    }
+
+    /// Highlights the visible rows the background highlighting hasn't reached yet, carrying
+    /// on from whatever state the row above them is in, until the real results arrive.
+    pub(crate) fn editor_highlight_visible(&mut self) {
+        let frontier = match self.editor_highlight_frontier() {
+            Some(frontier) => frontier,
+            None => return,
+        };
+
+        let end = cmp::min(self.rowoff + self.screenrows, self.buffer.rows.len());
+        for at in cmp::max(self.rowoff, frontier)..end {
+            self.editor_highlight_row(at);
+        }
+    }
+
+    /// Applies the batches the background highlighting has finished, and restarts it if
+    /// edits made it out of date.
+    pub(crate) fn editor_poll_highlight(&mut self) {
+        if let (None, Some(restart)) = (&self.highlight.job, self.highlight.restart) {
+            self.editor_highlight_rows(restart.min(self.buffer.rows.len()));
+        }
+
+        let job = match self.highlight.job {
+            Some(ref mut job) => job,
+            None => return,
+        };
+        loop {
+            match job.receiver.try_recv() {
+                Ok((start, results)) => {
+                    job.next = start + results.len();
+                    let rows = self.buffer.rows.iter_mut().skip(start);
+                    for (row, (hl, hl_state)) in rows.zip(results) {
+                        row.hl = hl;
+                        row.hl_state = hl_state;
+                    }
+                }
+                Err(mpsc::TryRecvError::Empty) => return,
+                Err(mpsc::TryRecvError::Disconnected) => break,
+            }
+        }
+
+        let (next, elapsed) = (job.next, job.started.elapsed());
+        self.highlight.job = None;
+        self.editor_log(format_args!("highlight: up to row {} in {:?}", next, elapsed));
+    }
+
+    /// Highlights row `at` alone, continuing from the state the row above ended in.
+    fn editor_highlight_row(&mut self, at: usize) -> bool {
+        self.editor_prepare_rows(at, at + 1);
+        let state = match at {
+            0 => HighlightState::default(),
+            _ => self.buffer.rows[at - 1].hl_state,
+        };
+        let row = &mut self.buffer.rows[at];
+        let render = row.render.chars().collect::<Vec<char>>();
+
+        let (hl, hl_state) = match self.highlight.syntax {
+            Some(i) => highlight_line(&render, &self.highlight.syntaxes, i, state),
+            None => (vec![Highlight::Normal; render.len()], HighlightState::default()),
+        };
+
+        let changed = row.hl_state != hl_state;
+        row.hl = hl;
+        row.hl_state = hl_state;
+        changed
+    }
+
+    /// Re-highlights row `at` after it changed, and the rows after it for as long as the
+    /// state carried between rows changes. Long cascades, such as from opening a comment
+    /// near the top of a large file, are finished in the background.
+    pub(crate) fn editor_update_syntax(&mut self, at: usize) {
+        // Whatever the background highlighting was working on may have changed.
+        if let Some(frontier) = self.editor_highlight_frontier() {
+            self.highlight.job = None;
+            self.highlight.restart = Some(cmp::min(frontier, at));
+        }
+
+        let first = at;
+        let mut at = at;
+        while at < self.buffer.rows.len() && self.editor_highlight_row(at) {
+            at += 1;
+            if at - first >= KILO_HIGHLIGHT_BATCH {
+                let restart = self.highlight.restart.map_or(at, |r| cmp::min(r, at));
+                self.highlight.restart = Some(restart);
+                break;
+            }
+        }
+    }
+}
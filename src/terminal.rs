@@ -0,0 +1,306 @@
+//! Raw mode, window size queries and buffered output to the terminal.
+
+use super::*;
+
+/// Collects terminal output and hands it to the terminal in a single `write` per flush.
+///
+/// Unlike `io::Stdout`, which flushes at every newline, nothing is written before `flush`,
+/// so a frame never reaches the terminal half drawn.
+pub struct TerminalWriter {
+    pub(crate) buffer: Vec<u8>,
+}
+
+impl TerminalWriter {
+    pub fn new() -> Self {
+        TerminalWriter { buffer: Vec::new() }
+    }
+}
+
+impl Default for TerminalWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Write for TerminalWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut written = 0;
+
+        // Only a short write (e.g. interrupted by a signal) takes more than one call.
+        while written < self.buffer.len() {
+            let remaining = &self.buffer[written..];
+            let n = unsafe {
+                libc::write(
+                    libc::STDOUT_FILENO,
+                    remaining.as_ptr() as *const libc::c_void,
+                    remaining.len(),
+                )
+            };
+
+            if n < 0 {
+                let err = io::Error::last_os_error();
+
+                if err.kind() == ErrorKind::Interrupted {
+                    continue;
+                }
+
+                self.buffer.drain(..written);
+                return Err(err);
+            } else if n == 0 {
+                self.buffer.drain(..written);
+                return Err(io::Error::new(ErrorKind::WriteZero, "terminal accepted no output"));
+            }
+
+            written += n as usize;
+        }
+
+        self.buffer.clear();
+
+        Ok(())
+    }
+}
+
+pub(crate) fn write_terminal(bytes: &[u8]) -> io::Result<()> {
+    let mut out = TerminalWriter::new();
+    out.write_all(bytes)?;
+    out.flush()
+}
+
+pub fn clear_screen() -> io::Result<()> {
+    write_terminal(b"\x1b[2J\x1b[H")
+}
+
+/// Whether the terminal emulator is one known to support OSC 8 hyperlinks. There is no way
+/// to query for them, and a terminal that doesn't understand them may print the escapes.
+pub(crate) fn terminal_supports_hyperlinks() -> bool {
+    let var = |name: &str| env::var(name).unwrap_or_default();
+    let term = var("TERM");
+
+    if term.starts_with("screen") || term.starts_with("tmux") || env::var_os("TMUX").is_some() {
+        return false;
+    }
+
+    ["iTerm.app", "WezTerm", "vscode", "ghostty"].contains(&var("TERM_PROGRAM").as_str())
+        || var("VTE_VERSION").parse::<u32>().is_ok_and(|v| v >= 5000)
+        || ["xterm-kitty", "foot", "alacritty", "wezterm", "xterm-ghostty"]
+            .iter()
+            .any(|t| term.starts_with(t))
+        || env::var_os("KITTY_WINDOW_ID").is_some()
+        || env::var_os("WT_SESSION").is_some()
+}
+
+/// The OSC 8 escape starting a hyperlink to `uri`, or ending one if `uri` is empty.
+pub(crate) fn hyperlink(uri: &str) -> String {
+    format!("\x1b]8;;{}\x1b\\", uri)
+}
+
+pub(crate) fn ioctl_window_size(fd: RawFd) -> Option<(usize, usize)> {
+    // SAFETY: winsize is plain integers, so all zeroes is a valid value.
+    let mut ws: winsize = unsafe { mem::zeroed() };
+
+    if unsafe { ioctl(fd, TIOCGWINSZ, &mut ws) } == -1 || ws.ws_col == 0 || ws.ws_row == 0 {
+        None
+    } else {
+        Some((ws.ws_row as usize, ws.ws_col as usize))
+    }
+}
+
+/// The terminal the editor runs in and the input read from it.
+pub(crate) struct TerminalState {
+    pub(crate) stdin_fd: RawFd,
+    /// Bytes read from the terminal but not yet parsed into keys.
+    pub(crate) input: RefCell<VecDeque<u8>>,
+    /// Terminal settings to restore on exit, or `None` for a headless editor.
+    pub(crate) orig_termios: Option<Termios>,
+    /// Whether the terminal looks like it supports OSC 8 hyperlinks.
+    pub(crate) hyperlinks: bool,
+    /// Whether the terminal window has focus, as last reported by a focus event.
+    pub(crate) focused: Cell<bool>,
+    /// Set when the window regains focus, until the file on disk has been checked.
+    pub(crate) refocused: Cell<bool>,
+    /// The text of the last paste, until it is inserted.
+    pub(crate) pasted: RefCell<String>,
+}
+
+impl Kilo {
+    /// Also turns focus reporting and bracketed paste off, so that programs run in the
+    /// meantime don't receive focus events or paste markers.
+    pub(crate) fn disable_raw_mode(&self) -> io::Result<()> {
+        match self.terminal.orig_termios {
+            Some(ref termios) => {
+                write_terminal(b"\x1b[?1004l\x1b[?2004l")?;
+                tcsetattr(self.terminal.stdin_fd, TCSAFLUSH, termios)
+            }
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn enable_raw_mode(&self) -> io::Result<()> {
+        let mut raw = match self.terminal.orig_termios {
+            Some(termios) => termios,
+            None => return Ok(()),
+        };
+
+        raw.c_iflag &= !(BRKINT | ICRNL | INPCK | ISTRIP | IXON);
+        raw.c_oflag &= !(OPOST);
+        raw.c_cflag |= CS8;
+        raw.c_lflag &= !(ECHO | ICANON | IEXTEN | ISIG);
+        raw.c_cc[VMIN] = 0;
+        raw.c_cc[VTIME] = 1;
+
+        tcsetattr(self.terminal.stdin_fd, TCSAFLUSH, &raw)?;
+        // Report focus changes as `ESC [ I` and `ESC [ O`, and mark pastes with `ESC [ 200 ~`
+        // and `ESC [ 201 ~`.
+        write_terminal(b"\x1b[?1004h\x1b[?2004h")
+    }
+
+    /// Rings the bell configured with `bell`, if any. Like the status message that comes with
+    /// it, a bell that can't be rung isn't worth failing over.
+    pub(crate) fn editor_ring_bell(&self) {
+        if self.terminal.orig_termios.is_none() {
+            return;
+        }
+
+        match self.config.bell {
+            Bell::Off => {}
+            Bell::Audible => {
+                let _ = write_terminal(b"\x07");
+            }
+            Bell::Visual => {
+                // Flash the screen by briefly switching it to reverse video.
+                let _ = write_terminal(b"\x1b[?5h");
+                thread::sleep(VISUAL_BELL_DURATION);
+                let _ = write_terminal(b"\x1b[?5l");
+            }
+        }
+    }
+
+    /// Reads the next input byte, waiting at most the `VTIME` timeout for more to arrive.
+    /// Everything available is read at once so bursts of input can be detected.
+    pub(crate) fn editor_read_byte(&self) -> io::Result<Option<u8>> {
+        if let Some(byte) = self.terminal.input.borrow_mut().pop_front() {
+            return Ok(Some(byte));
+        }
+        if self.terminal.orig_termios.is_none() {
+            return Ok(None);
+        }
+
+        let mut buffer = [0u8; 4096];
+        let n = loop {
+            let ptr = buffer.as_mut_ptr() as *mut libc::c_void;
+            match unsafe { libc::read(self.terminal.stdin_fd, ptr, buffer.len()) } {
+                0 => return Ok(None),
+                n if n > 0 => break n as usize,
+                _ => {
+                    let e = io::Error::last_os_error();
+                    if e.kind() != ErrorKind::Interrupted {
+                        return Err(e);
+                    }
+                }
+            }
+        };
+
+        let mut input = self.terminal.input.borrow_mut();
+        input.extend(&buffer[..n]);
+        Ok(input.pop_front())
+    }
+
+    /// Whether more input is ready to be read without waiting.
+    pub(crate) fn editor_input_pending(&self) -> bool {
+        self.editor_input_ready(Duration::from_millis(0))
+    }
+
+    /// Whether input is ready to be read or arrives within `timeout`.
+    pub(crate) fn editor_input_ready(&self, timeout: Duration) -> bool {
+        if !self.terminal.input.borrow().is_empty() {
+            return true;
+        }
+        if self.terminal.orig_termios.is_none() {
+            return false;
+        }
+
+        let mut fds = libc::pollfd {
+            fd: self.terminal.stdin_fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        unsafe { libc::poll(&mut fds, 1, timeout.as_millis() as libc::c_int) > 0 }
+    }
+
+    pub(crate) fn get_cursor_position(&self) -> io::Result<(usize, usize)> {
+        write_terminal(b"\x1b[6n")?;
+
+        // The report (`ESC [ rows ; cols R`) may arrive over several reads.
+        let mut response = Vec::new();
+        loop {
+            match self.editor_read_byte()? {
+                Some(b'R') => break,
+                Some(b) if response.len() < 32 => response.push(b),
+                _ => {
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidData,
+                        "no cursor position report from the terminal",
+                    ))
+                }
+            }
+        }
+
+        let report = String::from_utf8_lossy(&response);
+        let report = match report.rfind("\x1b[") {
+            Some(start) => &report[start + 2..],
+            None => {
+                return Err(io::Error::new(ErrorKind::InvalidData, "bad cursor position report"))
+            }
+        };
+
+        let mut parts = report.split(';').map(|i| i.parse().unwrap_or(0));
+        match (parts.next().unwrap_or(0), parts.next().unwrap_or(0)) {
+            (0, _) | (_, 0) => {
+                Err(io::Error::new(ErrorKind::InvalidData, "bad cursor position report"))
+            }
+            size => Ok(size),
+        }
+    }
+
+    /// The terminal's size as reported by the kernel, asking the tty on stdout first.
+    pub(crate) fn editor_tty_size(&self) -> Option<(usize, usize)> {
+        match self.terminal.orig_termios {
+            Some(_) => ioctl_window_size(libc::STDOUT_FILENO)
+                .or_else(|| ioctl_window_size(self.terminal.stdin_fd)),
+            None => None,
+        }
+    }
+
+    pub(crate) fn get_window_size(&self) -> io::Result<(usize, usize)> {
+        match self.editor_tty_size() {
+            Some(size) => Ok(size),
+            None => {
+                write_terminal(b"\x1b[999C\x1b[999B")?;
+
+                self.get_cursor_position()
+            }
+        }
+    }
+
+    pub(crate) fn editor_check_resize(&mut self) -> io::Result<()> {
+        let (rows, cols) = match self.editor_tty_size() {
+            Some(size) => size,
+            None => return Ok(()),
+        };
+
+        if (rows.saturating_sub(2), cols) != (self.screenrows, self.screencols) {
+            self.screenrows = rows.saturating_sub(2);
+            self.screencols = cols;
+            self.editor_log(format_args!("resize: {}x{}", cols, rows));
+            self.editor_run_hook("Resize")?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,836 @@
+//! An optional vi-style front end, selected with `vi = true` or `--vi`. Keys typed in normal
+//! and visual mode become calls to the same editing primitives the default bindings use, and
+//! keys vi doesn't use there, such as Ctrl-S or the arrows, still reach the default bindings.
+
+use super::*;
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum ViMode {
+    Normal,
+    Insert,
+    Visual,
+    VisualLine,
+}
+
+impl ViMode {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            ViMode::Normal => "NORMAL",
+            ViMode::Insert => "INSERT",
+            ViMode::Visual => "VISUAL",
+            ViMode::VisualLine => "V-LINE",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ViOperator {
+    Delete,
+    Change,
+    Yank,
+}
+
+/// A command key with the register and count typed before it.
+struct ViCommand {
+    key: u8,
+    register: Option<char>,
+    count: Option<usize>,
+}
+
+/// How much of the text between the cursor and where a motion took it an operator covers.
+#[derive(Clone, Copy, PartialEq)]
+enum ViExtent {
+    /// Up to the motion's end, leaving it out.
+    Exclusive,
+    /// Up to and including the character at the motion's end.
+    Inclusive,
+    /// The whole rows from the cursor's to the motion's end.
+    Linewise,
+}
+
+/// What `w`, `b` and `e` treat as one word: a run of word characters, a run of other
+/// non-blank characters, or for `W`, `B` and `E` any run of non-blank characters.
+fn vi_class(c: char, big: bool) -> u8 {
+    if c.is_whitespace() {
+        0
+    } else if big || is_word_char(c) {
+        1
+    } else {
+        2
+    }
+}
+
+impl Kilo {
+    /// Handles `key` in vi mode, returning whether it was used up. In insert mode only
+    /// Escape is, which goes back to normal mode.
+    pub(crate) fn editor_vi_key(&mut self, key: EditorKey) -> io::Result<bool> {
+        let mode = match self.vi_mode {
+            Some(mode) => mode,
+            None => return Ok(false),
+        };
+
+        if mode == ViMode::Insert {
+            if key != Char(b'\x1b') {
+                return Ok(false);
+            }
+            self.vi_mode = Some(ViMode::Normal);
+            self.cx = self.cx.saturating_sub(1);
+            return Ok(true);
+        }
+
+        let c = match key {
+            Char(b'\r') => b'+',
+            Char(c) if c == 127 || c == ctrl_key('h') => b'h',
+            Char(c) if (b' '..=b'~').contains(&c) || c == b'\x1b' || c == b'\t' => c,
+            // Outside insert mode typed text isn't inserted, and no command is bound to it.
+            Text(_) => return Ok(true),
+            _ => return Ok(false),
+        };
+
+        self.editor_vi_clamp_cursor();
+        let mut pending = String::new();
+        let ViCommand { key: c, register, count } = match self.editor_vi_prefix(c, &mut pending)? {
+            Some(command) => command,
+            None => return Ok(true),
+        };
+
+        if mode == ViMode::Normal {
+            self.editor_vi_normal(c, register, count, &mut pending)?;
+        } else {
+            self.editor_vi_visual(c, register, count, &mut pending)?;
+        }
+        // Clear the keys typed so far unless the command left a message of its own.
+        if !self.message.text.is_empty() && pending.starts_with(&self.message.text) {
+            self.editor_set_status_message("");
+        }
+
+        if self.vi_mode != Some(ViMode::Insert) {
+            self.editor_vi_clamp_cursor();
+        }
+
+        Ok(true)
+    }
+
+    /// Keeps the cursor on a character, as vi does, rather than after the last one or on
+    /// the empty line after the last row.
+    fn editor_vi_clamp_cursor(&mut self) {
+        if self.cy >= self.buffer.rows.len() {
+            self.cy = self.buffer.rows.len().saturating_sub(1);
+        }
+
+        let len = self.buffer.rows.get(self.cy).map_or(0, |row| row.chars.chars().count());
+        if len > 0 && self.cx >= len {
+            self.cx = len - 1;
+        }
+    }
+
+    /// The next key of a command, shown after the keys typed so far; Escape or a key that
+    /// isn't a character cancels the command.
+    fn editor_vi_next(&mut self, pending: &mut String) -> io::Result<Option<u8>> {
+        self.editor_set_status_message(pending);
+        self.editor_refresh_screen()?;
+
+        match self.editor_wait_key()? {
+            Char(c) if c != b'\x1b' && (b' '..=b'~').contains(&c) => {
+                pending.push(c as char);
+                Ok(Some(c))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Reads the register (`"a`) and count (`3`) that may come before command key `c`.
+    fn editor_vi_prefix(
+        &mut self,
+        mut c: u8,
+        pending: &mut String,
+    ) -> io::Result<Option<ViCommand>> {
+        let mut register = None;
+        let mut count: Option<usize> = None;
+        pending.push(c as char);
+
+        loop {
+            match c {
+                b'"' if register.is_none() && count.is_none() => {
+                    match self.editor_vi_next(pending)? {
+                        Some(name) if name.is_ascii_alphabetic() => register = Some(name as char),
+                        _ => return Ok(None),
+                    }
+                }
+                b'1'..=b'9' | b'0' if c != b'0' || count.is_some() => {
+                    let n = count.unwrap_or(0);
+                    count = Some(n.saturating_mul(10).saturating_add((c - b'0') as usize));
+                }
+                _ => return Ok(Some(ViCommand { key: c, register, count })),
+            }
+            c = match self.editor_vi_next(pending)? {
+                Some(c) => c,
+                None => return Ok(None),
+            };
+        }
+    }
+
+    fn editor_vi_normal(
+        &mut self,
+        c: u8,
+        register: Option<char>,
+        count: Option<usize>,
+        pending: &mut String,
+    ) -> io::Result<()> {
+        let n = count.unwrap_or(1);
+        let edits = b"dcxXsSDCpPJr~iaIAoO".contains(&c);
+        if edits && self.editor_refuse_if_read_only() {
+            return Ok(());
+        }
+
+        let operator = match c {
+            b'd' => Some(ViOperator::Delete),
+            b'c' => Some(ViOperator::Change),
+            b'y' => Some(ViOperator::Yank),
+            _ => None,
+        };
+        if let Some(operator) = operator {
+            return self.editor_vi_operator(operator, c, register, count, pending);
+        }
+
+        // Shorthands for an operator and a motion.
+        let shorthand = match c {
+            b'x' => Some((ViOperator::Delete, b'l')),
+            b'X' => Some((ViOperator::Delete, b'h')),
+            b's' => Some((ViOperator::Change, b'l')),
+            b'D' => Some((ViOperator::Delete, b'$')),
+            b'C' => Some((ViOperator::Change, b'$')),
+            b'S' => Some((ViOperator::Change, b'_')),
+            b'Y' => Some((ViOperator::Yank, b'_')),
+            _ => None,
+        };
+        if let Some((operator, motion)) = shorthand {
+            let origin = self.editor_cursor_position();
+            if let Some(extent) = self.editor_vi_motion(motion, n, count.is_some(), pending)? {
+                self.editor_vi_apply(operator, origin, extent, register);
+            }
+            return Ok(());
+        }
+
+        match c {
+            b'i' => self.vi_mode = Some(ViMode::Insert),
+            b'a' => {
+                let len = self.buffer.rows.get(self.cy).map_or(0, |row| row.chars.chars().count());
+                self.cx = cmp::min(self.cx + 1, len);
+                self.vi_mode = Some(ViMode::Insert);
+            }
+            b'I' | b'A' => {
+                self.editor_vi_motion(if c == b'I' { b'^' } else { b'$' }, 1, false, pending)?;
+                if c == b'A' {
+                    self.editor_end();
+                }
+                self.vi_mode = Some(ViMode::Insert);
+            }
+            b'o' => {
+                self.editor_end();
+                self.editor_insert_newline();
+                self.vi_mode = Some(ViMode::Insert);
+            }
+            b'O' => {
+                self.cx = 0;
+                self.editor_insert_newline();
+                self.cy -= 1;
+                self.vi_mode = Some(ViMode::Insert);
+            }
+            b'p' | b'P' => {
+                for _ in 0..n {
+                    self.editor_vi_put(register, c == b'p');
+                }
+            }
+            b'J' => {
+                for _ in 0..cmp::max(n, 2) - 1 {
+                    self.editor_vi_join();
+                }
+            }
+            b'r' => {
+                if let Some(with) = self.editor_vi_next(pending)? {
+                    self.editor_vi_replace(with as char, n);
+                }
+            }
+            b'~' => self.editor_vi_toggle_case(n),
+            b'v' | b'V' => {
+                self.anchor = Some(self.editor_cursor_position());
+                self.block = false;
+                self.vi_mode = Some(if c == b'v' { ViMode::Visual } else { ViMode::VisualLine });
+            }
+            b'u' => self.editor_set_status_message("Undo isn't supported"),
+            b':' => self.editor_command_line()?,
+            b'/' => self.editor_find()?,
+            _ => {
+                if self.editor_vi_motion(c, n, count.is_some(), pending)?.is_some() {
+                    return Ok(());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn editor_vi_visual(
+        &mut self,
+        c: u8,
+        register: Option<char>,
+        count: Option<usize>,
+        pending: &mut String,
+    ) -> io::Result<()> {
+        let operator = match c {
+            b'd' | b'x' => Some(ViOperator::Delete),
+            b'c' | b's' => Some(ViOperator::Change),
+            b'y' => Some(ViOperator::Yank),
+            _ => None,
+        };
+
+        match c {
+            _ if operator.is_some() => {
+                let operator = operator.unwrap_or(ViOperator::Yank);
+                if operator != ViOperator::Yank && self.editor_refuse_if_read_only() {
+                    return Ok(());
+                }
+                let anchor = self.anchor.unwrap_or(self.editor_cursor_position());
+                let extent = match self.vi_mode {
+                    Some(ViMode::VisualLine) => ViExtent::Linewise,
+                    _ => ViExtent::Inclusive,
+                };
+                self.anchor = None;
+                self.vi_mode = Some(ViMode::Normal);
+                self.editor_vi_apply(operator, anchor, extent, register);
+            }
+            b'\x1b' => {
+                self.anchor = None;
+                self.vi_mode = Some(ViMode::Normal);
+            }
+            b'v' | b'V' => {
+                let mode = if c == b'v' { ViMode::Visual } else { ViMode::VisualLine };
+                if self.vi_mode == Some(mode) {
+                    self.anchor = None;
+                    self.vi_mode = Some(ViMode::Normal);
+                } else {
+                    self.vi_mode = Some(mode);
+                }
+            }
+            b'o' => {
+                let pos = self.editor_cursor_position();
+                if let Some(anchor) = self.anchor.replace(pos) {
+                    self.editor_move_to(anchor);
+                }
+            }
+            b':' => self.editor_command_line()?,
+            _ => {
+                self.editor_vi_motion(c, count.unwrap_or(1), count.is_some(), pending)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The text a visual mode selection covers, from its first character up to (not
+    /// including) the end position.
+    pub(crate) fn editor_vi_selection(&self) -> Option<(Position, Position)> {
+        let mode = self.vi_mode.filter(|&m| m == ViMode::Visual || m == ViMode::VisualLine)?;
+        let (first, last) = self.editor_selection()?;
+        let len = |cy: usize| self.buffer.rows.get(cy).map_or(0, |row| row.chars.chars().count());
+
+        Some(match mode {
+            ViMode::VisualLine => {
+                (Position { cx: 0, ..first }, Position { cx: len(last.cy), ..last })
+            }
+            _ => (first, Position { cx: cmp::min(last.cx + 1, len(last.cy)), ..last }),
+        })
+    }
+
+    /// Reads the motion after operator key `key` and applies the operator to the text it
+    /// moves over; `dd`, `cc` and `yy` cover whole lines.
+    fn editor_vi_operator(
+        &mut self,
+        operator: ViOperator,
+        key: u8,
+        register: Option<char>,
+        count: Option<usize>,
+        pending: &mut String,
+    ) -> io::Result<()> {
+        let c = match self.editor_vi_next(pending)? {
+            Some(c) => c,
+            None => return Ok(()),
+        };
+        let (motion, register, count) = match self.editor_vi_prefix(c, &mut String::new())? {
+            Some(inner) => {
+                let count = match (count, inner.count) {
+                    (None, None) => None,
+                    (a, b) => Some(a.unwrap_or(1).saturating_mul(b.unwrap_or(1))),
+                };
+                (inner.key, inner.register.or(register), count)
+            }
+            None => return Ok(()),
+        };
+        let motion = if motion == key { b'_' } else { motion };
+        let n = count.unwrap_or(1);
+
+        let origin = self.editor_cursor_position();
+        // `cw` on a word changes just the word, as `ce` would.
+        let on_word = self.editor_vi_char(origin).is_some_and(|c| !c.is_whitespace());
+        let motion = match motion {
+            b'w' if operator == ViOperator::Change && on_word => b'e',
+            b'W' if operator == ViOperator::Change && on_word => b'E',
+            motion => motion,
+        };
+
+        // A word motion whose last word ends a line stops at the end of that word rather than
+        // at the start of the next line's first one.
+        let word = motion == b'w' || motion == b'W';
+        if word && n > 1 {
+            self.editor_vi_motion(motion, n - 1, true, pending)?;
+        }
+        let before = self.editor_cursor_position();
+        let steps = if word { 1 } else { n };
+
+        let extent = match self.editor_vi_motion(motion, steps, count.is_some(), pending)? {
+            Some(extent) => extent,
+            None => {
+                self.editor_move_to(origin);
+                return Ok(());
+            }
+        };
+        if word && self.cy > before.cy {
+            let len = self.buffer.rows[before.cy].chars.chars().count();
+            self.editor_move_to(Position { cx: len, cy: before.cy });
+        }
+
+        self.editor_vi_apply(operator, origin, extent, register);
+        Ok(())
+    }
+
+    /// Applies `operator` to the text between `origin` and the cursor.
+    fn editor_vi_apply(
+        &mut self,
+        operator: ViOperator,
+        origin: Position,
+        extent: ViExtent,
+        register: Option<char>,
+    ) {
+        if self.buffer.rows.is_empty() {
+            if operator == ViOperator::Change {
+                self.vi_mode = Some(ViMode::Insert);
+            }
+            return;
+        }
+        let last = self.buffer.rows.len() - 1;
+        let target = self.editor_cursor_position();
+        let len = |editor: &Kilo, cy: usize| editor.buffer.rows[cy].chars.chars().count();
+
+        if extent == ViExtent::Linewise {
+            let top = cmp::min(origin.cy, target.cy).min(last);
+            let bottom = cmp::max(origin.cy, target.cy).min(last);
+            let mut text = self.buffer.rows[top..=bottom]
+                .iter()
+                .map(|row| row.chars.as_str())
+                .collect::<Vec<&str>>()
+                .join("\n");
+            text.push('\n');
+            self.editor_kill(text, false, register);
+
+            match operator {
+                ViOperator::Yank => {
+                    let cx = if origin.cy == top { origin.cx } else { target.cx };
+                    self.editor_move_to(Position { cx, cy: top });
+                }
+                ViOperator::Delete => {
+                    if bottom < last {
+                        let end = Position { cx: 0, cy: bottom + 1 };
+                        self.editor_delete_range(Position { cx: 0, cy: top }, end);
+                    } else if top > 0 {
+                        let start = Position { cx: len(self, top - 1), cy: top - 1 };
+                        let end = Position { cx: len(self, bottom), cy: bottom };
+                        self.editor_delete_range(start, end);
+                    } else {
+                        let end = Position { cx: len(self, bottom), cy: bottom };
+                        self.editor_delete_range(Position { cx: 0, cy: 0 }, end);
+                    }
+                    self.editor_move_to(Position { cx: 0, cy: top });
+                    self.editor_vi_motion_to_first_non_blank();
+                }
+                ViOperator::Change => {
+                    let row = &self.buffer.rows[top].chars;
+                    let indent = row.chars().take_while(|c| c.is_whitespace()).count();
+                    let end = Position { cx: len(self, bottom), cy: bottom };
+                    self.editor_delete_range(Position { cx: indent, cy: top }, end);
+                    self.vi_mode = Some(ViMode::Insert);
+                }
+            }
+            return;
+        }
+
+        let (start, mut end) = match (origin.cy, origin.cx) <= (target.cy, target.cx) {
+            true => (origin, target),
+            false => (target, origin),
+        };
+        if end.cy > last {
+            end = Position { cx: len(self, last), cy: last };
+        }
+        if extent == ViExtent::Inclusive {
+            end.cx = cmp::min(end.cx + 1, len(self, end.cy));
+        }
+
+        let text = self.editor_text_range(start, end);
+        self.editor_kill(text, false, register);
+        match operator {
+            ViOperator::Yank => self.editor_move_to(start),
+            ViOperator::Delete => self.editor_delete_range(start, end),
+            ViOperator::Change => {
+                self.editor_delete_range(start, end);
+                self.vi_mode = Some(ViMode::Insert);
+            }
+        }
+    }
+
+    /// Moves the cursor as motion key `c` says, `count` times, returning how much of the text
+    /// moved over an operator would cover, or `None` if `c` isn't a motion.
+    fn editor_vi_motion(
+        &mut self,
+        c: u8,
+        count: usize,
+        has_count: bool,
+        pending: &mut String,
+    ) -> io::Result<Option<ViExtent>> {
+        let rows = self.buffer.rows.len();
+        let len = self.buffer.rows.get(self.cy).map_or(0, |row| row.chars.chars().count());
+
+        let extent = match c {
+            b'h' => {
+                self.cx = self.cx.saturating_sub(count);
+                ViExtent::Exclusive
+            }
+            b'l' | b' ' => {
+                self.cx = cmp::min(self.cx + count, len);
+                ViExtent::Exclusive
+            }
+            b'j' | b'k' | b'+' | b'-' | b'_' => {
+                let down = c == b'j' || c == b'+' || c == b'_';
+                let steps = if c == b'_' { count - 1 } else { count };
+                for _ in 0..steps {
+                    // Stop on the last row rather than the empty line after it.
+                    if down && self.cy + 1 >= rows {
+                        break;
+                    }
+                    self.editor_move_cursor(if down { ArrowDown } else { ArrowUp });
+                }
+                if c != b'j' && c != b'k' {
+                    self.editor_vi_motion_to_first_non_blank();
+                }
+                ViExtent::Linewise
+            }
+            b'0' => {
+                self.cx = 0;
+                ViExtent::Exclusive
+            }
+            b'^' => {
+                self.editor_vi_motion_to_first_non_blank();
+                ViExtent::Exclusive
+            }
+            b'$' => {
+                for _ in 1..count {
+                    if self.cy + 1 < rows {
+                        self.cy += 1;
+                    }
+                }
+                let len = self.buffer.rows.get(self.cy).map_or(0, |row| row.chars.chars().count());
+                self.cx = len.saturating_sub(1);
+                ViExtent::Inclusive
+            }
+            b'|' => {
+                self.cx = cmp::min(count - 1, len);
+                ViExtent::Exclusive
+            }
+            b'w' | b'W' | b'b' | b'B' | b'e' | b'E' => {
+                let big = c.is_ascii_uppercase();
+                for _ in 0..count {
+                    let pos = self.editor_cursor_position();
+                    let pos = match c.to_ascii_lowercase() {
+                        b'w' => self.editor_vi_word_start(pos, big),
+                        b'b' => self.editor_vi_word_back(pos, big),
+                        _ => self.editor_vi_word_end(pos, big),
+                    };
+                    self.editor_move_to(pos);
+                }
+                match c.to_ascii_lowercase() {
+                    b'e' => ViExtent::Inclusive,
+                    _ => ViExtent::Exclusive,
+                }
+            }
+            b'G' | b'g' => {
+                if c == b'g' && self.editor_vi_next(pending)? != Some(b'g') {
+                    return Ok(None);
+                }
+                self.editor_record_jump();
+                self.cy = match (has_count, c) {
+                    (true, _) => cmp::min(count, rows).saturating_sub(1),
+                    (false, b'G') => rows.saturating_sub(1),
+                    (false, _) => 0,
+                };
+                self.editor_vi_motion_to_first_non_blank();
+                ViExtent::Linewise
+            }
+            b'f' | b't' | b'F' | b'T' => {
+                let target = match self.editor_vi_next(pending)? {
+                    Some(target) => target as char,
+                    None => return Ok(None),
+                };
+                let chars = self.buffer.rows.get(self.cy).map_or(Vec::new(), |row| {
+                    row.chars.chars().collect::<Vec<char>>()
+                });
+                let forward = c == b'f' || c == b't';
+                let till = c == b't' || c == b'T';
+                // A till motion right before its character looks past it.
+                let from = match (forward, till) {
+                    (true, true) => self.cx + 1,
+                    (false, true) => self.cx.saturating_sub(1),
+                    _ => self.cx,
+                };
+                let mut found = None;
+                let mut at = from;
+                for _ in 0..count {
+                    let next = match forward {
+                        true => (at + 1..chars.len()).find(|&i| chars[i] == target),
+                        false => (0..at).rev().find(|&i| chars[i] == target),
+                    };
+                    match next {
+                        Some(i) => at = i,
+                        None => {
+                            found = None;
+                            break;
+                        }
+                    }
+                    found = Some(at);
+                }
+                match found {
+                    Some(i) if till && forward => self.cx = i - 1,
+                    Some(i) if till => self.cx = i + 1,
+                    Some(i) => self.cx = i,
+                    None => return Ok(None),
+                }
+                match forward {
+                    true => ViExtent::Inclusive,
+                    false => ViExtent::Exclusive,
+                }
+            }
+            b'}' | b'{' => {
+                for _ in 0..count {
+                    self.editor_next_paragraph(c == b'}');
+                }
+                ViExtent::Exclusive
+            }
+            b'\t' | b'\x1b' => return Ok(Some(ViExtent::Exclusive)),
+            _ => return Ok(None),
+        };
+
+        Ok(Some(extent))
+    }
+
+    fn editor_vi_motion_to_first_non_blank(&mut self) {
+        self.cx = match self.buffer.rows.get(self.cy) {
+            Some(row) => row.chars.chars().take_while(|c| c.is_whitespace()).count(),
+            None => 0,
+        };
+    }
+
+    /// The character at `pos`, or `None` at the end of a row.
+    fn editor_vi_char(&self, pos: Position) -> Option<char> {
+        self.buffer.rows.get(pos.cy)?.chars.chars().nth(pos.cx)
+    }
+
+    /// The position after `pos`, counting the end of each row as one.
+    fn editor_vi_next_position(&self, pos: Position) -> Option<Position> {
+        let len = self.buffer.rows.get(pos.cy)?.chars.chars().count();
+        if pos.cx < len {
+            Some(Position { cx: pos.cx + 1, ..pos })
+        } else if pos.cy + 1 < self.buffer.rows.len() {
+            Some(Position { cx: 0, cy: pos.cy + 1 })
+        } else {
+            None
+        }
+    }
+
+    fn editor_vi_previous_position(&self, pos: Position) -> Option<Position> {
+        match (pos.cx, pos.cy) {
+            (0, 0) => None,
+            (0, cy) => {
+                let len = self.buffer.rows[cy - 1].chars.chars().count();
+                Some(Position { cx: len, cy: cy - 1 })
+            }
+            (cx, _) => Some(Position { cx: cx - 1, ..pos }),
+        }
+    }
+
+    /// Whether `pos` is on an empty row, which `w` and `b` stop at like a word.
+    fn editor_vi_empty_row(&self, pos: Position) -> bool {
+        pos.cx == 0 && self.buffer.rows.get(pos.cy).is_some_and(|row| row.chars.is_empty())
+    }
+
+    fn editor_vi_class(&self, pos: Position, big: bool) -> u8 {
+        self.editor_vi_char(pos).map_or(0, |c| vi_class(c, big))
+    }
+
+    fn editor_vi_word_start(&self, pos: Position, big: bool) -> Position {
+        let class = self.editor_vi_class(pos, big);
+        let mut pos = pos;
+        let mut left = class == 0;
+
+        while let Some(next) = self.editor_vi_next_position(pos) {
+            left |= next.cy != pos.cy;
+            pos = next;
+            if self.editor_vi_empty_row(pos) {
+                return pos;
+            }
+            let here = self.editor_vi_class(pos, big);
+            left |= here != class;
+            if left && here != 0 {
+                return pos;
+            }
+        }
+
+        pos
+    }
+
+    fn editor_vi_word_end(&self, pos: Position, big: bool) -> Position {
+        let mut pos = match self.editor_vi_next_position(pos) {
+            Some(next) => next,
+            None => return pos,
+        };
+        while self.editor_vi_class(pos, big) == 0 {
+            match self.editor_vi_next_position(pos) {
+                Some(next) => pos = next,
+                None => return pos,
+            }
+        }
+
+        let class = self.editor_vi_class(pos, big);
+        while let Some(next) = self.editor_vi_next_position(pos) {
+            if next.cy != pos.cy || self.editor_vi_class(next, big) != class {
+                break;
+            }
+            pos = next;
+        }
+
+        pos
+    }
+
+    fn editor_vi_word_back(&self, pos: Position, big: bool) -> Position {
+        let mut pos = match self.editor_vi_previous_position(pos) {
+            Some(previous) => previous,
+            None => return pos,
+        };
+        while self.editor_vi_class(pos, big) == 0 && !self.editor_vi_empty_row(pos) {
+            match self.editor_vi_previous_position(pos) {
+                Some(previous) => pos = previous,
+                None => return pos,
+            }
+        }
+
+        let class = self.editor_vi_class(pos, big);
+        while let Some(previous) = self.editor_vi_previous_position(pos) {
+            if class == 0 || previous.cy != pos.cy || self.editor_vi_class(previous, big) != class
+            {
+                break;
+            }
+            pos = previous;
+        }
+
+        pos
+    }
+
+    /// Puts the last kill, or `register`, after the cursor (or before it); whole lines
+    /// go below the cursor's row (or above it).
+    fn editor_vi_put(&mut self, register: Option<char>, after: bool) {
+        let text = match register {
+            Some(name) => self.clipboard.registers.get(&name.to_ascii_lowercase()).cloned(),
+            None => self.clipboard.kill_ring.last().cloned(),
+        };
+        let text = match text {
+            Some(text) if !text.is_empty() => text,
+            _ => {
+                self.editor_set_status_message("Nothing to put");
+                return;
+            }
+        };
+
+        if !text.ends_with('\n') {
+            let len = self.buffer.rows.get(self.cy).map_or(0, |row| row.chars.chars().count());
+            if after && len > 0 {
+                self.cx = cmp::min(self.cx + 1, len);
+            }
+            self.editor_insert_str(&text);
+            self.cx = self.cx.saturating_sub(1);
+            return;
+        }
+
+        let cy = self.cy;
+        if !after || cy + 1 < self.buffer.rows.len() {
+            let at = if after { cy + 1 } else { cy };
+            self.editor_move_to(Position { cx: 0, cy: at });
+            self.editor_insert_str(&text);
+            self.editor_move_to(Position { cx: 0, cy: at });
+        } else {
+            self.editor_end();
+            self.editor_insert_str(&format!("\n{}", &text[..(text.len() - 1)]));
+            self.editor_move_to(Position { cx: 0, cy: cy + 1 });
+        }
+        self.editor_vi_motion_to_first_non_blank();
+    }
+
+    /// Joins the next row onto the cursor's, with a space instead of its indentation.
+    fn editor_vi_join(&mut self) {
+        if self.cy + 1 >= self.buffer.rows.len() {
+            return;
+        }
+
+        let cy = self.cy;
+        let len = self.buffer.rows[cy].chars.chars().count();
+        let next = &self.buffer.rows[cy + 1].chars;
+        let indent = next.chars().take_while(|c| c.is_whitespace()).count();
+        let space = len > 0 && indent < next.chars().count();
+
+        self.editor_delete_range(Position { cx: len, cy }, Position { cx: indent, cy: cy + 1 });
+        if space {
+            self.editor_insert_str(" ");
+        }
+        self.editor_move_to(Position { cx: len, cy });
+    }
+
+    /// Replaces `count` characters from the cursor with `with`.
+    fn editor_vi_replace(&mut self, with: char, count: usize) {
+        let len = self.buffer.rows.get(self.cy).map_or(0, |row| row.chars.chars().count());
+        if self.cx + count > len {
+            return;
+        }
+
+        let start = self.editor_cursor_position();
+        self.editor_delete_range(start, Position { cx: start.cx + count, ..start });
+        self.editor_insert_str(&iter::repeat_n(with, count).collect::<String>());
+        self.cx -= 1;
+    }
+
+    /// Switches the case of `count` characters from the cursor, moving past them.
+    fn editor_vi_toggle_case(&mut self, count: usize) {
+        let len = self.buffer.rows.get(self.cy).map_or(0, |row| row.chars.chars().count());
+        let start = self.editor_cursor_position();
+        let end = Position { cx: cmp::min(start.cx + count, len), ..start };
+        if start.cx >= end.cx {
+            return;
+        }
+
+        let toggled = self
+            .editor_text_range(start, end)
+            .chars()
+            .flat_map(|c| match c.is_uppercase() {
+                true => c.to_lowercase().collect::<Vec<char>>(),
+                false => c.to_uppercase().collect::<Vec<char>>(),
+            })
+            .collect::<String>();
+        self.editor_delete_range(start, end);
+        self.editor_insert_str(&toggled);
+    }
+}